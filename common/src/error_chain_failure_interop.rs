@@ -1,18 +0,0 @@
-use failure::SyncFailure;
-
-pub trait ResultExt<T, E> {
-    fn sync(self) -> Result<T, SyncFailure<E>>
-    where
-        Self: Sized,
-        E: ::std::error::Error + Send + 'static;
-}
-
-impl<T, E> ResultExt<T, E> for Result<T, E> {
-    fn sync(self) -> Result<T, SyncFailure<E>>
-    where
-        Self: Sized,
-        E: ::std::error::Error + Send + 'static,
-    {
-        self.map_err(SyncFailure::new)
-    }
-}
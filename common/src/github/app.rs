@@ -0,0 +1,258 @@
+use base64;
+use chrono::{DateTime, Duration, Utc};
+use failure::Error;
+use json::{self, Value};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::sign::Signer;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, USER_AGENT};
+use reqwest::{Method, StatusCode};
+
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use github::transport::Transport;
+use github::RequestError;
+
+// GitHub refuses JWTs valid for more than 10 minutes
+const JWT_LIFETIME_MINUTES: i64 = 10;
+// Installation tokens are refreshed this long before they actually expire
+const REFRESH_MARGIN_MINUTES: i64 = 5;
+
+/// GitHub App identity, used to obtain access tokens for its installations
+pub struct GithubApp {
+    id: u64,
+    key: PKey<Private>,
+    transport: Transport,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    iat: i64,
+    exp: i64,
+    iss: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct InstallationToken {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl InstallationToken {
+    fn needs_refresh(&self, now: DateTime<Utc>) -> bool {
+        now + Duration::minutes(REFRESH_MARGIN_MINUTES) >= self.expires_at
+    }
+}
+
+impl GithubApp {
+    /// Create the App from PEM-encoded RSA private key.
+    ///
+    /// Token exchanges go through `transport`, so they share the concurrency bound and
+    /// the cassette with the clients.
+    pub fn new(id: u64, private_key: &[u8], transport: Transport) -> Result<Self, Error> {
+        Ok(GithubApp {
+            id,
+            key: PKey::private_key_from_pem(private_key)?,
+            transport,
+        })
+    }
+
+    pub fn from_key_file(
+        id: u64,
+        path: impl AsRef<Path>,
+        transport: Transport,
+    ) -> Result<Self, Error> {
+        Self::new(id, &fs::read(path)?, transport)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// RS256-signed JWT authenticating the App itself
+    pub fn jwt(&self) -> Result<String, Error> {
+        let now = Utc::now();
+        let claims = Claims {
+            // Backdated to tolerate clock drift between us and GitHub
+            iat: (now - Duration::seconds(60)).timestamp(),
+            exp: (now + Duration::minutes(JWT_LIFETIME_MINUTES) - Duration::seconds(60))
+                .timestamp(),
+            iss: self.id,
+        };
+
+        let header = encode_segment(&json!({ "alg": "RS256", "typ": "JWT" }))?;
+        let claims = encode_segment(&claims)?;
+        let message = format!("{}.{}", header, claims);
+
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key)?;
+        signer.update(message.as_bytes())?;
+        let signature = base64::encode_config(&signer.sign_to_vec()?, base64::URL_SAFE_NO_PAD);
+
+        Ok(format!("{}.{}", message, signature))
+    }
+
+    /// Exchange the App JWT for an access token of the installation
+    pub fn installation_token(&self, installation_id: u64) -> Result<InstallationToken, Error> {
+        info!(
+            "requesting access token for installation {}",
+            installation_id
+        );
+
+        let endpoint = format!("app/installations/{}/access_tokens", installation_id);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {}", self.jwt()?))?,
+        );
+        headers.insert(USER_AGENT, HeaderValue::from_static("rustyrobot"));
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("application/vnd.github.machine-man-preview+json"),
+        );
+
+        let response = self
+            .transport
+            .send(Method::POST, &endpoint, headers, None)?;
+
+        if response.status != StatusCode::CREATED {
+            let body: Option<Value> = json::from_str(&response.body).ok();
+            raise!(RequestError::status(
+                response.status.as_u16(),
+                body.as_ref()
+            ))
+        }
+
+        let token: InstallationToken = json::from_str(&response.body)?;
+        debug!(
+            "installation {} token expires at {}",
+            installation_id, token.expires_at
+        );
+        Ok(token)
+    }
+}
+
+fn encode_segment<T: ::serde::Serialize>(value: &T) -> Result<String, Error> {
+    Ok(base64::encode_config(
+        &json::to_vec(value)?,
+        base64::URL_SAFE_NO_PAD,
+    ))
+}
+
+/// Installation of the App, keeps its access token fresh
+pub struct Installation {
+    app: Arc<GithubApp>,
+    id: u64,
+    token: Mutex<Option<InstallationToken>>,
+}
+
+impl Installation {
+    pub fn new(app: Arc<GithubApp>, id: u64) -> Self {
+        Installation {
+            app,
+            id,
+            token: Mutex::new(None),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Current access token, requests a new one if it's about to expire
+    pub fn token(&self) -> Result<String, Error> {
+        // Held for the whole refresh so concurrent requests don't exchange the JWT twice
+        let mut token = self.token.lock().unwrap();
+
+        let fresh = match *token {
+            Some(ref token) => !token.needs_refresh(Utc::now()),
+            None => false,
+        };
+
+        if !fresh {
+            *token = Some(self.app.installation_token(self.id)?);
+        }
+
+        Ok(token.as_ref().unwrap().token.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::FakeGithub;
+    use github::tokens::TokenPool;
+    use github::v3::{ExecutorExt, Github, StatusCode};
+    use openssl::rsa::Rsa;
+    use openssl::sign::Verifier;
+
+    fn app(api_url: &str) -> (GithubApp, PKey<Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let pem = key.private_key_to_pem_pkcs8().unwrap();
+        let transport = Transport::new(api_url).unwrap();
+        (GithubApp::new(1337, &pem, transport).unwrap(), key)
+    }
+
+    #[test]
+    fn jwt_is_signed() {
+        let (app, key) = app("http://localhost");
+        let jwt = app.jwt().unwrap();
+
+        let segments: Vec<&str> = jwt.split('.').collect();
+        assert_eq!(segments.len(), 3);
+
+        let decode = |segment| base64::decode_config(segment, base64::URL_SAFE_NO_PAD).unwrap();
+        let header: Value = json::from_slice(&decode(segments[0])).unwrap();
+        let claims: Claims = json::from_slice(&decode(segments[1])).unwrap();
+        assert_eq!(header["alg"], "RS256");
+        assert_eq!(claims.iss, 1337);
+        assert!(claims.exp - claims.iat <= JWT_LIFETIME_MINUTES * 60);
+
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &key).unwrap();
+        verifier
+            .update(format!("{}.{}", segments[0], segments[1]).as_bytes())
+            .unwrap();
+        assert!(verifier.verify(&decode(segments[2])).unwrap());
+    }
+
+    #[test]
+    fn installation_token_is_cached_until_expiry() {
+        let fake = FakeGithub::start().unwrap();
+        fake.state().installations.push(42);
+        let (app, _) = app(&fake.url());
+        let installation = Installation::new(Arc::new(app), 42);
+
+        let token = installation.token().unwrap();
+        assert_eq!(installation.token().unwrap(), token);
+
+        // Tokens expiring within the refresh margin are exchanged on every use
+        fake.state().installation_token_ttl = Duration::minutes(1);
+        *installation.token.lock().unwrap() = None;
+        let token = installation.token().unwrap();
+        assert_ne!(installation.token().unwrap(), token);
+    }
+
+    #[test]
+    fn requests_with_installation_token() {
+        let fake = FakeGithub::start().unwrap();
+        fake.state().installations.push(42);
+        fake.state().tokens = Some(vec![]);
+        let (app, _) = app(&fake.url());
+
+        let pool = TokenPool::new().bot(Installation::new(Arc::new(app), 42));
+        let gh = Github::with_pool(Arc::new(pool), &fake.url()).unwrap();
+
+        let user: Value = gh
+            .get()
+            .custom_endpoint("user")
+            .send(&[StatusCode::OK])
+            .unwrap();
+        assert_eq!(user["login"], "rustyrobot");
+
+        let requests = fake.requests();
+        assert_eq!(requests[0].path, "/app/installations/42/access_tokens");
+        assert_eq!(requests[1].header("authorization"), Some("token v1.42.1"));
+    }
+}
@@ -0,0 +1,372 @@
+use failure::Error;
+use json::{self, Value};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, StatusCode};
+
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use github::transport::RawResponse;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Requests go to GitHub, every exchange is appended to the cassette
+    Record,
+    /// Responses are served from the cassette, no network is involved
+    Replay,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Relative to the API url, so the cassette can be replayed against any host
+    pub endpoint: String,
+    pub body: Option<Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Tape {
+    /// API url the interactions were recorded against
+    api_url: Option<String>,
+    interactions: Vec<Interaction>,
+}
+
+struct Playback {
+    tape: Tape,
+    used: Vec<bool>,
+}
+
+/// Recorded request/response pairs of the GitHub clients.
+///
+/// Credentials are never written: only the method, endpoint and body of the requests are kept,
+/// and the installation tokens handed out by GitHub are redacted from the responses.
+/// Identical requests are replayed in the order they were recorded, so flows polling
+/// the same resource (e.g. PR status) replay faithfully.
+pub struct Cassette {
+    mode: CassetteMode,
+    path: PathBuf,
+    playback: Mutex<Playback>,
+}
+
+impl Cassette {
+    /// Start recording into `path`, overwriting it
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Cassette {
+            mode: CassetteMode::Record,
+            path: path.into(),
+            playback: Mutex::new(Playback {
+                tape: Tape::default(),
+                used: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+        let tape: Tape = json::from_reader(BufReader::new(File::open(&path)?))?;
+        info!(
+            "replaying {} GitHub interactions from {}",
+            tape.interactions.len(),
+            path.display()
+        );
+
+        Ok(Cassette {
+            mode: CassetteMode::Replay,
+            path,
+            playback: Mutex::new(Playback {
+                used: vec![false; tape.interactions.len()],
+                tape,
+            }),
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.playback.lock().unwrap().tape.interactions.clone()
+    }
+
+    pub(crate) fn save(
+        &self,
+        api_url: &str,
+        method: &Method,
+        url: &str,
+        body: Option<&Value>,
+        response: &RawResponse,
+    ) -> Result<(), Error> {
+        let interaction = Interaction {
+            request: RecordedRequest {
+                method: method.to_string(),
+                endpoint: relative(url, &[api_url]).to_string(),
+                body: body.cloned(),
+            },
+            response: RecordedResponse {
+                status: response.status.as_u16(),
+                headers: response
+                    .headers
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+                    })
+                    .collect(),
+                body: redact(url, &response.body),
+            },
+        };
+
+        let mut playback = self.playback.lock().unwrap();
+        playback.tape.api_url = Some(api_url.to_string());
+        playback.tape.interactions.push(interaction);
+
+        // Saved after every exchange, so the cassette is complete even if the flow fails midway
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        json::to_writer_pretty(File::create(&self.path)?, &playback.tape)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn lookup(
+        &self,
+        api_url: &str,
+        method: &Method,
+        url: &str,
+        body: Option<&Value>,
+    ) -> Result<RawResponse, Error> {
+        let mut playback = self.playback.lock().unwrap();
+
+        let endpoint = {
+            let recorded_url = playback.tape.api_url.as_ref().map(String::as_str);
+            let prefixes: Vec<&str> = Some(api_url).into_iter().chain(recorded_url).collect();
+            relative(url, &prefixes).to_string()
+        };
+        let method = method.to_string();
+
+        let position = {
+            let Playback { ref tape, ref used } = *playback;
+            tape.interactions
+                .iter()
+                .zip(used.iter())
+                .position(|(interaction, used)| {
+                    !used
+                        && interaction.request.method == method
+                        && interaction.request.endpoint == endpoint
+                        && interaction.request.body.as_ref() == body
+                })
+        };
+
+        let position = match position {
+            Some(position) => position,
+            None => raise!(CassetteError::Unrecorded { method, endpoint }),
+        };
+        playback.used[position] = true;
+
+        let response = &playback.tape.interactions[position].response;
+        let mut headers = HeaderMap::new();
+        for (name, value) in &response.headers {
+            headers.append(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        Ok(RawResponse {
+            status: StatusCode::from_u16(response.status)?,
+            headers,
+            body: response.body.clone(),
+        })
+    }
+}
+
+/// Installation access tokens are stripped from the recorded responses
+fn redact(url: &str, body: &str) -> String {
+    if !url.ends_with("/access_tokens") {
+        return body.to_string();
+    }
+
+    match json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            if value.get("token").is_some() {
+                value["token"] = Value::String("<redacted>".to_string());
+            }
+            value.to_string()
+        }
+        Err(_) => body.to_string(),
+    }
+}
+
+fn relative<'a>(url: &'a str, api_urls: &[&str]) -> &'a str {
+    api_urls
+        .iter()
+        .filter(|api_url| url.starts_with(*api_url))
+        .map(|api_url| url[api_url.len()..].trim_left_matches('/'))
+        .next()
+        .unwrap_or(url)
+}
+
+#[derive(Debug, Fail)]
+pub enum CassetteError {
+    #[fail(display = "no recorded interaction for {} {}", method, endpoint)]
+    Unrecorded { method: String, endpoint: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{FakeGithub, FakeRepo};
+    use github::app::{GithubApp, Installation};
+    use github::tokens::TokenPool;
+    use github::transport::Transport;
+    use github::v3::{ExecutorExt, Github};
+    use search::query::{Query, SearchFor};
+    use search::search;
+    use std::sync::Arc;
+    use tempfile;
+    use types::repo;
+
+    // Nothing listens there, replay must not touch the network
+    const UNREACHABLE: &str = "http://127.0.0.1:9";
+
+    fn v3(api_url: &str, cassette: Cassette) -> Github {
+        let transport = Transport::new(api_url)
+            .unwrap()
+            .with_cassette(Arc::new(cassette));
+        Github::with_transport(Arc::new(TokenPool::single("secret")), transport)
+    }
+
+    fn v4(api_url: &str, cassette: Cassette) -> ::github::v4::Github {
+        let transport = Transport::new(api_url)
+            .unwrap()
+            .with_cassette(Arc::new(cassette));
+        ::github::v4::Github::with_transport(Arc::new(TokenPool::single("secret")), transport)
+            .unwrap()
+    }
+
+    #[test]
+    fn replay_rest_flow() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fork.json");
+
+        let fork = |gh: &Github| -> Value {
+            gh.post(())
+                .custom_endpoint("repos/owner/repo/forks")
+                .send(&[StatusCode::ACCEPTED])
+                .unwrap()
+        };
+
+        let recorded = {
+            let fake = FakeGithub::start().unwrap();
+            fake.add_repo(FakeRepo::new("owner/repo"));
+            fork(&v3(&fake.url(), Cassette::record(&path)))
+        };
+
+        let tape = fs::read_to_string(&path).unwrap();
+        assert!(!tape.contains("secret"), "credentials must not be recorded");
+
+        let replayed = fork(&v3(UNREACHABLE, Cassette::replay(&path).unwrap()));
+        assert_eq!(replayed, recorded);
+
+        // Every interaction is replayed once
+        let gh = v3(UNREACHABLE, Cassette::replay(&path).unwrap());
+        fork(&gh);
+        let result: Result<Value, Error> = gh
+            .post(())
+            .custom_endpoint("repos/owner/repo/forks")
+            .send(&[StatusCode::ACCEPTED]);
+        assert!(result.unwrap_err().downcast::<CassetteError>().is_ok());
+    }
+
+    #[test]
+    fn replay_search_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("search.json");
+
+        let fetch = |gh: &::github::v4::Github| -> Vec<String> {
+            let mut names = Vec::new();
+            let mut after = None;
+            loop {
+                let mut query = Query::builder()
+                    .search_for(SearchFor::Repository)
+                    .raw_query("language:Rust")
+                    .count(2);
+                if let Some(after) = after.take() {
+                    query = query.after(after);
+                }
+                let page = search::<repo::v4::Repository>(gh, query.build().unwrap()).unwrap();
+                names.extend(page.nodes.into_iter().map(|repo| repo.name_with_owner));
+                if !page.page_info.has_next_page {
+                    break names;
+                }
+                after = page.page_info.end_cursor;
+            }
+        };
+
+        let recorded = {
+            let fake = FakeGithub::start().unwrap();
+            for i in 0..5 {
+                fake.add_repo(FakeRepo::new(format!("owner/repo{}", i)));
+            }
+            fetch(&v4(&fake.url(), Cassette::record(&path)))
+        };
+        assert_eq!(recorded.len(), 5);
+
+        let replayed = fetch(&v4(UNREACHABLE, Cassette::replay(&path).unwrap()));
+        assert_eq!(replayed, recorded);
+    }
+
+    #[test]
+    fn installation_tokens_are_redacted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.json");
+        let pem = ::openssl::rsa::Rsa::generate(2048)
+            .unwrap()
+            .private_key_to_pem()
+            .unwrap();
+
+        let get_user = |cassette: Cassette, api_url: &str| -> Value {
+            let transport = Transport::new(api_url)
+                .unwrap()
+                .with_cassette(Arc::new(cassette));
+            let app = GithubApp::new(1, &pem, transport.clone()).unwrap();
+            let pool = TokenPool::single(Installation::new(Arc::new(app), 42));
+            Github::with_transport(Arc::new(pool), transport)
+                .get()
+                .custom_endpoint("user")
+                .send(&[StatusCode::OK])
+                .unwrap()
+        };
+
+        let recorded = {
+            let fake = FakeGithub::start().unwrap();
+            fake.state().installations.push(42);
+            get_user(Cassette::record(&path), &fake.url())
+        };
+
+        let tape = fs::read_to_string(&path).unwrap();
+        assert!(tape.contains("app/installations/42/access_tokens"));
+        assert!(
+            !tape.contains("v1.42."),
+            "installation tokens must not be recorded"
+        );
+
+        let replayed = get_user(Cassette::replay(&path).unwrap(), UNREACHABLE);
+        assert_eq!(replayed, recorded);
+    }
+}
@@ -0,0 +1,196 @@
+pub use threadpool::ThreadPool;
+
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Default number of requests allowed in flight at once.
+/// GitHub discourages hammering the API with concurrent requests from a single client.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+/// Bounds the number of concurrent requests, shared between the v3 and v4 clients
+pub struct ConcurrencyLimit {
+    max: usize,
+    in_flight: Mutex<usize>,
+    released: Condvar,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max: usize) -> Self {
+        assert!(max > 0, "concurrency limit must be positive");
+        ConcurrencyLimit {
+            max,
+            in_flight: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    pub fn in_flight(&self) -> usize {
+        *self.in_flight.lock().unwrap()
+    }
+
+    /// Block until the request is allowed to proceed, the slot is freed when `Permit` is dropped
+    pub fn acquire(&self) -> Permit {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while *in_flight >= self.max {
+            in_flight = self.released.wait(in_flight).unwrap();
+        }
+        *in_flight += 1;
+        Permit { limit: self }
+    }
+}
+
+impl Default for ConcurrencyLimit {
+    fn default() -> Self {
+        ConcurrencyLimit::new(DEFAULT_MAX_CONCURRENCY)
+    }
+}
+
+pub struct Permit<'a> {
+    limit: &'a ConcurrencyLimit,
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        *self.limit.in_flight.lock().unwrap() -= 1;
+        self.limit.released.notify_one();
+    }
+}
+
+/// Apply `f` to every item on the pool, results are returned in the order of items
+pub fn parallel_map<T, R, F>(pool: &ThreadPool, items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + Sync + 'static,
+{
+    parallel_stream(pool, items, move |item, _| f(item), &mut |()| ())
+}
+
+enum Message<V, R> {
+    Value(V),
+    Done(usize, R),
+}
+
+/// Apply `f` to every item on the pool, the values `f` yields are passed to `consume`
+/// on the calling thread as soon as they arrive.
+///
+/// Results are returned in the order of items once all of them are done.
+pub fn parallel_stream<T, V, R, F>(
+    pool: &ThreadPool,
+    items: Vec<T>,
+    f: F,
+    consume: &mut dyn FnMut(V),
+) -> Vec<R>
+where
+    T: Send + 'static,
+    V: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T, &mut dyn FnMut(V)) -> R + Send + Sync + 'static,
+{
+    let count = items.len();
+    if count <= 1 {
+        return items
+            .into_iter()
+            .map(|item| f(item, &mut *consume))
+            .collect();
+    }
+
+    let f = Arc::new(f);
+    let (tx, rx) = mpsc::channel();
+
+    for (idx, item) in items.into_iter().enumerate() {
+        let f = f.clone();
+        let tx = tx.clone();
+        pool.execute(move || {
+            // Receiver outlives the jobs, send can't fail
+            let result = f(item, &mut |value| {
+                tx.send(Message::Value(value)).ok();
+            });
+            tx.send(Message::Done(idx, result)).ok();
+        });
+    }
+    drop(tx);
+
+    let mut results: Vec<(usize, R)> = Vec::with_capacity(count);
+    for message in rx {
+        match message {
+            Message::Value(value) => consume(value),
+            Message::Done(idx, result) => results.push((idx, result)),
+        }
+    }
+    if results.len() != count {
+        panic!("parallel worker has panicked");
+    }
+
+    results.sort_by_key(|(idx, _)| *idx);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use github::{v3, v4};
+    use std::thread;
+    use std::time::Duration;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn clients_are_thread_safe() {
+        assert_send_sync::<v3::Github>();
+        assert_send_sync::<v4::Github>();
+    }
+
+    #[test]
+    fn limit_bounds_parallel_jobs() {
+        let limit = Arc::new(ConcurrencyLimit::new(2));
+        let peak = Arc::new(Mutex::new(0));
+
+        let results = {
+            let limit = limit.clone();
+            let peak = peak.clone();
+            parallel_map(&ThreadPool::new(8), (0..8).collect(), move |i: u32| {
+                let _permit = limit.acquire();
+                {
+                    let mut peak = peak.lock().unwrap();
+                    *peak = (*peak).max(limit.in_flight());
+                }
+                thread::sleep(Duration::from_millis(20));
+                i * 2
+            })
+        };
+
+        assert_eq!(results, (0..8).map(|i| i * 2).collect::<Vec<_>>());
+        assert_eq!(*peak.lock().unwrap(), 2);
+        assert_eq!(limit.in_flight(), 0);
+    }
+
+    #[test]
+    fn values_are_streamed_to_the_caller() {
+        let pool = ThreadPool::new(4);
+        let mut values = Vec::new();
+
+        let results = parallel_stream(
+            &pool,
+            vec![1, 2, 3],
+            |n: u32, yield_value: &mut dyn FnMut(u32)| {
+                for i in 0..n {
+                    yield_value(n * 10 + i);
+                }
+                n
+            },
+            &mut |value| values.push(value),
+        );
+
+        assert_eq!(results, vec![1, 2, 3]);
+        values.sort();
+        assert_eq!(values, vec![10, 20, 21, 30, 31, 32]);
+
+        // The pool is reused across calls
+        assert_eq!(parallel_map(&pool, vec![1, 2], |n: u32| n + 1), vec![2, 3]);
+    }
+}
@@ -0,0 +1,297 @@
+use failure::Error;
+use github::utils::is_secondary_rate_limit_message;
+use json::{self, Value};
+use reqwest;
+
+use std::fmt;
+
+/// Coarse classification of failed requests, tells callers what to do about the failure
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Transient failure: rate limit, GitHub being unavailable, network issues
+    Retryable,
+    /// Resource doesn't exist or is not visible to us
+    NotFound,
+    /// Credentials are invalid, expired or revoked; nothing works until they are replaced
+    Unauthorized,
+    /// Credentials lack access to the resource
+    Permission,
+    /// GitHub refused the request itself, repeating it won't help
+    Validation,
+    Other,
+}
+
+impl ErrorKind {
+    fn from_status(status: u16, message: Option<&str>) -> Self {
+        match status {
+            404 | 410 => ErrorKind::NotFound,
+            401 => ErrorKind::Unauthorized,
+            403 if message.map_or(false, is_secondary_rate_limit_message) => ErrorKind::Retryable,
+            403 => ErrorKind::Permission,
+            400 | 422 => ErrorKind::Validation,
+            500..=599 => ErrorKind::Retryable,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    fn from_graphql_type(kind: &str) -> Self {
+        match kind {
+            "NOT_FOUND" => ErrorKind::NotFound,
+            "FORBIDDEN" | "INSUFFICIENT_SCOPES" => ErrorKind::Permission,
+            "UNPROCESSABLE" | "INVALID_CURSOR_ARGUMENTS" => ErrorKind::Validation,
+            "RATE_LIMITED" | "SERVICE_UNAVAILABLE" | "TIMEOUT" => ErrorKind::Retryable,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Classify any error returned by the GitHub clients
+    pub fn of(error: &Error) -> Self {
+        if let Some(error) = error.downcast_ref::<RequestError>() {
+            return error.kind();
+        }
+
+        if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+            if error.is_timeout() || error.is_http() || error.is_server_error() {
+                return ErrorKind::Retryable;
+            }
+            if let Some(status) = error.status() {
+                return ErrorKind::from_status(status.as_u16(), None);
+            }
+        }
+
+        ErrorKind::Other
+    }
+}
+
+/// Single entry of the GraphQL `errors` array
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GraphQLError {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    /// Path to the field that failed, mixes field names and list indices
+    #[serde(default)]
+    pub path: Vec<Value>,
+}
+
+impl fmt::Display for GraphQLError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref kind) = self.kind {
+            write!(f, "{}: ", kind)?;
+        }
+        write!(f, "{}", self.message)?;
+        if !self.path.is_empty() {
+            let path: Vec<String> = self
+                .path
+                .iter()
+                .map(|segment| match segment {
+                    Value::String(field) => field.clone(),
+                    other => other.to_string(),
+                })
+                .collect();
+            write!(f, " (at {})", path.join("."))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Fail, Debug)]
+pub enum RequestError {
+    ResponseStatusNotOk {
+        status: u16,
+        message: Option<String>,
+        documentation_url: Option<String>,
+    },
+    EmptyResponse,
+    InvalidJson {
+        expected: String,
+        got: String,
+    },
+    ExceededRateLimit {
+        retry_in: u64,
+    },
+    GraphQL {
+        errors: Vec<GraphQLError>,
+    },
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequestError::ResponseStatusNotOk {
+                status, message, ..
+            } => write!(
+                f,
+                "server returned status {}: {}",
+                status,
+                message
+                    .as_ref()
+                    .map(String::as_str)
+                    .unwrap_or("<no message>")
+            ),
+            RequestError::EmptyResponse => write!(f, "server returned empty json response"),
+            RequestError::InvalidJson { expected, got } => write!(
+                f,
+                "invalid json schema:\n\texpected {:?}\n\tgot {:?}",
+                expected, got
+            ),
+            RequestError::ExceededRateLimit { retry_in } => {
+                write!(f, "exceeded rate limit: retry in {} seconds", retry_in)
+            }
+            RequestError::GraphQL { errors } => {
+                write!(f, "GraphQL query failed: ")?;
+                for (idx, error) in errors.iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl RequestError {
+    /// Unexpected status error with the message GitHub put in the response body
+    pub fn status(status: u16, body: Option<&Value>) -> Self {
+        let field = |name| {
+            body.and_then(|body| body.get(name))
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned)
+        };
+
+        RequestError::ResponseStatusNotOk {
+            status,
+            message: field("message"),
+            documentation_url: field("documentation_url"),
+        }
+    }
+
+    /// GraphQL errors from the response body, `None` if there are none
+    pub fn graphql(body: &Value) -> Option<Self> {
+        let errors: Vec<GraphQLError> = body
+            .get("errors")
+            .and_then(|errors| json::from_value(errors.clone()).ok())
+            .unwrap_or_default();
+
+        if errors.is_empty() {
+            None
+        } else {
+            Some(RequestError::GraphQL { errors })
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            RequestError::ResponseStatusNotOk {
+                status, message, ..
+            } => ErrorKind::from_status(*status, message.as_ref().map(String::as_str)),
+            RequestError::ExceededRateLimit { .. } => ErrorKind::Retryable,
+            RequestError::GraphQL { errors } => errors
+                .iter()
+                .filter_map(|error| error.kind.as_ref())
+                .map(|kind| ErrorKind::from_graphql_type(kind))
+                .find(|kind| *kind != ErrorKind::Other)
+                .unwrap_or(ErrorKind::Other),
+            RequestError::EmptyResponse | RequestError::InvalidJson { .. } => ErrorKind::Other,
+        }
+    }
+
+    pub fn http_status(&self) -> Option<u16> {
+        match self {
+            RequestError::ResponseStatusNotOk { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            RequestError::ResponseStatusNotOk { message, .. } => {
+                message.as_ref().map(String::as_str)
+            }
+            RequestError::GraphQL { errors } => errors.first().map(|error| error.message.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn documentation_url(&self) -> Option<&str> {
+        match self {
+            RequestError::ResponseStatusNotOk {
+                documentation_url, ..
+            } => documentation_url.as_ref().map(String::as_str),
+            _ => None,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.kind() == ErrorKind::Retryable
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.kind() == ErrorKind::NotFound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_errors_keep_github_message() {
+        let body = json!({
+            "message": "Validation Failed",
+            "documentation_url": "https://developer.github.com/v3/pulls/#create-a-pull-request",
+        });
+        let error = RequestError::status(422, Some(&body));
+
+        assert_eq!(error.kind(), ErrorKind::Validation);
+        assert_eq!(error.http_status(), Some(422));
+        assert_eq!(error.message(), Some("Validation Failed"));
+        assert_eq!(
+            error.to_string(),
+            "server returned status 422: Validation Failed"
+        );
+
+        assert_eq!(RequestError::status(404, None).kind(), ErrorKind::NotFound);
+        assert_eq!(
+            RequestError::status(403, None).kind(),
+            ErrorKind::Permission
+        );
+        assert_eq!(
+            RequestError::status(401, None).kind(),
+            ErrorKind::Unauthorized
+        );
+        assert_eq!(RequestError::status(502, None).kind(), ErrorKind::Retryable);
+
+        let body = json!({
+            "message": "You have exceeded a secondary rate limit. Please wait a few minutes before you try again.",
+        });
+        assert_eq!(
+            RequestError::status(403, Some(&body)).kind(),
+            ErrorKind::Retryable
+        );
+    }
+
+    #[test]
+    fn graphql_errors() {
+        assert!(RequestError::graphql(&json!({ "data": {} })).is_none());
+
+        let body = json!({
+            "data": null,
+            "errors": [{
+                "type": "NOT_FOUND",
+                "path": ["repository", "pullRequests", 0],
+                "message": "Could not resolve to a Repository with the name 'nope'.",
+            }],
+        });
+        let error = RequestError::graphql(&body).unwrap();
+
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert_eq!(
+            error.to_string(),
+            "GraphQL query failed: NOT_FOUND: Could not resolve to a Repository with the name 'nope'. (at repository.pullRequests.0)"
+        );
+        assert_eq!(ErrorKind::of(&error.into()), ErrorKind::NotFound);
+    }
+}
@@ -1,7 +1,15 @@
+pub mod app;
+pub mod cassette;
+pub mod concurrency;
+mod error;
+pub mod tokens;
+pub mod transport;
 pub mod utils;
 pub mod v3;
 pub mod v4;
 
+pub use self::error::{ErrorKind, GraphQLError, RequestError};
+
 use failure::Error;
 use serde::de::DeserializeOwned;
 
@@ -11,18 +19,3 @@ pub trait GithubClient {
     where
         T: DeserializeOwned;
 }
-
-#[derive(Fail, Debug)]
-pub enum RequestError {
-    #[fail(display = "server returned status {}, expected 200", status)]
-    ResponseStatusNotOk { status: u16 },
-    #[fail(display = "server returned empty json response")]
-    EmptyResponse,
-    #[fail(
-        display = "invalid json schema:\n\texpected {:?}\n\tgot {:?}",
-        expected, got
-    )]
-    InvalidJson { expected: String, got: String },
-    #[fail(display = "exceeded rate limit: retry in {} seconds", retry_in)]
-    ExceededRateLimit { retry_in: u64 },
-}
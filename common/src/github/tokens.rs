@@ -0,0 +1,403 @@
+use chrono::{DateTime, TimeZone, Utc};
+use failure::Error;
+use openssl::sha::sha256;
+use reqwest::header::HeaderMap;
+
+use std::sync::RwLock;
+
+use github::app::Installation;
+use github::RequestError;
+
+// Remaining requests threshold. Under this value the token is considered exhausted until limits reset
+const LIMIT_THRESHOLD: u64 = 5;
+
+/// Identity a request has to be performed with
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Affinity {
+    /// Any token with enough budget left
+    Any,
+    /// Only the bot account token: forks, PRs and everything else visible on behalf of the bot
+    Bot,
+}
+
+/// REST and GraphQL APIs have separate budgets
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Api {
+    Rest,
+    GraphQL,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_at: DateTime<Utc>,
+}
+
+impl RateLimit {
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| -> Option<i64> { headers.get(name)?.to_str().ok()?.parse().ok() };
+
+        Some(RateLimit {
+            limit: header("X-RateLimit-Limit")? as u64,
+            remaining: header("X-RateLimit-Remaining")? as u64,
+            reset_at: Utc.timestamp(header("X-RateLimit-Reset")?, 0),
+        })
+    }
+
+    fn is_exhausted(&self, now: DateTime<Utc>) -> bool {
+        self.remaining < LIMIT_THRESHOLD && now < self.reset_at
+    }
+
+    /// Budget left at the moment `now`, full limit if the window has already reset
+    fn budget(&self, now: DateTime<Utc>) -> u64 {
+        if now >= self.reset_at {
+            self.limit
+        } else {
+            self.remaining
+        }
+    }
+}
+
+/// Where the secret of the pooled token comes from
+pub enum Credentials {
+    /// Personal access token
+    Token(String),
+    /// GitHub App installation, its token is refreshed before expiry
+    Installation(Installation),
+}
+
+impl Credentials {
+    fn secret(&self) -> Result<String, Error> {
+        match self {
+            Credentials::Token(token) => Ok(token.clone()),
+            Credentials::Installation(installation) => installation.token(),
+        }
+    }
+}
+
+impl From<String> for Credentials {
+    fn from(token: String) -> Self {
+        Credentials::Token(token)
+    }
+}
+
+impl<'a> From<&'a str> for Credentials {
+    fn from(token: &'a str) -> Self {
+        Credentials::Token(token.to_string())
+    }
+}
+
+impl From<Installation> for Credentials {
+    fn from(installation: Installation) -> Self {
+        Credentials::Installation(installation)
+    }
+}
+
+struct PooledToken {
+    credentials: Credentials,
+    bot: bool,
+    login: RwLock<Option<String>>,
+    rest: RwLock<Option<RateLimit>>,
+    graphql: RwLock<Option<RateLimit>>,
+}
+
+impl PooledToken {
+    fn limit(&self, api: Api) -> &RwLock<Option<RateLimit>> {
+        match api {
+            Api::Rest => &self.rest,
+            Api::GraphQL => &self.graphql,
+        }
+    }
+}
+
+/// Token picked for a single request
+#[derive(Clone, Debug)]
+pub struct Lease {
+    pub index: usize,
+    pub secret: String,
+}
+
+/// Set of tokens GitHub requests are spread across.
+///
+/// Every token has its own REST and GraphQL budgets, the one with the most requests left is
+/// picked for each request. Only the tokens added with `bot` are eligible for `Affinity::Bot`,
+/// there may be several of them as long as they all act as the same bot account.
+pub struct TokenPool {
+    tokens: Vec<PooledToken>,
+}
+
+impl TokenPool {
+    pub fn new() -> Self {
+        TokenPool { tokens: Vec::new() }
+    }
+
+    /// Pool consisting of a single bot token
+    pub fn single(token: impl Into<Credentials>) -> Self {
+        TokenPool::new().bot(token)
+    }
+
+    /// Add the token of the bot account
+    pub fn bot(self, token: impl Into<Credentials>) -> Self {
+        self.push(token.into(), true)
+    }
+
+    /// Add a token used only for requests that don't care about identity
+    pub fn token(self, token: impl Into<Credentials>) -> Self {
+        self.push(token.into(), false)
+    }
+
+    fn push(mut self, credentials: Credentials, bot: bool) -> Self {
+        self.tokens.push(PooledToken {
+            credentials,
+            bot,
+            login: RwLock::new(None),
+            rest: RwLock::new(None),
+            graphql: RwLock::new(None),
+        });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Secret to authenticate with, installation tokens are refreshed if needed
+    pub fn secret(&self, index: usize) -> Result<String, Error> {
+        self.tokens[index].credentials.secret()
+    }
+
+    pub fn is_installation(&self, index: usize) -> bool {
+        match self.tokens[index].credentials {
+            Credentials::Installation(_) => true,
+            Credentials::Token(_) => false,
+        }
+    }
+
+    /// Name of the account the token acts as, stable across restarts and token refreshes
+    pub fn identity(&self, index: usize) -> String {
+        match self.tokens[index].credentials {
+            Credentials::Token(ref token) => {
+                let digest: String = sha256(token.as_bytes())[..8]
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+                format!("token:{}", digest)
+            }
+            Credentials::Installation(ref installation) => {
+                format!("installation:{}", installation.id())
+            }
+        }
+    }
+
+    pub fn login(&self, index: usize) -> Option<String> {
+        self.tokens[index].login.read().unwrap().clone()
+    }
+
+    pub fn set_login(&self, index: usize, login: impl Into<String>) {
+        *self.tokens[index].login.write().unwrap() = Some(login.into());
+    }
+
+    pub fn rate_limit(&self, index: usize, api: Api) -> Option<RateLimit> {
+        *self.tokens[index].limit(api).read().unwrap()
+    }
+
+    /// Pick the eligible token with the most requests left
+    pub fn select(&self, api: Api, affinity: Affinity) -> Result<Lease, Error> {
+        let now = Utc::now();
+
+        let eligible = self
+            .tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, token)| affinity == Affinity::Any || token.bot)
+            .map(|(index, token)| (index, *token.limit(api).read().unwrap()));
+
+        let mut best: Option<(usize, u64)> = None;
+        let mut earliest_reset: Option<DateTime<Utc>> = None;
+
+        for (index, limit) in eligible {
+            let budget = match limit {
+                Some(ref limit) if limit.is_exhausted(now) => {
+                    earliest_reset = Some(match earliest_reset {
+                        Some(reset) if reset < limit.reset_at => reset,
+                        _ => limit.reset_at,
+                    });
+                    continue;
+                }
+                Some(ref limit) => limit.budget(now),
+                // Token is yet to be used, assume it has the full budget
+                None => u64::max_value(),
+            };
+
+            if best.map(|(_, best)| budget > best).unwrap_or(true) {
+                best = Some((index, budget));
+            }
+        }
+
+        match (best, earliest_reset) {
+            (Some((index, _)), _) => {
+                trace!(
+                    "selected token #{} for {:?} {:?} request",
+                    index,
+                    affinity,
+                    api
+                );
+                Ok(Lease {
+                    index,
+                    secret: self.secret(index)?,
+                })
+            }
+            (None, Some(reset_at)) => {
+                let retry_in = (reset_at - now).num_seconds().max(0) as u64;
+                Err(RequestError::ExceededRateLimit { retry_in }.into())
+            }
+            (None, None) => Err(TokenPoolError::NoEligibleToken { affinity }.into()),
+        }
+    }
+
+    /// Record the limits reported by GitHub for the token
+    pub fn update(&self, index: usize, api: Api, limit: RateLimit) {
+        debug!("API {:?} limits of token #{}: {:?}", api, index, limit);
+        *self.tokens[index].limit(api).write().unwrap() = Some(limit);
+    }
+
+    /// Mark token as exhausted after GitHub refused the request because of the rate limit
+    pub fn exhausted(&self, index: usize, api: Api) {
+        warn!("token #{} exceeded {:?} API rate limit", index, api);
+        let mut limit = self.tokens[index].limit(api).write().unwrap();
+        let new_limit = match *limit {
+            Some(limit) if limit.reset_at > Utc::now() => RateLimit {
+                remaining: 0,
+                ..limit
+            },
+            // Don't know when it resets, GitHub windows are an hour long
+            Some(limit) => RateLimit {
+                remaining: 0,
+                reset_at: Utc::now() + ::chrono::Duration::hours(1),
+                ..limit
+            },
+            None => RateLimit {
+                limit: 0,
+                remaining: 0,
+                reset_at: Utc::now() + ::chrono::Duration::hours(1),
+            },
+        };
+        *limit = Some(new_limit);
+    }
+}
+
+impl Default for TokenPool {
+    fn default() -> Self {
+        TokenPool::new()
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum TokenPoolError {
+    #[fail(display = "no token in pool is eligible for {:?} requests", affinity)]
+    NoEligibleToken { affinity: Affinity },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn limit(remaining: u64) -> RateLimit {
+        RateLimit {
+            limit: 5000,
+            remaining,
+            reset_at: Utc::now() + Duration::minutes(30),
+        }
+    }
+
+    #[test]
+    fn selects_token_with_most_budget() {
+        let pool = TokenPool::new().bot("bot").token("a").token("b");
+        pool.update(0, Api::Rest, limit(100));
+        pool.update(1, Api::Rest, limit(4000));
+        pool.update(2, Api::Rest, limit(300));
+
+        let lease = pool.select(Api::Rest, Affinity::Any).unwrap();
+        assert_eq!(lease.secret, "a");
+    }
+
+    #[test]
+    fn budgets_are_per_api() {
+        let pool = TokenPool::new().bot("bot").token("a");
+        pool.update(0, Api::GraphQL, limit(4000));
+        pool.update(1, Api::GraphQL, limit(100));
+        pool.update(0, Api::Rest, limit(100));
+        pool.update(1, Api::Rest, limit(4000));
+
+        assert_eq!(
+            pool.select(Api::GraphQL, Affinity::Any).unwrap().secret,
+            "bot"
+        );
+        assert_eq!(pool.select(Api::Rest, Affinity::Any).unwrap().secret, "a");
+    }
+
+    #[test]
+    fn bot_affinity_ignores_other_tokens() {
+        let pool = TokenPool::new().token("a").bot("bot");
+        pool.update(0, Api::Rest, limit(4000));
+        pool.update(1, Api::Rest, limit(10));
+
+        assert_eq!(pool.select(Api::Rest, Affinity::Bot).unwrap().secret, "bot");
+    }
+
+    #[test]
+    fn fails_over_exhausted_tokens() {
+        let pool = TokenPool::new().bot("bot").token("a");
+        pool.update(0, Api::Rest, limit(4000));
+        pool.update(1, Api::Rest, limit(3000));
+        pool.exhausted(0, Api::Rest);
+
+        assert_eq!(pool.select(Api::Rest, Affinity::Any).unwrap().secret, "a");
+
+        match pool
+            .select(Api::Rest, Affinity::Bot)
+            .unwrap_err()
+            .downcast::<RequestError>()
+        {
+            Ok(RequestError::ExceededRateLimit { retry_in }) => assert!(retry_in <= 30 * 60),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reset_window_restores_budget() {
+        let pool = TokenPool::new().bot("bot").token("a");
+        pool.update(
+            0,
+            Api::Rest,
+            RateLimit {
+                limit: 5000,
+                remaining: 0,
+                reset_at: Utc::now() - Duration::seconds(1),
+            },
+        );
+        pool.update(1, Api::Rest, limit(3000));
+
+        assert_eq!(pool.select(Api::Rest, Affinity::Any).unwrap().secret, "bot");
+    }
+
+    #[test]
+    fn identities_are_distinct() {
+        let pool = TokenPool::new().bot("bot").token("a");
+        assert_ne!(pool.identity(0), pool.identity(1));
+        assert_eq!(pool.identity(0), TokenPool::single("bot").identity(0));
+        assert!(!pool.identity(0).contains("bot"));
+    }
+
+    #[test]
+    fn empty_pool() {
+        assert!(TokenPool::new().select(Api::Rest, Affinity::Any).is_err());
+    }
+}
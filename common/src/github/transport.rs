@@ -0,0 +1,102 @@
+use failure::Error;
+use json::Value;
+use reqwest::header::HeaderMap;
+use reqwest::{self, Method, StatusCode};
+
+use std::sync::Arc;
+
+use github::cassette::{Cassette, CassetteMode};
+use github::concurrency::ConcurrencyLimit;
+
+/// HTTP layer shared by the v3 and v4 clients.
+///
+/// Owns the connection pool and the concurrency bound, and records or replays
+/// the exchanges if a cassette is attached.
+#[derive(Clone)]
+pub struct Transport {
+    http: reqwest::Client,
+    api_url: String,
+    limit: Arc<ConcurrencyLimit>,
+    cassette: Option<Arc<Cassette>>,
+}
+
+/// Response as it came from the wire
+#[derive(Clone, Debug)]
+pub struct RawResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl Transport {
+    pub fn new(api_url: &str) -> Result<Self, Error> {
+        Ok(Transport {
+            http: reqwest::Client::builder().build()?,
+            api_url: api_url.trim_right_matches('/').to_string(),
+            limit: Arc::new(ConcurrencyLimit::default()),
+            cassette: None,
+        })
+    }
+
+    /// Share the bound on requests in flight with other transports
+    pub fn with_concurrency_limit(mut self, limit: Arc<ConcurrencyLimit>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Record exchanges into the cassette or serve them from it, depending on its mode
+    pub fn with_cassette(mut self, cassette: Arc<Cassette>) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    /// Endpoints may be relative to the API url or absolute, e.g. from the `Link` header
+    pub fn send(
+        &self,
+        method: Method,
+        endpoint: &str,
+        headers: HeaderMap,
+        body: Option<&Value>,
+    ) -> Result<RawResponse, Error> {
+        let url = if endpoint.starts_with("http://") || endpoint.starts_with("https://") {
+            endpoint.to_string()
+        } else {
+            format!("{}/{}", self.api_url, endpoint.trim_left_matches('/'))
+        };
+        debug!("{} {}", method, url);
+
+        if let Some(ref cassette) = self.cassette {
+            if cassette.mode() == CassetteMode::Replay {
+                return cassette.lookup(&self.api_url, &method, &url, body);
+            }
+        }
+
+        let mut builder = self
+            .http
+            .request(method.clone(), url.as_str())
+            .headers(headers);
+        if let Some(body) = body {
+            builder = builder.json(body);
+        }
+
+        let response = {
+            let _permit = self.limit.acquire();
+            let mut response = builder.send()?;
+            RawResponse {
+                body: response.text()?,
+                status: response.status(),
+                headers: response.headers().clone(),
+            }
+        };
+
+        if let Some(ref cassette) = self.cassette {
+            cassette.save(&self.api_url, &method, &url, body, &response)?;
+        }
+
+        Ok(response)
+    }
+}
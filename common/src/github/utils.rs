@@ -1,30 +1,43 @@
-use gh3;
-use gh4;
+use reqwest::StatusCode;
 
 use json::Value;
 
-pub fn is_rate_limit_error_v4(status: gh4::StatusCode, body: &Value) -> bool {
-    match status {
-        gh4::StatusCode::Forbidden => (),
-        _ => return false,
-    }
-
-    is_body_rate_limit_error(body)
-}
+use github::app::{GithubApp, Installation};
+use github::cassette::Cassette;
+use github::concurrency::{ConcurrencyLimit, DEFAULT_MAX_CONCURRENCY};
+use github::tokens::TokenPool;
+use github::transport::Transport;
+use github::v3::ResponseCache;
 
-pub fn is_rate_limit_error_v3(status: gh3::StatusCode, body: &Value) -> bool {
+pub fn is_rate_limit_error(status: StatusCode, body: &Value) -> bool {
     match status {
-        gh3::StatusCode::Forbidden => (),
-        _ => return false,
+        StatusCode::FORBIDDEN => is_body_rate_limit_error(body),
+        // GraphQL reports exceeded limit as a regular error
+        StatusCode::OK => is_graphql_rate_limit_error(body),
+        _ => false,
     }
-
-    is_body_rate_limit_error(body)
 }
 
 pub fn get_error_message(body: &Value) -> Option<&str> {
     body.get("message").and_then(|v| v.as_str())
 }
 
+fn is_graphql_rate_limit_error(body: &Value) -> bool {
+    body.get("errors")
+        .and_then(Value::as_array)
+        .map(|errors| {
+            errors
+                .iter()
+                .any(|error| error.get("type").and_then(Value::as_str) == Some("RATE_LIMITED"))
+        })
+        .unwrap_or(false)
+}
+
+/// Abuse detection limits, they aren't tied to the token quota and are lifted after a while
+pub fn is_secondary_rate_limit_message(message: &str) -> bool {
+    message.contains("secondary rate limit") || message.contains("abuse detection")
+}
+
 fn is_body_rate_limit_error(body: &Value) -> bool {
     let message = get_error_message(body);
     message
@@ -32,9 +45,14 @@ fn is_body_rate_limit_error(body: &Value) -> bool {
         .unwrap_or(false)
 }
 
+use chrono::Duration;
 use dotenv;
 use failure::Error;
 use std::env;
+use std::sync::Arc;
+
+pub static DEFAULT_API_URL: &str = "https://api.github.com";
+pub const DEFAULT_REVISIT_HOURS: i64 = 7 * 24;
 
 pub fn load_token() -> Result<String, Error> {
     load_env("GITHUB_TOKEN")
@@ -44,6 +62,137 @@ pub fn load_username() -> Result<String, Error> {
     load_env("GITHUB_USERNAME")
 }
 
+/// Base url of the GitHub API, `GITHUB_API_URL` if set
+pub fn load_api_url() -> String {
+    load_env("GITHUB_API_URL").unwrap_or_else(|_| DEFAULT_API_URL.to_string())
+}
+
+/// Maximum number of GitHub requests in flight, `GITHUB_MAX_CONCURRENCY` if set
+pub fn load_max_concurrency() -> Result<usize, Error> {
+    match load_env("GITHUB_MAX_CONCURRENCY") {
+        Ok(max) => Ok(max.parse()?),
+        Err(_) => Ok(DEFAULT_MAX_CONCURRENCY),
+    }
+}
+
+/// Time before a fetched repository is emitted again, `GITHUB_REVISIT_HOURS` if set, 0 disables
+/// the deduplication
+pub fn load_revisit_interval() -> Result<Duration, Error> {
+    match load_env("GITHUB_REVISIT_HOURS") {
+        Ok(hours) => Ok(Duration::hours(hours.parse()?)),
+        Err(_) => Ok(Duration::hours(DEFAULT_REVISIT_HOURS)),
+    }
+}
+
+/// Cache of REST responses, persisted to `GITHUB_CACHE_PATH` if set and bounded by
+/// `GITHUB_CACHE_CAPACITY` entries
+pub fn load_response_cache() -> Result<ResponseCache, Error> {
+    let cache = match load_env("GITHUB_CACHE_PATH") {
+        Ok(path) => ResponseCache::persistent(path)?,
+        Err(_) => ResponseCache::in_memory(),
+    };
+
+    match load_env("GITHUB_CACHE_CAPACITY") {
+        Ok(capacity) => Ok(cache.with_capacity(capacity.parse()?)),
+        Err(_) => Ok(cache),
+    }
+}
+
+/// Cassette at `GITHUB_CASSETTE`, `GITHUB_CASSETTE_MODE` is either `record` or `replay` (default)
+pub fn load_cassette() -> Result<Option<Cassette>, Error> {
+    let path = match load_env("GITHUB_CASSETTE") {
+        Ok(path) => path,
+        Err(_) => return Ok(None),
+    };
+
+    let mode = load_env("GITHUB_CASSETTE_MODE").unwrap_or_else(|_| "replay".to_string());
+    let cassette = match mode.as_str() {
+        "record" => Cassette::record(path),
+        "replay" => Cassette::replay(path)?,
+        _ => raise!(LoadError::InvalidCassetteMode { mode }),
+    };
+
+    warn!("GitHub cassette is in {:?} mode", cassette.mode());
+    Ok(Some(cassette))
+}
+
+/// Transport to the `GITHUB_API_URL`, bounded by `GITHUB_MAX_CONCURRENCY` requests in flight
+/// and going through the `GITHUB_CASSETTE` if there is one.
+///
+/// The clones of the transport share both the bound and the cassette.
+pub fn load_transport() -> Result<Transport, Error> {
+    let limit = ConcurrencyLimit::new(load_max_concurrency()?);
+    let mut transport = Transport::new(&load_api_url())?.with_concurrency_limit(Arc::new(limit));
+    if let Some(cassette) = load_cassette()? {
+        transport = transport.with_cassette(Arc::new(cassette));
+    }
+    Ok(transport)
+}
+
+/// Pool of the bot `GITHUB_TOKEN`, comma-separated `GITHUB_EXTRA_TOKENS` and GitHub App installations.
+///
+/// The App is configured with `GITHUB_APP_ID`, `GITHUB_APP_KEY` (path to the PEM private key)
+/// and comma-separated `GITHUB_APP_INSTALLATIONS`. Without `GITHUB_TOKEN` the first installation
+/// becomes the bot identity. Installation tokens are requested through `transport`.
+pub fn load_token_pool(transport: &Transport) -> Result<TokenPool, Error> {
+    let mut pool = TokenPool::new();
+    let mut has_bot = false;
+
+    if let Ok(token) = load_token() {
+        pool = pool.bot(token);
+        has_bot = true;
+    }
+
+    if let Ok(extra) = load_env("GITHUB_EXTRA_TOKENS") {
+        for token in split_list(&extra) {
+            pool = pool.token(token);
+        }
+    }
+
+    if let Ok(app_id) = load_env("GITHUB_APP_ID") {
+        let app = GithubApp::from_key_file(
+            app_id.parse()?,
+            load_env("GITHUB_APP_KEY")?,
+            transport.clone(),
+        )?;
+        let app = Arc::new(app);
+
+        for installation in split_list(&load_env("GITHUB_APP_INSTALLATIONS")?) {
+            let installation = Installation::new(app.clone(), installation.parse()?);
+            if has_bot {
+                pool = pool.token(installation);
+            } else {
+                pool = pool.bot(installation);
+                has_bot = true;
+            }
+        }
+    }
+
+    if !has_bot {
+        raise!(LoadError::NoBotCredentials)
+    }
+
+    info!("loaded {} GitHub tokens", pool.len());
+    Ok(pool)
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+#[derive(Debug, Fail)]
+enum LoadError {
+    #[fail(display = "neither GITHUB_TOKEN nor GitHub App installations are configured")]
+    NoBotCredentials,
+    #[fail(
+        display = "invalid GITHUB_CASSETTE_MODE {:?}, expected record or replay",
+        mode
+    )]
+    InvalidCassetteMode { mode: String },
+}
+
 fn load_env(key: &str) -> Result<String, Error> {
     // First search .env
     let var = dotenv::var(key)
@@ -0,0 +1,413 @@
+use failure::Error;
+use json::{self, Value};
+use reqwest::header::{
+    HeaderMap, HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
+use reqwest::StatusCode;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use github::v3::Response;
+
+/// Number of responses kept unless configured otherwise
+pub const DEFAULT_CAPACITY: usize = 10_000;
+/// Persistent cache is written at most this often, and once more when dropped
+const PERSIST_INTERVAL_SECS: u64 = 60;
+
+/// Validators and the body of a previously fetched resource
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CachedResponse {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    status: u16,
+    body: Option<Value>,
+    /// Logical time of the last use, the least recently used entries are evicted first
+    #[serde(default)]
+    used: u64,
+}
+
+/// Entries keyed by the token identity and the endpoint, with their LRU order
+struct Entries {
+    map: HashMap<String, CachedResponse>,
+    /// Key of every entry by its `used` time
+    order: BTreeMap<u64, String>,
+    clock: u64,
+    /// Whether there are entries not written to the cache file yet
+    dirty: bool,
+    persisted_at: Instant,
+}
+
+impl Entries {
+    fn new(mut map: HashMap<String, CachedResponse>) -> Self {
+        // Renumber the loaded entries, so every one of them gets a distinct time
+        let mut keys: Vec<(u64, String)> = map
+            .iter()
+            .map(|(key, entry)| (entry.used, key.clone()))
+            .collect();
+        keys.sort();
+
+        let mut order = BTreeMap::new();
+        for (clock, (_, key)) in keys.into_iter().enumerate() {
+            map.get_mut(&key).unwrap().used = clock as u64;
+            order.insert(clock as u64, key);
+        }
+
+        Entries {
+            clock: map.len() as u64,
+            map,
+            order,
+            dirty: false,
+            persisted_at: Instant::now(),
+        }
+    }
+
+    fn touch(&mut self, key: &str) -> Option<&CachedResponse> {
+        let entry = self.map.get_mut(key)?;
+        self.order.remove(&entry.used);
+        entry.used = self.clock;
+        self.order.insert(self.clock, key.to_owned());
+        self.clock += 1;
+        Some(entry)
+    }
+
+    fn insert(&mut self, key: String, mut entry: CachedResponse, capacity: usize) {
+        if let Some(old) = self.map.remove(&key) {
+            self.order.remove(&old.used);
+        }
+
+        entry.used = self.clock;
+        self.clock += 1;
+        self.order.insert(entry.used, key.clone());
+        self.map.insert(key, entry);
+        self.dirty = true;
+
+        while self.map.len() > capacity {
+            let oldest = match self.order.keys().next() {
+                Some(&oldest) => oldest,
+                None => break,
+            };
+            let key = self.order.remove(&oldest).unwrap();
+            self.map.remove(&key);
+        }
+    }
+}
+
+/// ETag/Last-Modified cache of GET responses.
+///
+/// Cached resources are requested conditionally; GitHub answers 304 Not Modified if
+/// nothing changed, and such responses don't count against the rate limit.
+///
+/// Responses are only reused for the token identity they were fetched with, since what
+/// GitHub returns depends on who is asking.
+pub struct ResponseCache {
+    entries: Mutex<Entries>,
+    path: Option<PathBuf>,
+    capacity: usize,
+    persist_interval: Duration,
+}
+
+impl ResponseCache {
+    pub fn in_memory() -> Self {
+        ResponseCache {
+            entries: Mutex::new(Entries::new(HashMap::new())),
+            path: None,
+            capacity: DEFAULT_CAPACITY,
+            persist_interval: Duration::from_secs(PERSIST_INTERVAL_SECS),
+        }
+    }
+
+    /// Cache persisted to `path`, existing entries are loaded if the file exists
+    pub fn persistent(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+
+        let entries = if path.exists() {
+            let file = BufReader::new(File::open(&path)?);
+            json::from_reader(file)?
+        } else {
+            HashMap::new()
+        };
+
+        info!(
+            "loaded {} cached responses from {}",
+            entries.len(),
+            path.display()
+        );
+
+        Ok(ResponseCache {
+            entries: Mutex::new(Entries::new(entries)),
+            path: Some(path),
+            capacity: DEFAULT_CAPACITY,
+            persist_interval: Duration::from_secs(PERSIST_INTERVAL_SECS),
+        })
+    }
+
+    /// Keep no more than `capacity` responses, evicting the least recently used ones
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Minimum time between the writes of the cache file
+    pub fn with_persist_interval(mut self, persist_interval: Duration) -> Self {
+        self.persist_interval = persist_interval;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().map.is_empty()
+    }
+
+    /// Conditional headers for the version of `endpoint` cached for the token `identity`
+    pub(super) fn validators(&self, identity: &str, endpoint: &str) -> Option<HeaderMap> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.map.get(&key(identity, endpoint))?;
+
+        let mut headers = HeaderMap::new();
+        if let Some(value) = entry
+            .etag
+            .as_ref()
+            .and_then(|v| HeaderValue::from_str(v).ok())
+        {
+            headers.insert(IF_NONE_MATCH, value);
+        }
+        if let Some(value) = entry
+            .last_modified
+            .as_ref()
+            .and_then(|v| HeaderValue::from_str(v).ok())
+        {
+            headers.insert(IF_MODIFIED_SINCE, value);
+        }
+
+        if headers.is_empty() {
+            None
+        } else {
+            Some(headers)
+        }
+    }
+
+    /// Replace 304 with the cached response, or remember the fresh one
+    pub(super) fn process(
+        &self,
+        identity: &str,
+        endpoint: &str,
+        mut response: Response,
+    ) -> Response {
+        let key = key(identity, endpoint);
+
+        if response.status == StatusCode::NOT_MODIFIED {
+            if let Some(entry) = self.entries.lock().unwrap().touch(&key) {
+                debug!("{} is not modified, using cached response", endpoint);
+                response.status = StatusCode::from_u16(entry.status).unwrap_or(StatusCode::OK);
+                response.body = entry.body.clone();
+            }
+            return response;
+        }
+
+        if !response.status.is_success() {
+            return response;
+        }
+
+        let header = |name| {
+            response
+                .headers
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+
+        let entry = CachedResponse {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            status: response.status.as_u16(),
+            body: response.body.clone(),
+            used: 0,
+        };
+
+        if entry.etag.is_some() || entry.last_modified.is_some() {
+            let mut entries = self.entries.lock().unwrap();
+            entries.insert(key, entry, self.capacity);
+
+            if entries.persisted_at.elapsed() >= self.persist_interval {
+                if let Err(e) = self.write(&mut entries) {
+                    warn!("failed to persist response cache: {}", e);
+                }
+            }
+        }
+
+        response
+    }
+
+    /// Write entries to the cache file, no-op for in-memory cache
+    pub fn persist(&self) -> Result<(), Error> {
+        self.write(&mut self.entries.lock().unwrap())
+    }
+
+    fn write(&self, entries: &mut Entries) -> Result<(), Error> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+
+        // Write into temporary file first so the cache is never left half-written
+        let tmp_path = tmp_path(path);
+        {
+            let file = File::create(&tmp_path)?;
+            json::to_writer(file, &entries.map)?;
+        }
+        fs::rename(&tmp_path, path)?;
+
+        entries.dirty = false;
+        entries.persisted_at = Instant::now();
+        Ok(())
+    }
+}
+
+impl Drop for ResponseCache {
+    fn drop(&mut self) {
+        let dirty = self.entries.lock().unwrap().dirty;
+        if dirty {
+            if let Err(e) = self.persist() {
+                warn!("failed to persist response cache: {}", e);
+            }
+        }
+    }
+}
+
+fn key(identity: &str, endpoint: &str) -> String {
+    format!("{} {}", identity, endpoint)
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        ResponseCache::in_memory()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{FakeGithub, FakeRepo};
+    use github::tokens::TokenPool;
+    use github::v3::{ExecutorExt, Github};
+    use std::sync::Arc;
+    use tempfile;
+
+    fn github(fake: &FakeGithub, cache: ResponseCache) -> Github {
+        with_token(fake, "bot", Arc::new(cache))
+    }
+
+    fn with_token(fake: &FakeGithub, token: &str, cache: Arc<ResponseCache>) -> Github {
+        Github::with_pool(Arc::new(TokenPool::single(token)), &fake.url())
+            .unwrap()
+            .with_cache(cache)
+    }
+
+    fn get_repo(gh: &Github) -> Value {
+        gh.get()
+            .custom_endpoint("repos/owner/repo")
+            .send(&[StatusCode::OK])
+            .unwrap()
+    }
+
+    #[test]
+    fn not_modified_responses_are_served_from_cache() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo").stars(1));
+        let gh = github(&fake, ResponseCache::in_memory());
+
+        assert_eq!(get_repo(&gh)["stargazers_count"], 1);
+        assert_eq!(get_repo(&gh)["stargazers_count"], 1);
+        assert_eq!(fake.state().core_limit.remaining, 4999);
+        assert!(fake.requests()[1].header("if-none-match").is_some());
+
+        fake.state().repos.get_mut("owner/repo").unwrap().stars = 2;
+        assert_eq!(get_repo(&gh)["stargazers_count"], 2);
+        assert_eq!(fake.state().core_limit.remaining, 4998);
+    }
+
+    #[test]
+    fn persistent_cache_survives_restart() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo"));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.json");
+
+        get_repo(&github(&fake, ResponseCache::persistent(&path).unwrap()));
+
+        let cache = ResponseCache::persistent(&path).unwrap();
+        assert_eq!(cache.len(), 1);
+        get_repo(&github(&fake, cache));
+        assert_eq!(fake.state().core_limit.remaining, 4999);
+    }
+
+    #[test]
+    fn responses_are_not_shared_between_tokens() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo"));
+        let cache = Arc::new(ResponseCache::in_memory());
+
+        get_repo(&with_token(&fake, "bot", cache.clone()));
+        get_repo(&with_token(&fake, "other", cache.clone()));
+        assert_eq!(cache.len(), 2);
+        assert!(fake.requests()[1].header("if-none-match").is_none());
+    }
+
+    #[test]
+    fn least_recently_used_responses_are_evicted() {
+        let fake = FakeGithub::start().unwrap();
+        for name in &["owner/a", "owner/b", "owner/c"] {
+            fake.add_repo(FakeRepo::new(*name));
+        }
+        let gh = github(&fake, ResponseCache::in_memory().with_capacity(2));
+        let get = |name| {
+            let _: Value = gh
+                .get()
+                .custom_endpoint(&format!("repos/{}", name))
+                .send(&[StatusCode::OK])
+                .unwrap();
+            fake.requests()
+                .last()
+                .unwrap()
+                .header("if-none-match")
+                .is_some()
+        };
+
+        assert!(!get("owner/a"));
+        assert!(!get("owner/b"));
+        assert!(get("owner/a"));
+        assert!(!get("owner/c"));
+        assert_eq!(gh.cache().unwrap().len(), 2);
+        assert!(get("owner/a"));
+        assert!(!get("owner/b"));
+    }
+
+    #[test]
+    fn persistence_is_debounced() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo"));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache.json");
+
+        let gh = github(&fake, ResponseCache::persistent(&path).unwrap());
+        get_repo(&gh);
+        assert!(!path.exists());
+
+        drop(gh);
+        assert_eq!(ResponseCache::persistent(&path).unwrap().len(), 1);
+    }
+}
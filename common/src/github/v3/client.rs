@@ -0,0 +1,183 @@
+use failure::Error;
+use json::{self, Value};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, USER_AGENT};
+use reqwest::{Method, StatusCode};
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use github::cassette::Cassette;
+use github::concurrency::ConcurrencyLimit;
+use github::tokens::{Affinity, Api, RateLimit, TokenPool};
+use github::transport::Transport;
+use github::utils;
+use github::v3::cache::ResponseCache;
+use github::RequestError;
+
+pub struct Github {
+    transport: Transport,
+    pool: Arc<TokenPool>,
+    cache: Option<Arc<ResponseCache>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: Method,
+    pub endpoint: String,
+    pub headers: HeaderMap,
+    pub body: Option<Value>,
+    pub affinity: Affinity,
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Option<Value>,
+}
+
+impl Github {
+    pub fn new(token: &str) -> Result<Self, Error> {
+        Self::with_pool(Arc::new(TokenPool::single(token)), &utils::load_api_url())
+    }
+
+    pub fn with_pool(pool: Arc<TokenPool>, api_url: &str) -> Result<Self, Error> {
+        Ok(Self::with_transport(pool, Transport::new(api_url)?))
+    }
+
+    pub fn with_transport(pool: Arc<TokenPool>, transport: Transport) -> Self {
+        Github {
+            transport,
+            pool,
+            cache: None,
+        }
+    }
+
+    /// Share the bound on requests in flight, e.g. with the v4 client
+    pub fn with_concurrency_limit(mut self, limit: Arc<ConcurrencyLimit>) -> Self {
+        self.transport = self.transport.with_concurrency_limit(limit);
+        self
+    }
+
+    /// Record the requests into the cassette or replay them from it
+    pub fn with_cassette(mut self, cassette: Arc<Cassette>) -> Self {
+        self.transport = self.transport.with_cassette(cassette);
+        self
+    }
+
+    /// Make GET requests conditional on the responses cached earlier
+    pub fn with_cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn pool(&self) -> &Arc<TokenPool> {
+        &self.pool
+    }
+
+    pub fn cache(&self) -> Option<&Arc<ResponseCache>> {
+        self.cache.as_ref()
+    }
+
+    /// Perform request with the best token available for it.
+    ///
+    /// If the token turns out to be rate limited, the request is retried with another one,
+    /// if there are none left -- waits until the earliest limit reset.
+    ///
+    /// GET requests are made conditional on the response cached for the selected token.
+    pub fn execute(&self, request: &Request) -> Result<Response, Error> {
+        let cache = match self.cache {
+            Some(ref cache) if request.method == Method::GET => Some(cache),
+            _ => None,
+        };
+
+        loop {
+            let lease = match self.pool.select(Api::Rest, request.affinity) {
+                Ok(lease) => lease,
+                Err(err) => match err.downcast::<RequestError>() {
+                    Ok(RequestError::ExceededRateLimit { retry_in }) => {
+                        warn!("request limit exceeded: retrying in {} seconds", retry_in);
+                        thread::sleep(Duration::from_secs(retry_in));
+                        continue;
+                    }
+                    Ok(err) => return Err(err.into()),
+                    Err(err) => return Err(err),
+                },
+            };
+
+            let cached = cache.map(|cache| (cache, self.pool.identity(lease.index)));
+            let validators = cached
+                .as_ref()
+                .and_then(|(cache, identity)| cache.validators(identity, &request.endpoint));
+
+            let response = self.send(&lease.secret, request, validators)?;
+
+            if let Some(limits) = RateLimit::from_headers(&response.headers) {
+                self.pool.update(lease.index, Api::Rest, limits);
+            }
+
+            let rate_limited = response
+                .body
+                .as_ref()
+                .map(|body| utils::is_rate_limit_error(response.status, body))
+                .unwrap_or(false);
+
+            if rate_limited {
+                self.pool.exhausted(lease.index, Api::Rest);
+                continue;
+            }
+
+            return Ok(match cached {
+                Some((cache, identity)) => cache.process(&identity, &request.endpoint, response),
+                None => response,
+            });
+        }
+    }
+
+    fn send(
+        &self,
+        token: &str,
+        request: &Request,
+        validators: Option<HeaderMap>,
+    ) -> Result<Response, Error> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("token {}", token))?,
+        );
+        headers.insert(USER_AGENT, HeaderValue::from_static("rustyrobot"));
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("application/vnd.github.v3+json"),
+        );
+        headers.extend(request.headers.clone());
+        if let Some(validators) = validators {
+            headers.extend(validators);
+        }
+
+        let response = self.transport.send(
+            request.method.clone(),
+            &request.endpoint,
+            headers,
+            request.body.as_ref(),
+        )?;
+
+        trace!("status: {}", response.status);
+        let body = if response.body.is_empty() {
+            trace!("response: empty");
+            None
+        } else {
+            let text = response.body;
+            let json = json::from_str(&text).unwrap_or_else(|_| Value::String(text));
+            trace!("response: {}", json);
+            Some(json)
+        };
+
+        Ok(Response {
+            status: response.status,
+            headers: response.headers,
+            body,
+        })
+    }
+}
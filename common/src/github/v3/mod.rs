@@ -1,139 +1,231 @@
-pub use gh3::client::Github;
-use gh3::headers::{rate_limit, rate_limit_remaining, rate_limit_reset};
-use gh3::StatusCode;
+mod cache;
+mod client;
+mod pages;
 
-use std::sync::{Arc, RwLock};
+pub use self::cache::ResponseCache;
+pub use self::client::{Github, Request, Response};
+pub use self::pages::Pages;
+pub use reqwest::{Method, StatusCode};
 
 use failure::Error;
-use gh3::client::Executor;
-use github::utils;
+use github::tokens::Affinity;
 use github::RequestError;
 use json::{self, Value};
+use reqwest::header::HeaderMap;
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 
-use error_chain_failure_interop::ResultExt;
+pub struct RequestBuilder<'g> {
+    github: &'g Github,
+    request: Result<Request, Error>,
+}
 
-use chrono::Utc;
-use std::thread;
-use std::time::Duration;
+impl Github {
+    pub fn get(&self) -> RequestBuilder {
+        RequestBuilder::new(self, Method::GET, Ok(Value::Null))
+    }
 
-pub trait ExecutorExt<T>: Executor + Sized {
-    fn send(self, good_statuses: &[StatusCode]) -> Result<T, Error>;
-}
+    pub fn post<T: Serialize>(&self, body: T) -> RequestBuilder {
+        RequestBuilder::new(self, Method::POST, json::to_value(body))
+    }
 
-impl<T, E: Executor + Sized> ExecutorExt<T> for E
-where
-    T: DeserializeOwned,
-{
-    fn send(self, good_statuses: &[StatusCode]) -> Result<T, Error>
-    where
-        T: DeserializeOwned,
-    {
-        let now = Utc::now().timestamp();
-        let limits = *RATE_LIMIT.read().unwrap();
-        if limits.remaining < LIMIT_THRESHOLD && now < limits.reset_at {
-            let timeout = limits.reset_at - Utc::now().timestamp();
-            warn!("request limit exceeded: retrying in {} seconds", timeout);
-            thread::sleep(Duration::from_secs(timeout as u64));
-        }
+    pub fn put<T: Serialize>(&self, body: T) -> RequestBuilder {
+        RequestBuilder::new(self, Method::PUT, json::to_value(body))
+    }
+
+    pub fn patch<T: Serialize>(&self, body: T) -> RequestBuilder {
+        RequestBuilder::new(self, Method::PATCH, json::to_value(body))
+    }
 
-        // Perform request
-        let (headers, status, data) = self.execute::<Value>().sync()?;
+    pub fn delete<T: Serialize>(&self, body: T) -> RequestBuilder {
+        RequestBuilder::new(self, Method::DELETE, json::to_value(body))
+    }
+}
+
+impl<'g> RequestBuilder<'g> {
+    fn new(github: &'g Github, method: Method, body: Result<Value, json::Error>) -> Self {
+        let request = body.map_err(Error::from).map(|body| Request {
+            method,
+            endpoint: String::new(),
+            headers: HeaderMap::new(),
+            // `()` bodies are serialized into null, that means no body at all
+            body: if body.is_null() { None } else { Some(body) },
+            affinity: Affinity::Any,
+        });
+
+        RequestBuilder { github, request }
+    }
 
-        trace!("status: {}", status);
-        if data.is_none() {
-            trace!("response: empty");
+    pub fn custom_endpoint(mut self, endpoint: &str) -> Self {
+        if let Ok(ref mut request) = self.request {
+            request.endpoint = endpoint.to_owned();
         }
+        self
+    }
 
-        // Get rate limits info
-        let rate_limit = || -> Option<RateLimit> {
-            let limits = RateLimit {
-                limit: rate_limit(&headers)?,
-                remaining: rate_limit_remaining(&headers)?,
-                reset_at: i64::from(rate_limit_reset(&headers)?),
-            };
-            debug!("API v3 limits: {:?}", limits);
-            Some(limits)
-        }();
-
-        if let Some(rate_limit) = rate_limit {
-            *RATE_LIMIT.write().unwrap() = rate_limit;
+    /// Identity the request must be made with, `Affinity::Any` by default
+    pub fn affinity(mut self, affinity: Affinity) -> Self {
+        if let Ok(ref mut request) = self.request {
+            request.affinity = affinity;
         }
+        self
+    }
 
-        // Handle the response
-        let json = data.ok_or(RequestError::EmptyResponse)?;
-        trace!("response: {}", json);
+    /// Iterate over the items of a list endpoint, following `Link: rel="next"` headers
+    pub fn paginate<T: DeserializeOwned>(self) -> Pages<'g, T> {
+        Pages::new(self.github, self.request)
+    }
 
-        if utils::is_rate_limit_error_v3(status, &json) {
-            // Raise unfilled rate limit error
-            raise!(RequestError::ExceededRateLimit { retry_in: 0 })
-        }
+    fn execute(self, good_statuses: &[StatusCode]) -> Result<Response, Error> {
+        execute(self.github, self.request?, good_statuses)
+    }
+}
 
-        if !good_statuses.contains(&status) {
-            raise!(RequestError::ResponseStatusNotOk {
-                status: status.as_u16()
-            })
-        }
+fn execute(
+    github: &Github,
+    request: Request,
+    good_statuses: &[StatusCode],
+) -> Result<Response, Error> {
+    let response = github.execute(&request)?;
+
+    if !good_statuses.contains(&response.status) {
+        raise!(RequestError::status(
+            response.status.as_u16(),
+            response.body.as_ref()
+        ))
+    }
 
+    Ok(response)
+}
+
+pub trait ExecutorExt<T>: Sized {
+    fn send(self, good_statuses: &[StatusCode]) -> Result<T, Error>;
+}
+
+impl<'g, T> ExecutorExt<T> for RequestBuilder<'g>
+where
+    T: DeserializeOwned,
+{
+    fn send(self, good_statuses: &[StatusCode]) -> Result<T, Error> {
+        let response = self.execute(good_statuses)?;
+        let json = response.body.ok_or(RequestError::EmptyResponse)?;
         Ok(json::from_value(json)?)
     }
 }
 
 pub struct EmptyResponse;
 
-impl<E: Executor + Sized> ExecutorExt<EmptyResponse> for E {
+impl<'g> ExecutorExt<EmptyResponse> for RequestBuilder<'g> {
     fn send(self, good_statuses: &[StatusCode]) -> Result<EmptyResponse, Error> {
-        let now = Utc::now().timestamp();
-        let limits = *RATE_LIMIT.read().unwrap();
-        if limits.remaining < LIMIT_THRESHOLD && now < limits.reset_at {
-            let timeout = limits.reset_at - Utc::now().timestamp();
-            warn!("request limit exceeded: retrying in {} seconds", timeout);
-            thread::sleep(Duration::from_secs(timeout as u64));
-        }
+        self.execute(good_statuses)?;
+        Ok(EmptyResponse)
+    }
+}
 
-        // Perform request
-        let (headers, status, data) = self.execute::<Value>().sync()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{Failure, FakeGithub, FakeRepo};
+    use github::tokens::{Api, TokenPool};
+    use github::ErrorKind;
+    use std::sync::Arc;
 
-        trace!("status: {}", status);
-        if data.is_none() {
-            trace!("response: empty");
-        }
+    fn github(fake: &FakeGithub, pool: TokenPool) -> Github {
+        Github::with_pool(Arc::new(pool), &fake.url()).unwrap()
+    }
 
-        // Get rate limits info
-        let rate_limit = || -> Option<RateLimit> {
-            let limits = RateLimit {
-                limit: rate_limit(&headers)?,
-                remaining: rate_limit_remaining(&headers)?,
-                reset_at: i64::from(rate_limit_reset(&headers)?),
-            };
-            debug!("API v3 limits: {:?}", limits);
-            Some(limits)
-        }();
-
-        if let Some(rate_limit) = rate_limit {
-            *RATE_LIMIT.write().unwrap() = rate_limit;
-        }
+    #[test]
+    fn send_and_track_limits() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo"));
+        let gh = github(&fake, TokenPool::single("bot"));
+
+        let fork: Value = gh
+            .post(())
+            .custom_endpoint("repos/owner/repo/forks")
+            .send(&[StatusCode::ACCEPTED])
+            .unwrap();
+        assert_eq!(fork["full_name"], "rustyrobot/repo");
+
+        let limit = gh.pool().rate_limit(0, Api::Rest).unwrap();
+        assert_eq!(limit.remaining, 4999);
+
+        let _: EmptyResponse = gh
+            .delete(())
+            .custom_endpoint("repos/rustyrobot/repo")
+            .send(&[StatusCode::NO_CONTENT])
+            .unwrap();
+    }
 
-        if !good_statuses.contains(&status) {
-            raise!(RequestError::ResponseStatusNotOk {
-                status: status.as_u16()
-            })
+    #[test]
+    fn unexpected_status() {
+        let fake = FakeGithub::start().unwrap();
+        let gh = github(&fake, TokenPool::single("bot"));
+
+        let result: Result<EmptyResponse, Error> = gh
+            .delete(())
+            .custom_endpoint("repos/rustyrobot/none")
+            .send(&[StatusCode::NO_CONTENT]);
+
+        match result.err().unwrap().downcast::<RequestError>() {
+            Ok(RequestError::ResponseStatusNotOk {
+                status, message, ..
+            }) => {
+                assert_eq!(status, 404);
+                assert_eq!(message.unwrap(), "Not Found");
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
         }
+    }
 
-        Ok(EmptyResponse)
+    #[test]
+    fn rate_limited_token_fails_over() {
+        let fake = FakeGithub::start().unwrap();
+        fake.fail_next("GET /user", Failure::rate_limited());
+        let gh = github(&fake, TokenPool::new().token("first").token("second"));
+
+        let user: Value = gh
+            .get()
+            .custom_endpoint("user")
+            .send(&[StatusCode::OK])
+            .unwrap();
+        assert_eq!(user["login"], "rustyrobot");
+
+        let tokens: Vec<String> = fake
+            .requests()
+            .iter()
+            .map(|r| r.header("authorization").unwrap().to_string())
+            .collect();
+        assert_eq!(tokens, vec!["token first", "token second"]);
     }
-}
 
-#[derive(Copy, Clone, Debug, Deserialize, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct RateLimit {
-    limit: u32,
-    remaining: u32,
-    reset_at: i64,
-}
+    #[test]
+    fn secondary_rate_limit_is_retryable() {
+        let fake = FakeGithub::start().unwrap();
+        fake.fail_next("GET /user", Failure::secondary_rate_limited());
+        let gh = github(&fake, TokenPool::new().token("first").token("second"));
 
-// Remaining requests threshold. Under this value requests will be stopped until limits reset
-const LIMIT_THRESHOLD: u32 = 5;
-lazy_static! {
-    static ref RATE_LIMIT: Arc<RwLock<RateLimit>> = Arc::new(RwLock::new(RateLimit::default()));
+        let result: Result<Value, Error> = gh.get().custom_endpoint("user").send(&[StatusCode::OK]);
+        assert_eq!(ErrorKind::of(&result.unwrap_err()), ErrorKind::Retryable);
+        // Not a token quota, the other token would be limited all the same
+        assert_eq!(fake.requests().len(), 1);
+    }
+
+    #[test]
+    fn bot_affinity() {
+        let fake = FakeGithub::start().unwrap();
+        let gh = github(&fake, TokenPool::new().token("other").bot("bot"));
+
+        let _: Value = gh
+            .get()
+            .custom_endpoint("notifications")
+            .affinity(Affinity::Bot)
+            .send(&[StatusCode::OK])
+            .unwrap();
+
+        assert_eq!(
+            fake.requests()[0].header("authorization"),
+            Some("token bot")
+        );
+    }
 }
@@ -0,0 +1,166 @@
+use failure::Error;
+use json;
+use reqwest::header::{HeaderMap, LINK};
+use serde::de::DeserializeOwned;
+
+use std::collections::VecDeque;
+
+use github::v3::{execute, Github, Request, StatusCode};
+use github::RequestError;
+
+// GitHub maximum, defaults to 30 otherwise
+const PER_PAGE: u32 = 100;
+
+/// Items of a REST list endpoint, the next page is requested once the current one is drained.
+///
+/// Pages are requested through the token pool like any other request, so rate limits are
+/// respected between the pages as well.
+pub struct Pages<'g, T> {
+    github: &'g Github,
+    next: Option<Result<Request, Error>>,
+    items: VecDeque<T>,
+}
+
+impl<'g, T> Pages<'g, T>
+where
+    T: DeserializeOwned,
+{
+    pub(super) fn new(github: &'g Github, request: Result<Request, Error>) -> Self {
+        let request = request.map(|mut request| {
+            if !request.endpoint.contains("per_page=") {
+                let delimiter = if request.endpoint.contains('?') {
+                    '&'
+                } else {
+                    '?'
+                };
+                request.endpoint =
+                    format!("{}{}per_page={}", request.endpoint, delimiter, PER_PAGE);
+            }
+            request
+        });
+
+        Pages {
+            github,
+            next: Some(request),
+            items: VecDeque::new(),
+        }
+    }
+
+    fn fetch(&mut self, request: Request) -> Result<(), Error> {
+        let response = execute(self.github, request.clone(), &[StatusCode::OK])?;
+
+        self.next = next_link(&response.headers).map(|url| {
+            Ok(Request {
+                endpoint: url,
+                headers: HeaderMap::new(),
+                ..request
+            })
+        });
+
+        let page = response.body.ok_or(RequestError::EmptyResponse)?;
+        let items: Vec<T> = json::from_value(page)?;
+        debug!("fetched page of {} items", items.len());
+        self.items.extend(items);
+
+        Ok(())
+    }
+}
+
+impl<'g, T> Iterator for Pages<'g, T>
+where
+    T: DeserializeOwned,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.items.pop_front() {
+                return Some(Ok(item));
+            }
+
+            let request = match self.next.take()? {
+                Ok(request) => request,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if let Err(e) = self.fetch(request) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// Url of the `rel="next"` page from the `Link` header
+fn next_link(headers: &HeaderMap) -> Option<String> {
+    let link = headers.get(LINK)?.to_str().ok()?;
+
+    link.split(',')
+        .filter_map(|link| {
+            let mut params = link.split(';');
+            let url = params.next()?.trim();
+            let is_next = params.any(|param| param.trim() == "rel=\"next\"");
+
+            if is_next && url.starts_with('<') && url.ends_with('>') {
+                Some(url[1..url.len() - 1].to_string())
+            } else {
+                None
+            }
+        })
+        .next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{FakeGithub, FakePull, FakeRepo};
+    use github::tokens::TokenPool;
+    use json::Value;
+    use reqwest::header::HeaderValue;
+    use std::sync::Arc;
+
+    #[test]
+    fn parse_link_header() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            LINK,
+            HeaderValue::from_static(
+                "<https://api.github.com/notifications?page=1>; rel=\"prev\", \
+                 <https://api.github.com/notifications?page=3>; rel=\"next\", \
+                 <https://api.github.com/notifications?page=5>; rel=\"last\"",
+            ),
+        );
+
+        assert_eq!(
+            next_link(&headers),
+            Some("https://api.github.com/notifications?page=3".to_string())
+        );
+
+        headers.insert(
+            LINK,
+            HeaderValue::from_static(
+                "<https://api.github.com/notifications?page=1>; rel=\"first\"",
+            ),
+        );
+        assert_eq!(next_link(&headers), None);
+    }
+
+    #[test]
+    fn follows_next_pages() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo"));
+        for i in 0..5 {
+            fake.add_pull(FakePull::new("owner/repo", format!("rustyrobot:fmt{}", i)));
+        }
+
+        let gh = Github::with_pool(Arc::new(TokenPool::single("bot")), &fake.url()).unwrap();
+        let numbers: Vec<u64> = gh
+            .get()
+            .custom_endpoint("repos/owner/repo/pulls?per_page=2")
+            .paginate::<Value>()
+            .map(|pull| pull.unwrap()["number"].as_u64().unwrap())
+            .collect();
+
+        assert_eq!(numbers, vec![1, 2, 3, 4, 5]);
+        assert_eq!(fake.requests().len(), 3);
+    }
+}
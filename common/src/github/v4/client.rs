@@ -1,23 +1,21 @@
-use chrono::{DateTime, Utc};
-use error_chain_failure_interop::ResultExt;
 use failure::Error;
-use gh4::client::Github as Driver;
-use gh4::query::Query;
-use gh4::StatusCode;
+use github::concurrency::ConcurrencyLimit;
+use github::tokens::{Affinity, Api, Lease, RateLimit, TokenPool};
+use github::transport::Transport;
 use github::utils;
 use github::GithubClient;
 use github::RequestError;
 use json;
 use json::Value;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, USER_AGENT};
+use reqwest::{Method, StatusCode};
 use serde::de::DeserializeOwned;
 use std::borrow::Cow;
-use std::cell::RefCell;
-use std::fmt::Display;
+use std::sync::Arc;
 
 pub struct Client {
-    driver: RefCell<Driver>,
-    login: String,
-    limit: RateLimit,
+    transport: Transport,
+    pool: Arc<TokenPool>,
 }
 
 pub enum RequestType {
@@ -39,13 +37,14 @@ impl GithubClient for Client {
         T: DeserializeOwned,
     {
         let description = &request.description;
-        let result = match &request.body {
-            RequestType::Query(query) => {
-                Self::run_query::<_, &str>(&mut self.driver.borrow_mut(), description, query, None)
-            }
-            RequestType::Mutation(_query) => unimplemented!(),
+        // Mutations are visible to others, so they have to come from the bot
+        let (query, affinity) = match &request.body {
+            RequestType::Query(query) => (query, Affinity::Any),
+            RequestType::Mutation(query) => (query, Affinity::Bot),
         };
 
+        let result = self.run_pooled_query::<_, &str>(affinity, description, query, None);
+
         match result {
             Ok(data) => {
                 trace!("request succeeded");
@@ -53,57 +52,46 @@ impl GithubClient for Client {
             }
             Err(err) => {
                 error!("{} request failed: {}", description, err);
-                match err.downcast::<RequestError>() {
-                    Ok(err) => Err(self.fill_rate_limit_error(err)?),
-                    Err(err) => Err(err),
-                }
+                Err(err)
             }
         }
     }
 }
 
 impl Client {
-    pub fn new<T>(token: T) -> Result<Self, Error>
-    where
-        T: AsRef<str> + Display,
-    {
-        let mut driver = Driver::new(token).sync()?;
-
-        let login = Self::run_get_login(&mut driver)?;
-
-        let limit = Self::run_get_api_limit(&mut driver)?;
-
-        let driver = RefCell::new(driver);
-
-        let gh = Client {
-            driver,
-            login,
-            limit,
-        };
+    pub fn new(pool: Arc<TokenPool>, api_url: &str) -> Result<Self, Error> {
+        Self::with_concurrency_limit(pool, api_url, Arc::new(ConcurrencyLimit::default()))
+    }
 
-        Ok(gh)
+    pub fn with_concurrency_limit(
+        pool: Arc<TokenPool>,
+        api_url: &str,
+        limit: Arc<ConcurrencyLimit>,
+    ) -> Result<Self, Error> {
+        let transport = Transport::new(api_url)?.with_concurrency_limit(limit);
+        Self::with_transport(pool, transport)
     }
 
-    fn fill_rate_limit_error(&self, error: RequestError) -> Result<Error, Error> {
-        match error {
-            RequestError::ExceededRateLimit { .. } => {
-                let now = Utc::now();
-                let retry_in = self.limit.reset_at.timestamp() - now.timestamp();
-                assert!(retry_in >= 0);
-                Err(RequestError::ExceededRateLimit {
-                    retry_in: retry_in as u64,
-                }
-                .into())
+    pub fn with_transport(pool: Arc<TokenPool>, transport: Transport) -> Result<Self, Error> {
+        let client = Client { transport, pool };
+
+        for index in 0..client.pool.len() {
+            // Installations act on behalf of the App, they have no viewer to log in as
+            if !client.pool.is_installation(index) {
+                let login = client.run_get_login(index)?;
+                client.pool.set_login(index, login);
             }
-            err => Err(err.into()),
+            client.run_get_api_limit(index)?;
         }
+
+        Ok(client)
     }
 
-    fn run_get_login(driver: &mut Driver) -> Result<String, Error> {
-        info!("logging in via OAuth");
+    fn run_get_login(&self, index: usize) -> Result<String, Error> {
+        info!("logging in via OAuth with token #{}", index);
 
-        let login: String = Self::run_query(
-            driver,
+        let login: String = self.run_query(
+            index,
             "login",
             "query { viewer { login } }",
             Some(&[&"data", &"viewer", &"login"]),
@@ -113,27 +101,55 @@ impl Client {
         Ok(login)
     }
 
-    fn run_get_api_limit(driver: &mut Driver) -> Result<RateLimit, Error> {
-        info!("requesting rate limit");
+    fn run_get_api_limit(&self, index: usize) -> Result<RateLimit, Error> {
+        info!("requesting rate limit of token #{}", index);
 
-        let mut limit: RateLimit = Self::run_query(
-            driver,
+        let limit: RateLimit = self.run_query(
+            index,
             "rate limit",
             "query { rateLimit { limit remaining resetAt } }",
             Some(&[&"data", &"rateLimit"]),
         )?;
 
-        limit.used = limit.limit - limit.remaining;
+        self.pool.update(index, Api::GraphQL, limit);
 
         info!("rate limit: {}/hr", limit.limit);
-        info!("used: {}", limit.used);
+        info!("used: {}", limit.limit - limit.remaining);
         info!("reset at: {}", limit.reset_at);
 
         Ok(limit)
     }
 
+    /// Run query with the best token available, switching to the next one if it's rate limited
+    fn run_pooled_query<T, S>(
+        &self,
+        affinity: Affinity,
+        description: &str,
+        query: &str,
+        json_selectors: Option<&[&S]>,
+    ) -> Result<T, Error>
+    where
+        T: DeserializeOwned,
+        S: json::value::Index,
+    {
+        loop {
+            // Fails with ExceededRateLimit when every eligible token is exhausted
+            let Lease { index, .. } = self.pool.select(Api::GraphQL, affinity)?;
+
+            match self.run_query(index, description, query, json_selectors) {
+                Err(err) => match err.downcast::<RequestError>() {
+                    Ok(RequestError::ExceededRateLimit { .. }) => continue,
+                    Ok(err) => return Err(err.into()),
+                    Err(err) => return Err(err),
+                },
+                result => return result,
+            }
+        }
+    }
+
     fn run_query<T, S>(
-        driver: &mut Driver,
+        &self,
+        index: usize,
         description: &str,
         query: &str,
         json_selectors: Option<&[&S]>,
@@ -142,22 +158,47 @@ impl Client {
         T: DeserializeOwned,
         S: json::value::Index,
     {
-        let (_, status, json) = driver.query::<Value>(&Query::new_raw(query)).sync()?;
+        let secret = self.pool.secret(index)?;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_str(&format!("bearer {}", secret))?,
+        );
+        headers.insert(USER_AGENT, HeaderValue::from_static("rustyrobot"));
+
+        let body = json!({ "query": query });
+        let response = self
+            .transport
+            .send(Method::POST, "graphql", headers, Some(&body))?;
+        let (status, headers, text) = (response.status, response.headers, response.body);
+
+        if let Some(limit) = RateLimit::from_headers(&headers) {
+            self.pool.update(index, Api::GraphQL, limit);
+        }
 
         debug!("{} status: {}", description, status);
-        let mut json = json.ok_or(RequestError::EmptyResponse)?;
+        if text.is_empty() {
+            raise!(RequestError::EmptyResponse)
+        }
+        let mut json: Value = json::from_str(&text)?;
         trace!("{} response: {}", description, json);
 
-        if utils::is_rate_limit_error_v4(status, &json) {
+        if utils::is_rate_limit_error(status, &json) {
+            self.pool.exhausted(index, Api::GraphQL);
             // Raise unfilled rate limit error
             raise!(RequestError::ExceededRateLimit { retry_in: 0 })
         }
 
-        match status {
-            StatusCode::Ok => (),
-            status => raise!(RequestError::ResponseStatusNotOk {
-                status: status.as_u16()
-            }),
+        if status != StatusCode::OK {
+            raise!(RequestError::status(status.as_u16(), Some(&json)))
+        }
+
+        if let Some(error) = RequestError::graphql(&json) {
+            // Errors along with the data only concern some of the fields, e.g. a deleted repository
+            if json["data"].is_null() {
+                raise!(error)
+            }
+            warn!("{} partially failed: {}", description, error);
         }
 
         if let Some(selectors) = json_selectors {
@@ -170,12 +211,43 @@ impl Client {
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct RateLimit {
-    limit: u64,
-    remaining: u64,
-    reset_at: DateTime<Utc>,
-    #[serde(skip)]
-    used: u64,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{Failure, FakeGithub};
+    use github::ErrorKind;
+
+    #[test]
+    fn graphql_errors_are_raised() {
+        let fake = FakeGithub::start().unwrap();
+        let client = Client::new(Arc::new(TokenPool::single("bot")), &fake.url()).unwrap();
+
+        fake.fail_next(
+            "POST /graphql",
+            Failure::status(200).body(json!({
+                "data": null,
+                "errors": [{
+                    "type": "NOT_FOUND",
+                    "path": ["repository"],
+                    "message": "Could not resolve to a Repository with the name 'nope'.",
+                }],
+            })),
+        );
+
+        let request = Request {
+            description: "repository".into(),
+            body: RequestType::Query(
+                "query { repository(owner: \"o\", name: \"nope\") { id } }".into(),
+            ),
+        };
+        let error = client.request::<Value>(&request).unwrap_err();
+        assert_eq!(ErrorKind::of(&error), ErrorKind::NotFound);
+
+        match error.downcast::<RequestError>() {
+            Ok(RequestError::GraphQL { errors }) => {
+                assert_eq!(errors[0].path, vec![json!("repository")])
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
@@ -2,9 +2,14 @@ use failure::Error;
 use json::Value;
 use serde::de::DeserializeOwned;
 use std::borrow::Cow;
+use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
+use github::concurrency::ConcurrencyLimit;
+use github::tokens::TokenPool;
+use github::transport::Transport;
+use github::utils;
 use github::v4::client::Client;
 use github::v4::client::Request;
 use github::v4::client::RequestType;
@@ -17,8 +22,30 @@ pub struct Github {
 
 impl Github {
     pub fn new(token: &str) -> Result<Self, Error> {
+        Self::with_pool(Arc::new(TokenPool::single(token)), &utils::load_api_url())
+    }
+
+    pub fn with_pool(pool: Arc<TokenPool>, api_url: &str) -> Result<Self, Error> {
+        Ok(Github {
+            client: Client::new(pool, api_url)?,
+        })
+    }
+
+    /// Same as `with_pool`, sharing the bound on requests in flight with other clients
+    pub fn with_concurrency_limit(
+        pool: Arc<TokenPool>,
+        api_url: &str,
+        limit: Arc<ConcurrencyLimit>,
+    ) -> Result<Self, Error> {
+        Ok(Github {
+            client: Client::with_concurrency_limit(pool, api_url, limit)?,
+        })
+    }
+
+    /// Same as `with_pool` over a preconfigured transport, e.g. with a cassette attached
+    pub fn with_transport(pool: Arc<TokenPool>, transport: Transport) -> Result<Self, Error> {
         Ok(Github {
-            client: Client::new(token)?,
+            client: Client::with_transport(pool, transport)?,
         })
     }
 
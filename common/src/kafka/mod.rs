@@ -12,6 +12,24 @@ pub enum Event {
     PRCreated(Repository),
     Notification(Notification),
     PRStatusChange(Repository),
+    /// The failed run is the last record in the history
+    StageFailed(Repository),
+}
+
+impl Event {
+    /// Repository the event is about
+    pub fn repository(&self) -> Option<&Repository> {
+        match self {
+            Event::RepositoryFetched(repo)
+            | Event::RepositoryForked(repo)
+            | Event::ForkDeleted(repo)
+            | Event::RepositoryFormatted(repo)
+            | Event::PRCreated(repo)
+            | Event::PRStatusChange(repo)
+            | Event::StageFailed(repo) => Some(repo),
+            Event::Notification(_) => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,7 +51,11 @@ pub mod topic {
     pub const GITHUB_REQUEST: &str = "rustyrobot.github.request";
     pub const EVENT: &str = "rustyrobot.event";
     pub const GITHUB_STATE: &str = "rustyrobot.github.state";
+    pub const GITHUB_SEEN: &str = "rustyrobot.github.seen";
     pub const FETCHER_STATE: &str = "rustyrobot.fetcher.state";
+    /// Compacted, only the latest snapshot of every repository is kept: the stage history
+    /// can't be rebuilt from the topic and has to be carried inside `Repository`
+    pub const REPOSITORY_HISTORY: &str = "rustyrobot.repository.history";
 }
 
 pub mod group {
@@ -42,4 +64,6 @@ pub mod group {
     pub const FORKER: &str = "rustyrobot.forker";
     pub const FORMATTER: &str = "rustyrobot.formatter";
     pub const PR_ISSUER: &str = "rustyrobot.prissuer";
+    pub const EVENT_HANDLER: &str = "rustyrobot.eventhandler";
+    pub const HISTORY: &str = "rustyrobot.history";
 }
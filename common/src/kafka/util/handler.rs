@@ -8,15 +8,20 @@ use failure::{err_msg, Error};
 use json;
 use serde::{de::DeserializeOwned, Serialize};
 
-use std::cell::RefCell;
 use std::fmt::Debug;
 use std::marker::PhantomData;
-use std::rc::Rc;
 use std::time::Duration;
 
 use kafka::util::producer::ThreadedProducer;
 use shutdown::GracefulShutdownHandle;
 
+/// Consumes the input topic, passing every message to the handler and producing
+/// what it emits into the output topic.
+///
+/// Delivery is at-least-once: outputs are produced as soon as they are emitted, before
+/// the input is committed. If the handler fails with `HandlerError::Internal` after emitting,
+/// the message is handled again after restart and its outputs are produced once more,
+/// so consumers of the output topic must tolerate duplicates.
 pub struct HandlingConsumer<I, O> {
     group: String,
     input_topic: String,
@@ -115,13 +120,31 @@ where
                 }
             }
 
-            // Prepare vector to store responses
-            let responses = Rc::new(RefCell::new(Vec::new()));
-
-            // Pass message to handler
+            // Responses are sent as soon as the handler emits them, long-running handlers
+            // must not hold back their progress until they are finished. They are not
+            // taken back if the handler fails later on, see the delivery note above
             {
-                let responses = responses.clone();
-                let mut callback = move |resp| responses.borrow_mut().push(resp);
+                let mut sent = 0;
+                let mut callback = |resp: O| {
+                    let message_key = if let Some(key) = self.key.as_ref() {
+                        key(&resp)
+                    } else {
+                        let mut base = message.key().unwrap().to_vec();
+                        if sent != 0 {
+                            base.extend(format!("-{}", sent).as_bytes());
+                        }
+                        base
+                    };
+                    sent += 1;
+
+                    if let Some(producer) = producer.as_ref() {
+                        match producer.send_with_key(&message_key, resp) {
+                            Ok(()) => (),
+                            Err(e) => error!("failed to produce message: {}", e),
+                        }
+                    }
+                };
+
                 match (self.handler)(payload, &mut callback) {
                     Ok(_) => trace!("message handled successfully"),
                     Err(HandlerError::Other { error }) => {
@@ -138,26 +161,6 @@ where
                 }
             }
 
-            // Send responses to the output topic
-            for (idx, resp) in responses.borrow().iter().enumerate() {
-                let message_key = if let Some(key) = self.key.as_ref() {
-                    key(resp)
-                } else {
-                    let mut base = message.key().unwrap().to_vec();
-                    if idx != 0 {
-                        base.extend(format!("-{}", idx).as_bytes());
-                    }
-                    base
-                };
-
-                if let Some(producer) = producer.as_ref() {
-                    match producer.send_with_key(&message_key, resp) {
-                        Ok(()) => (),
-                        Err(e) => error!("failed to produce message: {}", e),
-                    }
-                }
-            }
-
             consumer.commit_message(&borrowed_message, CommitMode::Sync)?;
         }
 
@@ -1,3 +1,4 @@
 pub mod handler;
 pub mod producer;
+pub mod seen;
 pub mod state;
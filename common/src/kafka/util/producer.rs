@@ -80,6 +80,10 @@ impl ThreadedProducer {
     {
         self.handle().send_with_key(key, value)
     }
+
+    pub fn send_tombstone(&self, key: impl ToBytes) {
+        self.handle().send_tombstone(key)
+    }
 }
 
 impl ThreadedProducerHandle {
@@ -112,6 +116,21 @@ impl ThreadedProducerHandle {
         debug!("produced message into {}", self.topic);
         Ok(())
     }
+
+    /// Message with no payload, compaction drops the earlier messages with the same key
+    pub fn send_tombstone(&self, key: impl ToBytes) {
+        loop {
+            let record: BaseRecord<_, ()> = BaseRecord::to(&self.topic).key(&key);
+            match self.producer.send(record) {
+                Ok(()) => break,
+                Err((e, _)) => {
+                    warn!("Failed to enqueue, retrying: {}", e);
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+        debug!("produced tombstone into {}", self.topic);
+    }
 }
 
 impl Drop for ThreadedProducer {
@@ -0,0 +1,201 @@
+use chrono::{DateTime, Duration, Utc};
+use failure::{err_msg, Error};
+use json;
+use rdkafka::{
+    consumer::{BaseConsumer, Consumer},
+    error::KafkaError,
+    message::Message,
+    ClientConfig,
+};
+use uuid;
+
+use kafka::util::producer::ThreadedProducer;
+use shutdown::GracefulShutdown;
+
+use std::collections::HashMap;
+use std::str;
+
+/// Time of the last visit of each repository, keyed by `Repository::id`
+#[derive(Debug)]
+pub struct Visits {
+    visits: HashMap<String, DateTime<Utc>>,
+    revisit: Duration,
+}
+
+impl Visits {
+    pub fn new(revisit: Duration) -> Self {
+        Visits {
+            visits: HashMap::new(),
+            revisit,
+        }
+    }
+
+    pub fn last_visit(&self, id: &str) -> Option<DateTime<Utc>> {
+        self.visits.get(id).cloned()
+    }
+
+    /// Whether the repository wasn't visited within the revisit interval
+    pub fn is_due(&self, id: &str, now: DateTime<Utc>) -> bool {
+        match self.last_visit(id) {
+            Some(visited) => now.signed_duration_since(visited) >= self.revisit,
+            None => true,
+        }
+    }
+
+    pub fn visit(&mut self, id: &str, now: DateTime<Utc>) {
+        self.visits.insert(id.to_owned(), now);
+    }
+
+    /// Apply a record of the topic as `SeenIndex::visit` produces it
+    fn restore_record(&mut self, key: Option<&[u8]>, payload: Option<&[u8]>) -> Result<(), Error> {
+        let id = match key.and_then(|key| str::from_utf8(key).ok()) {
+            Some(id) => id,
+            None => return Err(err_msg("visit without a repository id")),
+        };
+        // Tombstones aren't produced, but the topic might have been cleaned up by hand
+        let payload = match payload {
+            Some(payload) => payload,
+            None => return Ok(()),
+        };
+        let visited = json::from_slice::<String>(payload)?;
+        let visited = DateTime::parse_from_rfc3339(&visited)?;
+        self.visit(id, visited.with_timezone(&Utc));
+        Ok(())
+    }
+}
+
+/// `Visits` kept in a compacted topic, a single record per visit.
+///
+/// Unlike `StateHandler`, a visit doesn't resend the whole index, so it stays cheap
+/// with millions of repositories.
+pub struct SeenIndex {
+    visits: Visits,
+    topic: String,
+    producer: ThreadedProducer,
+    shutdown: GracefulShutdown,
+}
+
+impl SeenIndex {
+    pub fn new(topic: impl AsRef<str>, revisit: Duration) -> Result<Self, Error> {
+        let topic = topic.as_ref().to_owned();
+        let shutdown = GracefulShutdown::new();
+        let producer = ThreadedProducer::new(&topic, shutdown.thread_handle())?;
+        let mut index = SeenIndex {
+            visits: Visits::new(revisit),
+            topic,
+            producer,
+            shutdown,
+        };
+        index.restore()?;
+        Ok(index)
+    }
+
+    fn restore(&mut self) -> Result<(), Error> {
+        let group = format!("{}", uuid::Uuid::new_v4());
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", "127.0.0.1:9092")
+            .set("group.id", &group)
+            .set("enable.partition.eof", "true")
+            .set("session.timeout.ms", "6000")
+            .set("enable.auto.commit", "true")
+            .set("auto.offset.reset", "earliest")
+            .create()?;
+
+        consumer.subscribe(&[self.topic.as_ref()])?;
+
+        for message in &consumer {
+            let message = match message {
+                Ok(message) => message,
+                Err(KafkaError::PartitionEOF(_)) => break,
+                Err(e) => Err(e)?,
+            };
+
+            if let Err(e) = self.visits.restore_record(message.key(), message.payload()) {
+                warn!("invalid visit in {}: {}", self.topic, e);
+            }
+        }
+
+        info!(
+            "restored {} visits from {}",
+            self.visits.visits.len(),
+            self.topic
+        );
+        Ok(())
+    }
+
+    pub fn last_visit(&self, id: &str) -> Option<DateTime<Utc>> {
+        self.visits.last_visit(id)
+    }
+
+    /// Whether the repository wasn't visited within the revisit interval
+    pub fn is_due(&self, id: &str, now: DateTime<Utc>) -> bool {
+        self.visits.is_due(id, now)
+    }
+
+    pub fn visit(&mut self, id: &str, now: DateTime<Utc>) -> Result<(), Error> {
+        self.visits.visit(id, now);
+        self.producer.send_with_key(id.to_owned(), now.to_rfc3339())
+    }
+}
+
+impl Drop for SeenIndex {
+    fn drop(&mut self) {
+        self.shutdown.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revisit_interval() {
+        let now = Utc::now();
+        let mut visits = Visits::new(Duration::hours(24));
+        assert!(visits.is_due("MDEwOlJlcG9zaXRvcnkx", now));
+
+        visits.visit("MDEwOlJlcG9zaXRvcnkx", now);
+        assert_eq!(visits.last_visit("MDEwOlJlcG9zaXRvcnkx"), Some(now));
+        assert!(!visits.is_due("MDEwOlJlcG9zaXRvcnkx", now + Duration::hours(23)));
+        assert!(visits.is_due("MDEwOlJlcG9zaXRvcnkx", now + Duration::hours(24)));
+        assert!(visits.is_due("MDEwOlJlcG9zaXRvcnky", now));
+
+        // Zero interval disables the deduplication
+        let mut visits = Visits::new(Duration::zero());
+        visits.visit("MDEwOlJlcG9zaXRvcnkx", now);
+        assert!(visits.is_due("MDEwOlJlcG9zaXRvcnkx", now));
+    }
+
+    #[test]
+    fn restore_visits() {
+        let visited = DateTime::parse_from_rfc3339("2018-08-10T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        // As produced by `SeenIndex::visit`
+        let record = json::to_vec(&visited.to_rfc3339()).unwrap();
+
+        let mut visits = Visits::new(Duration::hours(24));
+        visits
+            .restore_record(Some(b"MDEwOlJlcG9zaXRvcnkx"), Some(&record))
+            .unwrap();
+        assert_eq!(visits.last_visit("MDEwOlJlcG9zaXRvcnkx"), Some(visited));
+        assert!(!visits.is_due("MDEwOlJlcG9zaXRvcnkx", visited + Duration::hours(1)));
+
+        // Later visits of the same repository replace the earlier ones
+        let revisited = visited + Duration::days(2);
+        let record = json::to_vec(&revisited.to_rfc3339()).unwrap();
+        visits
+            .restore_record(Some(b"MDEwOlJlcG9zaXRvcnkx"), Some(&record))
+            .unwrap();
+        assert_eq!(visits.last_visit("MDEwOlJlcG9zaXRvcnkx"), Some(revisited));
+
+        visits
+            .restore_record(Some(b"MDEwOlJlcG9zaXRvcnky"), None)
+            .unwrap();
+        assert!(visits.is_due("MDEwOlJlcG9zaXRvcnky", revisited));
+        assert!(visits.restore_record(None, Some(&record)).is_err());
+        assert!(visits
+            .restore_record(Some(b"MDEwOlJlcG9zaXRvcnky"), Some(b"\"yesterday\""))
+            .is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use rdkafka::{
     consumer::{BaseConsumer, Consumer},
     error::KafkaError,
-    message::{BorrowedMessage, Message},
+    message::Message,
     ClientConfig,
 };
 
@@ -66,9 +66,8 @@ impl StateHandler {
                 }
                 Err(e) => Err(e)?,
             };
-            let (key, value) = message.into_state_change()?;
-            debug!("restoring state from {}: {} => {}", self.topic, key, value);
-            self.old.insert(key, value);
+            debug!("restoring state from {}", self.topic);
+            restore_record(&mut self.old, message.key(), message.payload())?;
         }
 
         self.new = self.old.clone();
@@ -94,6 +93,10 @@ impl StateHandler {
             }
         }
 
+        for key in self.old.keys().filter(|key| !self.new.contains_key(*key)) {
+            self.producer.send_tombstone(key.clone());
+        }
+
         self.old = self.new.clone();
         debug!("state sync finished");
         Ok(())
@@ -137,6 +140,14 @@ impl StateHandler {
         self.sync()
     }
 
+    /// Forget the key, the next sync produces a tombstone so it's gone from the topic as well
+    pub fn remove<S>(&mut self, key: S)
+    where
+        S: AsRef<str>,
+    {
+        self.new.remove(key.as_ref());
+    }
+
     pub fn increment<S>(&mut self, key: S)
     where
         S: AsRef<str>,
@@ -203,29 +214,39 @@ where
     }
 }
 
-trait IntoStateChange {
-    fn into_state_change(&self) -> Result<StateChange, Error>;
-}
+/// Apply a single record of the state topic, a record without payload is a tombstone of a
+/// removed key
+fn restore_record(
+    state: &mut State,
+    key: Option<&[u8]>,
+    payload: Option<&[u8]>,
+) -> Result<(), Error> {
+    if payload.is_none() {
+        if let Some(key) = key.and_then(|key| str::from_utf8(key).ok()) {
+            trace!("{} is removed", key);
+            state.remove(key);
+        }
+        return Ok(());
+    }
 
-trait FromStateChange: Sized {
-    fn from_state_change(StateChange) -> Result<Self, Error>;
+    let (key, value) = state_change(key, payload)?;
+    trace!("{} => {}", key, value);
+    state.insert(key, value);
+    Ok(())
 }
 
-impl<'a> IntoStateChange for BorrowedMessage<'a> {
-    fn into_state_change(&self) -> Result<StateChange, Error> {
-        let key = self
-            .key()
-            .ok_or_else(|| err_msg("Missing key on state change"))?;
+fn state_change(key: Option<&[u8]>, payload: Option<&[u8]>) -> Result<StateChange, Error> {
+    let key = key.ok_or_else(|| err_msg("Missing key on state change"))?;
+    let value = payload.ok_or_else(|| err_msg("Empty state change"))?;
 
-        let value = self
-            .payload()
-            .ok_or_else(|| err_msg("Empty state change"))?;
+    let key = str::from_utf8(key)?;
+    let value: Value = json::from_slice(value)?;
 
-        let key = str::from_utf8(key)?;
-        let value: Value = json::from_slice(value)?;
+    Ok((key.to_owned(), value))
+}
 
-        Ok((key.to_owned(), value))
-    }
+trait FromStateChange: Sized {
+    fn from_state_change(StateChange) -> Result<Self, Error>;
 }
 
 pub type KeyValueBytes = (Vec<u8>, Vec<u8>);
@@ -275,9 +296,20 @@ impl<T: FromJsonValue> FromJsonValue for Vec<T> {
     }
 }
 
+/// `null` is read as `None`, e.g. a key that was reset
+impl<T: FromJsonValue> FromJsonValue for Option<T> {
+    fn from_json_value(value: Value) -> Self {
+        if value.is_null() {
+            None
+        } else {
+            Some(T::from_json_value(value))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::StateHandler;
+    use super::{restore_record, State, StateHandler};
     use env_logger;
     use json::Value;
     use uuid::Uuid;
@@ -312,6 +344,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn removed_keys_are_not_restored() {
+        env_logger::try_init();
+        let topic = "rustyrobot.test.state.removed_keys_are_not_restored";
+        let mut state = StateHandler::new(topic).unwrap();
+        state.set("kept", 1);
+        state.set("removed", 2);
+        state.sync().unwrap();
+        state.remove("removed");
+        state.sync().unwrap();
+
+        let mut restored = StateHandler::new(topic).unwrap();
+        restored.restore().unwrap();
+        assert_eq!(restored.get::<_, i64>("kept"), 1);
+        assert_eq!(restored.get_or_default::<_, Option<i64>>("removed"), None);
+    }
+
+    #[test]
+    fn tombstones_remove_restored_keys() {
+        let mut state = State::new();
+        restore_record(&mut state, Some(b"kept"), Some(b"1")).unwrap();
+        restore_record(&mut state, Some(b"removed"), Some(b"\"value\"")).unwrap();
+        restore_record(&mut state, Some(b"removed"), None).unwrap();
+        // A tombstone of a key that was never restored
+        restore_record(&mut state, Some(b"unknown"), None).unwrap();
+        assert_eq!(state.get("kept"), Some(&Value::from(1)));
+        assert!(!state.contains_key("removed"));
+        assert!(!state.contains_key("unknown"));
+
+        // Set again after the removal
+        restore_record(&mut state, Some(b"removed"), Some(b"2")).unwrap();
+        assert_eq!(state.get("removed"), Some(&Value::from(2)));
+
+        assert!(restore_record(&mut state, None, Some(b"1")).is_err());
+        assert!(restore_record(&mut state, Some(b"invalid"), Some(b"{")).is_err());
+    }
+
     #[test]
     fn delta() {
         let mut state = StateHandler::new("rustyrobot.test.state.save_and_restore").unwrap();
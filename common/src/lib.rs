@@ -11,18 +11,25 @@ extern crate serde_derive;
 extern crate log;
 #[macro_use]
 extern crate lazy_static;
+extern crate base64;
 extern crate chrono;
 extern crate dotenv;
 extern crate env_logger;
-extern crate github_gql as gh4;
-extern crate github_rs as gh3;
+extern crate openssl;
 extern crate rdkafka;
+extern crate reqwest;
 extern crate serde;
+#[macro_use]
 extern crate serde_json as json;
 extern crate shell_escape;
 extern crate threadpool;
 extern crate uuid;
 
+#[cfg(test)]
+extern crate fake_github;
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck;
 #[cfg(test)]
 extern crate tempfile;
 
@@ -38,9 +45,9 @@ macro_rules! json_get_chain {
 
 #[macro_use]
 mod macros;
-mod error_chain_failure_interop;
 pub mod github;
 pub mod kafka;
+pub mod policy;
 pub mod search;
 pub mod shutdown;
 pub mod types;
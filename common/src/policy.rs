@@ -0,0 +1,193 @@
+use chrono::{DateTime, Duration, Utc};
+use dotenv;
+use failure::Error;
+use std::env;
+use types::Repository;
+
+/// Which repositories the forker and pr-issuer take on.
+///
+/// Only the fields known about the repository are checked, the optional ones are filled in
+/// when the fetch query requests them.
+#[derive(Clone, Debug, Default)]
+pub struct Policy {
+    pub min_stars: u64,
+    /// Needs `RepositoryField::License` requested, the license is unknown otherwise
+    pub require_license: bool,
+    /// Repositories with any of these topics are left alone
+    pub excluded_topics: Vec<String>,
+    /// Repositories not pushed to for longer than this are left alone
+    pub max_inactivity: Option<Duration>,
+}
+
+/// Reason to leave a repository alone
+#[derive(Debug, Fail, PartialEq)]
+pub enum Skip {
+    #[fail(display = "repository is read-only")]
+    ReadOnly,
+    #[fail(display = "{} stars, at least {} required", stars, min_stars)]
+    TooFewStars { stars: u64, min_stars: u64 },
+    #[fail(display = "repository has no license")]
+    Unlicensed,
+    #[fail(display = "repository has excluded topic {:?}", topic)]
+    ExcludedTopic { topic: String },
+    #[fail(display = "last pushed at {}", pushed_at)]
+    Inactive { pushed_at: DateTime<Utc> },
+}
+
+impl Policy {
+    /// `POLICY_MIN_STARS`, `POLICY_REQUIRE_LICENSE`, comma-separated `POLICY_EXCLUDED_TOPICS`
+    /// and `POLICY_MAX_INACTIVE_DAYS`, every check but the read-only one is off if unset
+    pub fn from_env() -> Result<Self, Error> {
+        let mut policy = Policy::default();
+        if let Ok(min_stars) = load_env("POLICY_MIN_STARS") {
+            policy.min_stars = min_stars.parse()?;
+        }
+        if let Ok(require_license) = load_env("POLICY_REQUIRE_LICENSE") {
+            policy.require_license = require_license.parse()?;
+        }
+        if let Ok(topics) = load_env("POLICY_EXCLUDED_TOPICS") {
+            policy.excluded_topics = topics
+                .split(',')
+                .map(|topic| topic.trim().to_lowercase())
+                .filter(|topic| !topic.is_empty())
+                .collect();
+        }
+        if let Ok(days) = load_env("POLICY_MAX_INACTIVE_DAYS") {
+            policy.max_inactivity = Some(Duration::days(days.parse()?));
+        }
+        Ok(policy)
+    }
+
+    pub fn check(&self, repo: &Repository, now: DateTime<Utc>) -> Result<(), Skip> {
+        // Neither would accept the PRs
+        if repo.is_archived || repo.is_locked == Some(true) {
+            return Err(Skip::ReadOnly);
+        }
+
+        if let Some(stars) = repo.stars {
+            if stars < self.min_stars {
+                return Err(Skip::TooFewStars {
+                    stars,
+                    min_stars: self.min_stars,
+                });
+            }
+        }
+
+        if self.require_license && repo.license.is_none() {
+            return Err(Skip::Unlicensed);
+        }
+
+        if let Some(ref topics) = repo.topics {
+            let excluded = topics
+                .iter()
+                .find(|topic| self.excluded_topics.contains(&topic.to_lowercase()));
+            if let Some(topic) = excluded {
+                return Err(Skip::ExcludedTopic {
+                    topic: topic.clone(),
+                });
+            }
+        }
+
+        if let (Some(max_inactivity), Some(pushed_at)) = (self.max_inactivity, repo.pushed_at) {
+            if now.signed_duration_since(pushed_at) > max_inactivity {
+                return Err(Skip::Inactive { pushed_at });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn load_env(key: &str) -> Result<String, Error> {
+    Ok(dotenv::var(key).or_else(|_| env::var(key))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::License;
+
+    fn repository() -> Repository {
+        json::from_value(json!({
+            "id": "MDEwOlJlcG9zaXRvcnkx",
+            "name_with_owner": "owner/repo",
+            "description": null,
+            "ssh_url": "git@github.com:owner/repo.git",
+            "url": "https://github.com/owner/repo",
+            "default_branch": "master",
+            "created_at": "2018-08-10T00:00:00Z",
+            "parent": null,
+            "has_issues_enabled": true,
+            "is_fork": false,
+            "stats": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn unknown_fields_pass() {
+        let policy = Policy {
+            min_stars: 10,
+            require_license: false,
+            excluded_topics: vec!["deprecated".into()],
+            max_inactivity: Some(Duration::days(365)),
+        };
+        assert_eq!(policy.check(&repository(), Utc::now()), Ok(()));
+
+        let mut locked = repository();
+        locked.is_locked = Some(true);
+        assert_eq!(policy.check(&locked, Utc::now()), Err(Skip::ReadOnly));
+    }
+
+    #[test]
+    fn known_fields_are_checked() {
+        let now = Utc::now();
+        let policy = Policy {
+            min_stars: 10,
+            require_license: true,
+            excluded_topics: vec!["deprecated".into()],
+            max_inactivity: Some(Duration::days(365)),
+        };
+
+        let mut repo = repository();
+        repo.stars = Some(10);
+        repo.topics = Some(vec!["cli".into()]);
+        repo.license = Some(License {
+            key: "mit".into(),
+            name: None,
+            spdx_id: None,
+        });
+        repo.pushed_at = Some(now - Duration::days(30));
+        assert_eq!(policy.check(&repo, now), Ok(()));
+
+        let mut unpopular = repo.clone();
+        unpopular.stars = Some(9);
+        assert_eq!(
+            policy.check(&unpopular, now),
+            Err(Skip::TooFewStars {
+                stars: 9,
+                min_stars: 10,
+            })
+        );
+
+        let mut unlicensed = repo.clone();
+        unlicensed.license = None;
+        assert_eq!(policy.check(&unlicensed, now), Err(Skip::Unlicensed));
+
+        let mut deprecated = repo.clone();
+        deprecated.topics = Some(vec!["cli".into(), "Deprecated".into()]);
+        assert_eq!(
+            policy.check(&deprecated, now),
+            Err(Skip::ExcludedTopic {
+                topic: "Deprecated".into(),
+            })
+        );
+
+        let mut abandoned = repo.clone();
+        abandoned.pushed_at = Some(now - Duration::days(400));
+        assert!(match policy.check(&abandoned, now) {
+            Err(Skip::Inactive { .. }) => true,
+            _ => false,
+        });
+    }
+}
@@ -0,0 +1,146 @@
+/// Optional groups of repository fields, requested on top of the ones always fetched
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RepositoryField {
+    Stars,
+    License,
+    Topics,
+    /// Primary language and the size of the code in each language
+    Languages,
+    LatestPush,
+    /// Presence of `rustfmt.toml` or `.rustfmt.toml` in the default branch
+    RustfmtConfig,
+}
+
+impl RepositoryField {
+    pub fn all() -> &'static [RepositoryField] {
+        &[
+            RepositoryField::Stars,
+            RepositoryField::License,
+            RepositoryField::Topics,
+            RepositoryField::Languages,
+            RepositoryField::LatestPush,
+            RepositoryField::RustfmtConfig,
+        ]
+    }
+
+    fn fragment(self) -> &'static str {
+        match self {
+            RepositoryField::Stars => "stargazers { totalCount }",
+            RepositoryField::License => "licenseInfo { key name spdxId }",
+            RepositoryField::Topics => "repositoryTopics(first: 20) { nodes { topic { name } } }",
+            RepositoryField::Languages => {
+                "primaryLanguage { name } \
+                 languages(first: 10, orderBy: { field: SIZE, direction: DESC }) { \
+                 totalSize edges { size node { name } } }"
+            }
+            RepositoryField::LatestPush => "pushedAt",
+            RepositoryField::RustfmtConfig => {
+                "rustfmtToml: object(expression: \"HEAD:rustfmt.toml\") { id } \
+                 dotRustfmtToml: object(expression: \"HEAD:.rustfmt.toml\") { id }"
+            }
+        }
+    }
+}
+
+/// Set of the optional fields to request, empty by default
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FieldSet {
+    fields: Vec<RepositoryField>,
+}
+
+impl FieldSet {
+    pub fn new() -> Self {
+        FieldSet::default()
+    }
+
+    pub fn all() -> Self {
+        RepositoryField::all()
+            .iter()
+            .fold(FieldSet::new(), |set, field| set.with(*field))
+    }
+
+    pub fn with(mut self, field: RepositoryField) -> Self {
+        if !self.contains(field) {
+            self.fields.push(field);
+            self.fields.sort();
+        }
+        self
+    }
+
+    pub fn contains(&self, field: RepositoryField) -> bool {
+        self.fields.contains(&field)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Selection to put into the `... on Repository` fragment
+    pub fn to_selection(&self) -> String {
+        let fragments: Vec<&str> = self.fields.iter().map(|field| field.fragment()).collect();
+        fragments.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{FakeGithub, FakeRepo};
+    use github::tokens::TokenPool;
+    use github::v4::Github;
+    use search::query::{Query, SearchFor};
+    use search::search;
+    use std::sync::Arc;
+    use types::repo::{self, PrimaryLanguage};
+
+    #[test]
+    fn requested_fields_land_on_repository() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(
+            FakeRepo::new("owner/repo")
+                .stars(42)
+                .topic("cli")
+                .license("MIT")
+                .size(3)
+                .file(".rustfmt.toml"),
+        );
+
+        let gh = Github::with_pool(Arc::new(TokenPool::single("bot")), &fake.url()).unwrap();
+        let query = Query::builder()
+            .search_for(SearchFor::Repository)
+            .fields(FieldSet::all())
+            .build()
+            .unwrap();
+        let result = search::<repo::v4::Repository>(&gh, query).unwrap();
+
+        let body = String::from_utf8(fake.requests().last().unwrap().body.clone()).unwrap();
+        assert!(body.contains("rustfmtToml: object(expression:"));
+
+        let node = &result.nodes[0];
+        assert_eq!(node.stargazers.as_ref().unwrap().total_count, 42);
+        assert_eq!(node.topics(), Some(vec!["cli"]));
+        assert_eq!(node.license_info.as_ref().unwrap().key, "mit");
+        assert!(node.pushed_at.is_some());
+
+        let repo = repo::Repository::from(node.clone());
+        assert_eq!(repo.has_rustfmt_config, Some(true));
+        assert_eq!(
+            repo.primary_language,
+            Some(PrimaryLanguage {
+                name: "Rust".into(),
+                bytes: Some(3 * 1024),
+            })
+        );
+    }
+
+    #[test]
+    fn selection_follows_the_set() {
+        assert_eq!(FieldSet::new().to_selection(), "");
+
+        let set = FieldSet::new()
+            .with(RepositoryField::LatestPush)
+            .with(RepositoryField::Stars)
+            .with(RepositoryField::LatestPush);
+        assert_eq!(set.to_selection(), "stargazers { totalCount } pushedAt");
+    }
+}
@@ -0,0 +1,153 @@
+use failure::Error;
+
+use std::collections::VecDeque;
+
+use github::v4::Github;
+use search::query::IncompleteQuery;
+use search::{search, NodeType};
+
+/// Nodes matched by the query, the next page is requested once the current one is drained.
+///
+/// `cursor` points past the nodes already yielded as whole pages: storing it after handling
+/// the nodes and resuming from it later skips no results, though the nodes of a partially
+/// handled page are yielded again.
+pub struct SearchIter<'g, N> {
+    gh: &'g Github,
+    query: IncompleteQuery,
+    cursor: Option<String>,
+    /// End cursor of the page being drained
+    page_end: Option<String>,
+    nodes: VecDeque<N>,
+    total_count: Option<u64>,
+    has_next_page: bool,
+}
+
+impl<'g, N> SearchIter<'g, N>
+where
+    N: NodeType,
+{
+    pub fn new(gh: &'g Github, query: IncompleteQuery) -> Self {
+        SearchIter {
+            gh,
+            query,
+            cursor: None,
+            page_end: None,
+            nodes: VecDeque::new(),
+            total_count: None,
+            has_next_page: true,
+        }
+    }
+
+    /// Continue the search after a cursor previously returned by `cursor`
+    pub fn resume(gh: &'g Github, query: IncompleteQuery, cursor: impl Into<String>) -> Self {
+        SearchIter {
+            cursor: Some(cursor.into()),
+            ..Self::new(gh, query)
+        }
+    }
+
+    pub fn cursor(&self) -> Option<&str> {
+        self.cursor.as_ref().map(String::as_str)
+    }
+
+    /// Number of matches reported by GitHub, known after the first page is fetched
+    pub fn total_count(&self) -> Option<u64> {
+        self.total_count
+    }
+
+    fn fetch(&mut self) -> Result<(), Error> {
+        let query = match self.cursor.clone() {
+            Some(cursor) => self.query.clone().after(cursor),
+            None => self.query.clone(),
+        };
+
+        let result = search::<N>(self.gh, query.build()?)?;
+        debug!("fetched page of {} nodes", result.nodes.len());
+
+        self.total_count = Some(result.total_count());
+        self.has_next_page = result.page_info.has_next_page;
+        self.page_end = result.page_info.end_cursor;
+        self.nodes.extend(result.nodes);
+
+        // Empty page moves the cursor right away, there is nothing to hand out
+        if self.nodes.is_empty() {
+            self.advance();
+        }
+
+        Ok(())
+    }
+
+    fn advance(&mut self) {
+        if let Some(page_end) = self.page_end.take() {
+            self.cursor = Some(page_end);
+        }
+    }
+}
+
+impl<'g, N> Iterator for SearchIter<'g, N>
+where
+    N: NodeType,
+{
+    type Item = Result<N, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(node) = self.nodes.pop_front() {
+                if self.nodes.is_empty() {
+                    self.advance();
+                }
+                return Some(Ok(node));
+            }
+
+            if !self.has_next_page {
+                return None;
+            }
+
+            // Failed page is requested again on the next call
+            if let Err(e) = self.fetch() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{FakeGithub, FakeRepo};
+    use github::tokens::TokenPool;
+    use json::Value;
+    use search::query::{Query, SearchFor};
+    use std::sync::Arc;
+
+    fn name(node: Result<Value, Error>) -> String {
+        node.unwrap()["nameWithOwner"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn resume_from_cursor() {
+        let fake = FakeGithub::start().unwrap();
+        for i in 0..5 {
+            fake.add_repo(FakeRepo::new(format!("owner/repo{}", i)));
+        }
+
+        let gh = Github::with_pool(Arc::new(TokenPool::single("bot")), &fake.url()).unwrap();
+        let query = Query::builder().search_for(SearchFor::Repository).count(2);
+
+        let mut iter = SearchIter::<Value>::new(&gh, query.clone());
+        assert_eq!(name(iter.next().unwrap()), "owner/repo0");
+        assert_eq!(iter.cursor(), None);
+        assert_eq!(name(iter.next().unwrap()), "owner/repo1");
+        assert_eq!(iter.total_count(), Some(5));
+
+        // Crash in the middle of the second page
+        assert_eq!(name(iter.next().unwrap()), "owner/repo2");
+        let cursor = iter.cursor().unwrap().to_string();
+        drop(iter);
+
+        let resumed: Vec<String> = SearchIter::<Value>::resume(&gh, query, cursor)
+            .map(name)
+            .collect();
+        assert_eq!(resumed, vec!["owner/repo2", "owner/repo3", "owner/repo4"]);
+    }
+}
@@ -1,6 +1,11 @@
+pub mod fields;
+mod iter;
 pub mod query;
+pub mod window;
 
-use self::query::Query;
+pub use self::iter::SearchIter;
+
+use self::query::{Query, QueryBuilderError, SearchFor};
 use failure::Error;
 use github::v4::Github;
 use std::fmt::Debug;
@@ -22,16 +27,42 @@ pub struct PageInfo {
     pub has_next_page: bool,
 }
 
+/// Raw nodes, e.g. when only the counts are of interest
+impl NodeType for Value {
+    fn from_value(json: Value) -> Result<Self, Error> {
+        Ok(json)
+    }
+}
+
+/// Only the count of the searched type is present in the result
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchResult<N> {
     pub page_info: PageInfo,
+    #[serde(default)]
     pub repository_count: u64,
+    #[serde(default)]
+    pub user_count: u64,
+    #[serde(default)]
+    pub issue_count: u64,
     pub nodes: Vec<N>,
 }
 
+impl<N> SearchResult<N> {
+    /// Number of matches, including the ones past the search cap
+    pub fn total_count(&self) -> u64 {
+        self.repository_count + self.user_count + self.issue_count
+    }
+}
+
 static REPO_QUERY: &'static str =
     include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/res/repo_query.gql"));
+static USER_QUERY: &'static str =
+    include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/res/user_query.gql"));
+static ISSUE_QUERY: &'static str =
+    include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/res/issue_query.gql"));
+static PR_QUERY: &'static str =
+    include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/res/pr_query.gql"));
 
 pub fn search<N>(gh: &Github, query: Query) -> Result<SearchResult<N>, Error>
 where
@@ -40,10 +71,19 @@ where
     info!("performing search by {:?}", query);
 
     // Build query
-    let search_args = query.to_arg_list();
-    let query = String::from(REPO_QUERY)
+    let search_args = query.to_arg_list()?;
+    let template = match query.search_for {
+        SearchFor::Repository => REPO_QUERY,
+        SearchFor::User => USER_QUERY,
+        SearchFor::Issue => ISSUE_QUERY,
+        SearchFor::PullRequest => PR_QUERY,
+        SearchFor::Undefined => raise!(QueryBuilderError::UndefinedTarget),
+    };
+    // Optional fields only apply to repositories, other templates have no placeholder
+    let query = String::from(template)
         .uglify()
-        .replace("$ARGS$", &search_args);
+        .replace("$ARGS$", &search_args)
+        .replace("$FIELDS$", &query.fields.to_selection());
 
     trace!("search {}", query);
 
@@ -76,3 +116,43 @@ impl Uglify for String {
         buffer
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{FakeGithub, FakePull, FakeRepo};
+    use github::tokens::TokenPool;
+    use std::sync::Arc;
+    use types::pr::{PullRequest, PullRequestState};
+    use types::user::User;
+
+    #[test]
+    fn search_own_pull_requests() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo"));
+        fake.add_pull(FakePull::new("owner/repo", "rustyrobot:fmt"));
+        fake.add_pull(FakePull::new("owner/repo", "rustyrobot:fix"));
+        fake.state().pull_mut("owner/repo", 2).unwrap().close();
+
+        let gh = Github::with_pool(Arc::new(TokenPool::single("bot")), &fake.url()).unwrap();
+        let query = Query::builder()
+            .search_for(SearchFor::PullRequest)
+            .raw_query("author:rustyrobot is:open")
+            .build()
+            .unwrap();
+        let result = search::<PullRequest>(&gh, query).unwrap();
+
+        assert_eq!(result.total_count(), 1);
+        assert_eq!(result.nodes[0].number, 1);
+        assert_eq!(result.nodes[0].state, PullRequestState::Open);
+        assert_eq!(result.nodes[0].repository.name_with_owner, "owner/repo");
+
+        let query = Query::builder()
+            .search_for(SearchFor::User)
+            .raw_query("owner")
+            .build()
+            .unwrap();
+        let result = search::<User>(&gh, query).unwrap();
+        assert_eq!(result.nodes[0].login, "owner");
+    }
+}
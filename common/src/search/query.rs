@@ -1,4 +1,13 @@
+use chrono::NaiveDate;
 use failure::Error;
+use serde::{Deserialize, Deserializer};
+
+use search::fields::{FieldSet, RepositoryField};
+
+use std::borrow::Cow;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
 
 static ARG_LIST_DELIMITER: &str = ", ";
 static QUERY_DELIMITER: &str = " ";
@@ -12,6 +21,14 @@ pub trait AsQuerySegment {
     fn as_query_segment(&self) -> &'static str;
 }
 
+impl Lang {
+    pub fn name(self) -> &'static str {
+        match self {
+            Lang::Rust => "Rust",
+        }
+    }
+}
+
 impl AsQuerySegment for Lang {
     fn as_query_segment(&self) -> &'static str {
         match self {
@@ -20,17 +37,32 @@ impl AsQuerySegment for Lang {
     }
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SearchFor {
     Repository,
+    User,
+    Issue,
+    PullRequest,
     Undefined,
 }
 
 impl SearchFor {
-    fn type_str(self) -> &'static str {
-        match self {
+    fn type_str(self) -> Result<&'static str, Error> {
+        let type_str = match self {
             SearchFor::Repository => "REPOSITORY",
-            SearchFor::Undefined => panic!("Search target is not defined"),
+            SearchFor::User => "USER",
+            // GitHub searches issues and pull requests together, told apart by `is:`
+            SearchFor::Issue | SearchFor::PullRequest => "ISSUE",
+            SearchFor::Undefined => raise!(QueryBuilderError::UndefinedTarget),
+        };
+        Ok(type_str)
+    }
+
+    fn is_qualifier(self) -> Option<&'static str> {
+        match self {
+            SearchFor::Issue => Some("is:issue"),
+            SearchFor::PullRequest => Some("is:pr"),
+            _ => None,
         }
     }
 }
@@ -38,6 +70,8 @@ impl SearchFor {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Query {
     pub search_for: SearchFor,
+    #[serde(default)]
+    pub fields: FieldSet,
     query: Option<String>,
     count: u8,
     after: Option<String>,
@@ -50,72 +84,487 @@ impl Query {
 }
 
 impl Query {
-    pub fn to_arg_list(&self) -> String {
+    pub fn to_arg_list(&self) -> Result<String, Error> {
         let mut list = format!(
             "type: {}, first: {}",
-            self.search_for.type_str(),
+            self.search_for.type_str()?,
             self.count
         );
 
         if let Some(ref query) = self.query {
             list.push_str(ARG_LIST_DELIMITER);
-            list.push_str("query: \\\"");
-            list.push_str(&query);
-            list.push_str("\\\"")
+            list.push_str("query: ");
+            list.push_str(&quote(query));
         }
 
         if let Some(ref after) = self.after {
             list.push_str(ARG_LIST_DELIMITER);
-            list.push_str("after: \\\"");
-            list.push_str(&after);
-            list.push_str("\\\"")
+            list.push_str("after: ");
+            list.push_str(&quote(after));
         }
 
-        list
+        Ok(list)
     }
 }
 
+/// GraphQL string literal
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
 #[derive(Fail, Debug)]
-enum QueryBuilderError {
+pub(super) enum QueryBuilderError {
     #[fail(display = "count must be in 1..100, got {}", count)]
     InvalidCount { count: u8 },
+    #[fail(display = "search target is not defined")]
+    UndefinedTarget,
+    #[fail(display = "invalid qualifier {}: {}", qualifier, reason)]
+    InvalidQualifier {
+        qualifier: String,
+        reason: &'static str,
+    },
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Fail, Debug)]
+enum QueryParseError {
+    #[fail(display = "invalid value of {}: {:?}", key, value)]
+    InvalidValue { key: String, value: String },
+    #[fail(display = "unterminated quote in {:?}", query)]
+    UnterminatedQuote { query: String },
+}
+
+/// Inclusive bounds of the numeric and date qualifiers
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Bounds<T> {
+    Exactly(T),
+    AtLeast(T),
+    AtMost(T),
+    Between(T, T),
+}
+
+pub trait BoundValue: PartialOrd + Sized {
+    fn render(&self) -> String;
+    fn parse(value: &str) -> Option<Self>;
+    fn succ(&self) -> Option<Self>;
+    fn pred(&self) -> Option<Self>;
+}
+
+impl BoundValue for u64 {
+    fn render(&self) -> String {
+        self.to_string()
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+
+    fn succ(&self) -> Option<Self> {
+        self.checked_add(1)
+    }
+
+    fn pred(&self) -> Option<Self> {
+        self.checked_sub(1)
+    }
+}
+
+impl BoundValue for NaiveDate {
+    fn render(&self) -> String {
+        self.format("%Y-%m-%d").to_string()
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+    }
+
+    fn succ(&self) -> Option<Self> {
+        self.succ_opt()
+    }
+
+    fn pred(&self) -> Option<Self> {
+        self.pred_opt()
+    }
+}
+
+impl<T: BoundValue> Bounds<T> {
+    /// Parse `n`, `>n`, `>=n`, `<n`, `<=n`, `a..b`, `a..*` and `*..b`
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Some(idx) = value.find("..") {
+            let (from, to) = (&value[..idx], &value[idx + 2..]);
+            return match (from, to) {
+                ("*", "*") => None,
+                ("*", to) => T::parse(to).map(Bounds::AtMost),
+                (from, "*") => T::parse(from).map(Bounds::AtLeast),
+                (from, to) => Some(Bounds::Between(T::parse(from)?, T::parse(to)?)),
+            };
+        }
+
+        if value.starts_with(">=") {
+            T::parse(&value[2..]).map(Bounds::AtLeast)
+        } else if value.starts_with("<=") {
+            T::parse(&value[2..]).map(Bounds::AtMost)
+        } else if value.starts_with('>') {
+            T::parse(&value[1..])?.succ().map(Bounds::AtLeast)
+        } else if value.starts_with('<') {
+            T::parse(&value[1..])?.pred().map(Bounds::AtMost)
+        } else {
+            T::parse(value).map(Bounds::Exactly)
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        match self {
+            Bounds::Between(from, to) => from <= to,
+            _ => true,
+        }
+    }
+}
+
+impl<T: BoundValue> fmt::Display for Bounds<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Bounds::Exactly(value) => write!(f, "{}", value.render()),
+            Bounds::AtLeast(value) => write!(f, ">={}", value.render()),
+            Bounds::AtMost(value) => write!(f, "<={}", value.render()),
+            Bounds::Between(from, to) => write!(f, "{}..{}", from.render(), to.render()),
+        }
+    }
+}
+
+/// Fields matched by the search terms
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchIn {
+    Name,
+    Description,
+    Readme,
+}
+
+impl SearchIn {
+    fn as_str(self) -> &'static str {
+        match self {
+            SearchIn::Name => "name",
+            SearchIn::Description => "description",
+            SearchIn::Readme => "readme",
+        }
+    }
+}
+
+/// Forks are excluded from the results unless asked for
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ForkFilter {
+    Include,
+    Only,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Qualifier {
+    Language(String),
+    User(String),
+    Stars(Bounds<u64>),
+    Forks(Bounds<u64>),
+    /// Repository size in kilobytes
+    Size(Bounds<u64>),
+    Pushed(Bounds<NaiveDate>),
+    Created(Bounds<NaiveDate>),
+    Updated(Bounds<NaiveDate>),
+    Topic(String),
+    License(String),
+    Archived(bool),
+    Fork(ForkFilter),
+    Public,
+    In(Vec<SearchIn>),
+    /// Exclude results matching the qualifier, e.g. `-user:rust-lang`
+    Not(Box<Qualifier>),
+    /// Passed to GitHub as is
+    Raw(String),
+}
+
+impl Qualifier {
+    fn validate(&self) -> Result<(), Error> {
+        let reason = match self {
+            Qualifier::User(value) => {
+                if value.is_empty() {
+                    Some("value is empty")
+                } else if value.contains(char::is_whitespace) || value.contains('"') {
+                    Some("value must be a single word")
+                } else {
+                    None
+                }
+            }
+            // Rendered in quotes if there is whitespace
+            Qualifier::Language(value) | Qualifier::Topic(value) | Qualifier::License(value) => {
+                if value.is_empty() {
+                    Some("value is empty")
+                } else if value.contains('"') {
+                    Some("value must not contain quotes")
+                } else {
+                    None
+                }
+            }
+            Qualifier::Stars(bounds) | Qualifier::Forks(bounds) | Qualifier::Size(bounds) => {
+                Self::check_bounds(bounds)
+            }
+            Qualifier::Pushed(bounds) | Qualifier::Created(bounds) | Qualifier::Updated(bounds) => {
+                Self::check_bounds(bounds)
+            }
+            Qualifier::In(fields) if fields.is_empty() => Some("no fields to search in"),
+            Qualifier::Not(inner) => match **inner {
+                Qualifier::Not(_) => Some("double negation"),
+                Qualifier::Raw(_) => Some("raw query can't be negated"),
+                ref inner => return inner.validate(),
+            },
+            Qualifier::Raw(query) if query.trim().is_empty() => Some("query is empty"),
+            _ => None,
+        };
+
+        match reason {
+            Some(reason) => raise!(QueryBuilderError::InvalidQualifier {
+                qualifier: format!("{:?}", self),
+                reason,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn check_bounds<T: BoundValue>(bounds: &Bounds<T>) -> Option<&'static str> {
+        if bounds.is_valid() {
+            None
+        } else {
+            Some("lower bound is greater than the upper one")
+        }
+    }
+}
+
+impl fmt::Display for Qualifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Qualifier::Language(language) => write!(f, "language:{}", quoted(language)),
+            Qualifier::User(user) => write!(f, "user:{}", user),
+            Qualifier::Stars(bounds) => write!(f, "stars:{}", bounds),
+            Qualifier::Forks(bounds) => write!(f, "forks:{}", bounds),
+            Qualifier::Size(bounds) => write!(f, "size:{}", bounds),
+            Qualifier::Pushed(bounds) => write!(f, "pushed:{}", bounds),
+            Qualifier::Created(bounds) => write!(f, "created:{}", bounds),
+            Qualifier::Updated(bounds) => write!(f, "updated:{}", bounds),
+            Qualifier::Topic(topic) => write!(f, "topic:{}", quoted(topic)),
+            Qualifier::License(license) => write!(f, "license:{}", quoted(license)),
+            Qualifier::Archived(archived) => write!(f, "archived:{}", archived),
+            Qualifier::Fork(ForkFilter::Include) => write!(f, "fork:true"),
+            Qualifier::Fork(ForkFilter::Only) => write!(f, "fork:only"),
+            Qualifier::Public => write!(f, "is:public"),
+            Qualifier::In(fields) => {
+                let fields: Vec<&str> = fields.iter().map(|field| field.as_str()).collect();
+                write!(f, "in:{}", fields.join(","))
+            }
+            Qualifier::Not(inner) => write!(f, "-{}", inner),
+            Qualifier::Raw(query) => write!(f, "{}", query),
+        }
+    }
+}
+
+/// Values with whitespace, e.g. `language:"Protocol Buffer"`, go in quotes
+fn quoted(value: &str) -> Cow<str> {
+    if value.contains(char::is_whitespace) {
+        Cow::Owned(format!("\"{}\"", value))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct IncompleteQuery {
     pub search_for: SearchFor,
+    fields: FieldSet,
+    qualifiers: Vec<Qualifier>,
+    count: Option<u8>,
+    after: Option<String>,
+}
+
+/// Serialized `IncompleteQuery`, including the format older than the typed qualifiers
+#[derive(Deserialize)]
+struct IncompleteQueryRepr {
+    search_for: SearchFor,
+    #[serde(default)]
+    fields: FieldSet,
+    #[serde(default)]
+    qualifiers: Vec<Qualifier>,
+    /// Search string the qualifiers used to be kept in
+    #[serde(default)]
     query: Option<String>,
+    #[serde(default)]
     count: Option<u8>,
+    #[serde(default)]
     after: Option<String>,
 }
 
-impl IncompleteQuery {
-    pub fn raw_query(mut self, raw_query: impl Into<String>) -> Self {
-        let raw_query = raw_query.into();
-
-        // If we already have something in query field, we append
-        if let Some(mut query) = self.query {
-            query.push_str(QUERY_DELIMITER);
-            query.push_str(&raw_query);
-            self.query = Some(query);
-        } else {
-            self.query = Some(raw_query);
+/// Requests serialized in the old format may still sit in Kafka, their search string
+/// is parsed into the qualifiers.
+impl<'de> Deserialize<'de> for IncompleteQuery {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let repr = IncompleteQueryRepr::deserialize(deserializer)?;
+
+        let mut query = IncompleteQuery {
+            search_for: repr.search_for,
+            fields: repr.fields,
+            qualifiers: repr.qualifiers,
+            count: repr.count,
+            after: repr.after,
+        };
+        if let Some(raw) = repr.query {
+            query = query.raw_query(raw).normalize();
         }
+        Ok(query)
+    }
+}
+
+impl IncompleteQuery {
+    pub fn raw_query(self, raw_query: impl Into<String>) -> Self {
+        self.qualifier(Qualifier::Raw(raw_query.into()))
+    }
+
+    pub fn qualifier(mut self, qualifier: Qualifier) -> Self {
+        self.qualifiers.push(qualifier);
+        self
+    }
+
+    /// Exclude results matching the qualifier
+    pub fn exclude(self, qualifier: Qualifier) -> Self {
+        self.qualifier(Qualifier::Not(Box::new(qualifier)))
+    }
+
+    pub fn qualifiers(&self) -> &[Qualifier] {
+        &self.qualifiers
+    }
 
+    /// Parse the raw qualifiers, so the typed ones they carry can be found and replaced,
+    /// e.g. `Raw("language:Rust created:>2018-01-01")` becomes `Language` and `Created`.
+    ///
+    /// Raw qualifiers that fail to parse are kept as they are.
+    pub fn normalize(mut self) -> Self {
+        for qualifier in mem::replace(&mut self.qualifiers, Vec::new()) {
+            let parsed = match qualifier {
+                Qualifier::Raw(ref raw) => raw.parse::<IncompleteQuery>().ok(),
+                _ => None,
+            };
+            match parsed {
+                Some(parsed) => self.qualifiers.extend(parsed.qualifiers),
+                None => self.qualifiers.push(qualifier),
+            }
+        }
         self
     }
 
+    /// Whether a raw qualifier mentions `key`, e.g. `created` in `Raw("created:>2018-01-01")`
+    pub fn raw_mentions(&self, key: &str) -> bool {
+        let prefix = format!("{}:", key.to_lowercase());
+        self.qualifiers.iter().any(|qualifier| match qualifier {
+            Qualifier::Raw(raw) => raw
+                .split_whitespace()
+                .map(|token| token.trim_left_matches('-').to_lowercase())
+                .any(|token| token.starts_with(&prefix)),
+            _ => false,
+        })
+    }
+
+    /// Replace the qualifiers matching `matches` with `qualifier`
+    pub fn replace_qualifier<F>(mut self, matches: F, qualifier: Qualifier) -> Self
+    where
+        F: Fn(&Qualifier) -> bool,
+    {
+        self.qualifiers.retain(|q| !matches(q));
+        self.qualifier(qualifier)
+    }
+
     pub fn search_for(mut self, t: SearchFor) -> Self {
         self.search_for = t;
         self
     }
 
     pub fn lang(self, lang: Lang) -> Self {
-        self.raw_query(lang.as_query_segment())
+        self.language(lang.name())
+    }
+
+    pub fn language(self, language: impl Into<String>) -> Self {
+        self.qualifier(Qualifier::Language(language.into()))
     }
 
     pub fn owner(self, owner: &str) -> Self {
-        self.raw_query(format!("user:{}", owner))
+        self.qualifier(Qualifier::User(owner.to_string()))
+    }
+
+    pub fn stars(self, stars: Bounds<u64>) -> Self {
+        self.qualifier(Qualifier::Stars(stars))
+    }
+
+    pub fn forks(self, forks: Bounds<u64>) -> Self {
+        self.qualifier(Qualifier::Forks(forks))
+    }
+
+    /// Size in kilobytes
+    pub fn size(self, size: Bounds<u64>) -> Self {
+        self.qualifier(Qualifier::Size(size))
+    }
+
+    pub fn pushed(self, pushed: Bounds<NaiveDate>) -> Self {
+        self.qualifier(Qualifier::Pushed(pushed))
+    }
+
+    pub fn created(self, created: Bounds<NaiveDate>) -> Self {
+        self.qualifier(Qualifier::Created(created))
+    }
+
+    pub fn updated(self, updated: Bounds<NaiveDate>) -> Self {
+        self.qualifier(Qualifier::Updated(updated))
+    }
+
+    pub fn topic(self, topic: impl Into<String>) -> Self {
+        self.qualifier(Qualifier::Topic(topic.into()))
+    }
+
+    /// License keyword, e.g. `mit` or `apache-2.0`
+    pub fn license(self, license: impl Into<String>) -> Self {
+        self.qualifier(Qualifier::License(license.into()))
+    }
+
+    pub fn archived(self, archived: bool) -> Self {
+        self.qualifier(Qualifier::Archived(archived))
+    }
+
+    pub fn fork(self, fork: ForkFilter) -> Self {
+        self.qualifier(Qualifier::Fork(fork))
+    }
+
+    pub fn public(self) -> Self {
+        self.qualifier(Qualifier::Public)
+    }
+
+    pub fn search_in(self, fields: &[SearchIn]) -> Self {
+        self.qualifier(Qualifier::In(fields.to_vec()))
+    }
+
+    /// Request an optional group of repository fields
+    pub fn field(mut self, field: RepositoryField) -> Self {
+        self.fields = self.fields.with(field);
+        self
+    }
+
+    pub fn fields(mut self, fields: FieldSet) -> Self {
+        self.fields = fields;
+        self
     }
 
     pub fn count(mut self, count: u8) -> Self {
@@ -134,10 +583,32 @@ impl IncompleteQuery {
             raise!(QueryBuilderError::InvalidCount { count })
         }
 
+        if self.search_for == SearchFor::Undefined {
+            raise!(QueryBuilderError::UndefinedTarget)
+        }
+
+        for qualifier in &self.qualifiers {
+            qualifier.validate()?;
+        }
+
+        let mut segments: Vec<String> = self.qualifiers.iter().map(|q| q.to_string()).collect();
+        if let Some(is) = self.search_for.is_qualifier() {
+            if !segments.iter().any(|segment| segment == is) {
+                segments.insert(0, is.to_string());
+            }
+        }
+
+        let query = if segments.is_empty() {
+            None
+        } else {
+            Some(segments.join(QUERY_DELIMITER))
+        };
+
         let query = Query {
             count,
             search_for: self.search_for,
-            query: self.query,
+            fields: self.fields,
+            query,
             after: self.after,
         };
 
@@ -145,13 +616,365 @@ impl IncompleteQuery {
     }
 }
 
+/// Renders the search string, without the target, count and cursor
+impl fmt::Display for IncompleteQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, qualifier) in self.qualifiers.iter().enumerate() {
+            if idx != 0 {
+                write!(f, "{}", QUERY_DELIMITER)?;
+            }
+            write!(f, "{}", qualifier)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a search string as typed in the GitHub search box,
+/// e.g. `language:Rust stars:>50 pushed:>2018-01-01 -user:foo`.
+///
+/// Unknown qualifiers and search terms are kept as is.
+impl FromStr for IncompleteQuery {
+    type Err = Error;
+
+    fn from_str(query: &str) -> Result<Self, Error> {
+        let mut parsed = IncompleteQuery::default();
+        for token in tokenize(query)? {
+            let qualifier = token.parse::<Qualifier>()?;
+            qualifier.validate()?;
+            parsed = parsed.qualifier(qualifier);
+        }
+        Ok(parsed)
+    }
+}
+
+/// Split on whitespace, keeping `"quoted phrases"` together
+fn tokenize(query: &str) -> Result<Vec<String>, Error> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_quotes = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                token.push(c);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !token.is_empty() {
+                    tokens.push(token.clone());
+                    token.clear();
+                }
+            }
+            c => token.push(c),
+        }
+    }
+
+    if in_quotes {
+        raise!(QueryParseError::UnterminatedQuote {
+            query: query.to_string(),
+        })
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+impl FromStr for Qualifier {
+    type Err = Error;
+
+    fn from_str(token: &str) -> Result<Self, Error> {
+        let raw = || Ok(Qualifier::Raw(token.to_string()));
+
+        let idx = match token.find(':') {
+            Some(idx) if !token.starts_with('"') => idx,
+            _ => return raw(),
+        };
+
+        let (negated, key) = if token.starts_with('-') {
+            (true, &token[1..idx])
+        } else {
+            (false, &token[..idx])
+        };
+        // `topic:"machine learning"`, quotes anywhere else are left to GitHub
+        let value = match &token[idx + 1..] {
+            value if value.len() > 1 && value.starts_with('"') && value.ends_with('"') => {
+                &value[1..value.len() - 1]
+            }
+            value if value.contains('"') => return raw(),
+            value => value,
+        };
+
+        let invalid = || QueryParseError::InvalidValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        let number = || Bounds::<u64>::parse(value).ok_or_else(invalid);
+        let date = || Bounds::<NaiveDate>::parse(value).ok_or_else(invalid);
+
+        let qualifier = match key.to_lowercase().as_str() {
+            "language" => Qualifier::Language(value.to_string()),
+            "user" => Qualifier::User(value.to_string()),
+            "stars" => Qualifier::Stars(number()?),
+            "forks" => Qualifier::Forks(number()?),
+            "size" => Qualifier::Size(number()?),
+            "pushed" => Qualifier::Pushed(date()?),
+            "created" => Qualifier::Created(date()?),
+            "updated" => Qualifier::Updated(date()?),
+            "topic" => Qualifier::Topic(value.to_string()),
+            "license" => Qualifier::License(value.to_string()),
+            "archived" => match value {
+                "true" => Qualifier::Archived(true),
+                "false" => Qualifier::Archived(false),
+                _ => raise!(invalid()),
+            },
+            "fork" => match value {
+                "true" => Qualifier::Fork(ForkFilter::Include),
+                "only" => Qualifier::Fork(ForkFilter::Only),
+                _ => raise!(invalid()),
+            },
+            "is" if value == "public" => Qualifier::Public,
+            "in" => {
+                let fields: Result<Vec<SearchIn>, _> = value
+                    .split(',')
+                    .map(|field| match field {
+                        "name" => Ok(SearchIn::Name),
+                        "description" => Ok(SearchIn::Description),
+                        "readme" => Ok(SearchIn::Readme),
+                        _ => Err(invalid()),
+                    })
+                    .collect();
+                Qualifier::In(fields?)
+            }
+            _ => return raw(),
+        };
+
+        if negated {
+            Ok(Qualifier::Not(Box::new(qualifier)))
+        } else {
+            Ok(qualifier)
+        }
+    }
+}
+
 impl Default for IncompleteQuery {
     fn default() -> Self {
         IncompleteQuery {
             search_for: SearchFor::Undefined,
-            query: None,
+            fields: FieldSet::default(),
+            qualifiers: Vec::new(),
             count: None,
             after: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(query: IncompleteQuery) -> String {
+        query
+            .search_for(SearchFor::Repository)
+            .build()
+            .unwrap()
+            .query
+            .unwrap()
+    }
+
+    #[test]
+    fn render_qualifiers() {
+        let date = |d| NaiveDate::from_ymd(2018, 8, d);
+        let query = Query::builder()
+            .lang(Lang::Rust)
+            .stars(Bounds::AtLeast(10))
+            .forks(Bounds::Exactly(0))
+            .size(Bounds::AtMost(10_000))
+            .pushed(Bounds::AtLeast(date(1)))
+            .created(Bounds::Between(date(10), date(11)))
+            .updated(Bounds::AtMost(date(31)))
+            .topic("cli")
+            .license("apache-2.0")
+            .archived(false)
+            .fork(ForkFilter::Include)
+            .public()
+            .search_in(&[SearchIn::Name, SearchIn::Readme])
+            .raw_query("formatter")
+            .exclude(Qualifier::User("rust-lang".into()));
+
+        assert_eq!(
+            render(query),
+            "language:Rust stars:>=10 forks:0 size:<=10000 pushed:>=2018-08-01 \
+             created:2018-08-10..2018-08-11 updated:<=2018-08-31 topic:cli license:apache-2.0 \
+             archived:false fork:true is:public in:name,readme formatter -user:rust-lang"
+        );
+    }
+
+    #[test]
+    fn search_targets() {
+        assert!(Query::builder().build().is_err());
+
+        let query = Query::builder()
+            .search_for(SearchFor::PullRequest)
+            .raw_query("author:rustyrobot is:open")
+            .count(5)
+            .build()
+            .unwrap();
+        assert_eq!(
+            query.to_arg_list().unwrap(),
+            "type: ISSUE, first: 5, query: \"is:pr author:rustyrobot is:open\""
+        );
+
+        let query = Query::builder()
+            .search_for(SearchFor::User)
+            .build()
+            .unwrap();
+        assert_eq!(query.to_arg_list().unwrap(), "type: USER, first: 10");
+    }
+
+    #[test]
+    fn parse_search_string() {
+        let query: IncompleteQuery =
+            "language:Rust stars:>50 pushed:>2018-01-01 size:10..* in:name,readme \
+             \"code formatter\" -user:foo is:public archived:false fork:only NOT"
+                .parse()
+                .unwrap();
+
+        assert_eq!(
+            query.qualifiers(),
+            &[
+                Qualifier::Language("Rust".into()),
+                Qualifier::Stars(Bounds::AtLeast(51)),
+                Qualifier::Pushed(Bounds::AtLeast(NaiveDate::from_ymd(2018, 1, 2))),
+                Qualifier::Size(Bounds::AtLeast(10)),
+                Qualifier::In(vec![SearchIn::Name, SearchIn::Readme]),
+                Qualifier::Raw("\"code formatter\"".into()),
+                Qualifier::Not(Box::new(Qualifier::User("foo".into()))),
+                Qualifier::Public,
+                Qualifier::Archived(false),
+                Qualifier::Fork(ForkFilter::Only),
+                Qualifier::Raw("NOT".into()),
+            ][..]
+        );
+
+        // Display renders a string parsed into the same query
+        let rendered = query.to_string();
+        let reparsed: IncompleteQuery = rendered.parse().unwrap();
+        assert_eq!(reparsed.qualifiers(), query.qualifiers());
+        assert_eq!(reparsed.to_string(), rendered);
+    }
+
+    #[test]
+    fn parse_quoted_values() {
+        let query: IncompleteQuery =
+            "topic:\"machine learning\" -language:\"Protocol Buffer\" \"exact phrase\" \
+             license:\"mit\" topic:half\"quoted value\""
+                .parse()
+                .unwrap();
+
+        assert_eq!(
+            query.qualifiers(),
+            &[
+                Qualifier::Topic("machine learning".into()),
+                Qualifier::Not(Box::new(Qualifier::Language("Protocol Buffer".into()))),
+                Qualifier::Raw("\"exact phrase\"".into()),
+                Qualifier::License("mit".into()),
+                Qualifier::Raw("topic:half\"quoted value\"".into()),
+            ][..]
+        );
+        assert_eq!(
+            query.to_string(),
+            "topic:\"machine learning\" -language:\"Protocol Buffer\" \"exact phrase\" \
+             license:mit topic:half\"quoted value\""
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        for query in &[
+            "stars:lots",
+            "created:2018-13-01",
+            "archived:maybe",
+            "in:title",
+            "stars:<0",
+            "stars:100..10",
+            "\"unterminated",
+        ] {
+            assert!(
+                query.parse::<IncompleteQuery>().is_err(),
+                "{:?} is accepted",
+                query
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_qualifiers_are_rejected() {
+        let invalid = vec![
+            Qualifier::Stars(Bounds::Between(100, 10)),
+            Qualifier::Topic("".into()),
+            Qualifier::User("two words".into()),
+            Qualifier::Topic("half\"quoted".into()),
+            Qualifier::In(vec![]),
+            Qualifier::Not(Box::new(Qualifier::Not(Box::new(Qualifier::Public)))),
+            Qualifier::Not(Box::new(Qualifier::License("".into()))),
+        ];
+
+        for qualifier in invalid {
+            let query = Query::builder()
+                .search_for(SearchFor::Repository)
+                .qualifier(qualifier.clone());
+            assert!(query.build().is_err(), "{:?} is accepted", qualifier);
+        }
+    }
+
+    #[test]
+    fn normalize_raw_qualifiers() {
+        let query = Query::builder()
+            .raw_query("language:Rust created:>2018-01-01 formatter")
+            .raw_query("created:2018-01-01T00:00:00Z");
+        assert!(query.raw_mentions("created"));
+        assert!(!query.raw_mentions("stars"));
+
+        let query = query.normalize();
+        assert_eq!(
+            query.qualifiers(),
+            &[
+                Qualifier::Language("Rust".into()),
+                Qualifier::Created(Bounds::AtLeast(NaiveDate::from_ymd(2018, 1, 2))),
+                Qualifier::Raw("formatter".into()),
+                // Date-times aren't parsed, the qualifier is left as it is
+                Qualifier::Raw("created:2018-01-01T00:00:00Z".into()),
+            ][..]
+        );
+    }
+
+    #[test]
+    fn deserialize_old_format() {
+        let query: IncompleteQuery = ::json::from_value(json!({
+            "search_for": "Repository",
+            "query": "language:Rust created:2018-08-10..2018-08-11",
+            "count": 100,
+            "after": null,
+        }))
+        .unwrap();
+
+        assert_eq!(
+            query.qualifiers(),
+            &[
+                Qualifier::Language("Rust".into()),
+                Qualifier::Created(Bounds::Between(
+                    NaiveDate::from_ymd(2018, 8, 10),
+                    NaiveDate::from_ymd(2018, 8, 11),
+                )),
+            ][..]
+        );
+        assert_eq!(query.count, Some(100));
+
+        // The current format survives the round trip
+        let value = ::json::to_value(&query).unwrap();
+        let query: IncompleteQuery = ::json::from_value(value).unwrap();
+        assert_eq!(query.qualifiers().len(), 2);
+    }
+}
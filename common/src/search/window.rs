@@ -0,0 +1,242 @@
+use chrono::{NaiveDate, Utc};
+use failure::Error;
+use json::Value;
+
+use github::v4::Github;
+use search::query::{Bounds, IncompleteQuery, Qualifier};
+use search::search;
+
+/// GitHub returns at most this many results per search query
+pub const SEARCH_CAP: u64 = 1000;
+
+/// Nothing on GitHub was created before it went public
+pub const EARLIEST_DATE: (i32, u32, u32) = (2007, 10, 1);
+
+/// Split the query into sub-queries each matching no more than `cap` results.
+///
+/// The `created` range is bisected first, the query is limited to the GitHub lifetime if it has
+/// no range. Once a window is down to a single day, the `stars` range is bisected instead.
+/// Windows are disjoint and together cover every result of the original query.
+///
+/// Ranges given in raw qualifiers are parsed first; those that can't be parsed are never
+/// bisected, so the windows don't contradict them.
+pub fn split_windows(
+    gh: &Github,
+    query: IncompleteQuery,
+    cap: u64,
+) -> Result<Vec<IncompleteQuery>, Error> {
+    let mut windows = Vec::new();
+    split_into(gh, query.normalize(), cap, &mut windows)?;
+    Ok(windows)
+}
+
+fn split_into(
+    gh: &Github,
+    query: IncompleteQuery,
+    cap: u64,
+    windows: &mut Vec<IncompleteQuery>,
+) -> Result<(), Error> {
+    let count = count_results(gh, &query)?;
+    if count <= cap {
+        windows.push(query);
+        return Ok(());
+    }
+
+    match bisect(&query) {
+        Some((lower, upper)) => {
+            debug!(
+                "{} results past the search cap, splitting the window",
+                count
+            );
+            split_into(gh, lower, cap, windows)?;
+            split_into(gh, upper, cap, windows)
+        }
+        None => {
+            error!(
+                "can't split the window any further, {} of {} results are unreachable: {:?}",
+                count - cap,
+                count,
+                query
+            );
+            windows.push(query);
+            Ok(())
+        }
+    }
+}
+
+/// Number of results the query matches, including the ones past the search cap
+pub fn count_results(gh: &Github, query: &IncompleteQuery) -> Result<u64, Error> {
+    let query = query.clone().count(1).build()?;
+    let result = search::<Value>(gh, query)?;
+    Ok(result.total_count())
+}
+
+fn bisect(query: &IncompleteQuery) -> Option<(IncompleteQuery, IncompleteQuery)> {
+    let created = query
+        .qualifiers()
+        .iter()
+        .filter_map(|q| match q {
+            Qualifier::Created(bounds) => Some(bounds.clone()),
+            _ => None,
+        })
+        .next();
+    let stars = query
+        .qualifiers()
+        .iter()
+        .filter_map(|q| match q {
+            Qualifier::Stars(bounds) => Some(bounds.clone()),
+            _ => None,
+        })
+        .next();
+
+    let is_created = |q: &Qualifier| match q {
+        Qualifier::Created(_) => true,
+        _ => false,
+    };
+    let is_stars = |q: &Qualifier| match q {
+        Qualifier::Stars(_) => true,
+        _ => false,
+    };
+
+    let (from, to) = match created {
+        None => (earliest_date(), Utc::today().naive_utc()),
+        Some(Bounds::Between(from, to)) => (from, to),
+        Some(Bounds::AtLeast(from)) => (from, Utc::today().naive_utc()),
+        Some(Bounds::AtMost(to)) => (earliest_date(), to),
+        Some(Bounds::Exactly(date)) => (date, date),
+    };
+
+    // A range the query got in the raw form would contradict the bisected one
+    if from < to && !query.raw_mentions("created") {
+        let mid = from + (to - from) / 2;
+        let lower = query
+            .clone()
+            .replace_qualifier(is_created, Qualifier::Created(Bounds::Between(from, mid)));
+        let upper = query.clone().replace_qualifier(
+            is_created,
+            Qualifier::Created(Bounds::Between(mid.succ(), to)),
+        );
+        return Some((lower, upper));
+    }
+
+    if query.raw_mentions("stars") {
+        return None;
+    }
+    let (lower, upper) = split_stars(stars.unwrap_or(Bounds::AtLeast(0)))?;
+    Some((
+        query
+            .clone()
+            .replace_qualifier(is_stars, Qualifier::Stars(lower)),
+        query
+            .clone()
+            .replace_qualifier(is_stars, Qualifier::Stars(upper)),
+    ))
+}
+
+fn split_stars(stars: Bounds<u64>) -> Option<(Bounds<u64>, Bounds<u64>)> {
+    match stars {
+        Bounds::Between(from, to) if from < to => {
+            let mid = from + (to - from) / 2;
+            Some((Bounds::Between(from, mid), Bounds::Between(mid + 1, to)))
+        }
+        Bounds::AtMost(to) if to > 0 => {
+            let mid = to / 2;
+            Some((Bounds::Between(0, mid), Bounds::Between(mid + 1, to)))
+        }
+        // Most repositories have few stars, so the lower half grows slowly
+        Bounds::AtLeast(from) => {
+            let mid = from + from.max(1);
+            Some((Bounds::Between(from, mid), Bounds::AtLeast(mid + 1)))
+        }
+        _ => None,
+    }
+}
+
+fn earliest_date() -> NaiveDate {
+    let (year, month, day) = EARLIEST_DATE;
+    NaiveDate::from_ymd(year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use fake_github::{FakeGithub, FakeRepo};
+    use github::tokens::TokenPool;
+    use search::query::{Query, SearchFor};
+    use std::sync::Arc;
+
+    #[test]
+    fn windows_cover_every_result() {
+        let fake = FakeGithub::start().unwrap();
+        fake.state().search_cap = 3;
+        // Four repos created on the first day, so it has to be split by stars
+        for i in 0..10 {
+            let created = Utc.ymd(2018, 8, 1 + i % 3).and_hms(12, 0, 0);
+            let repo = FakeRepo::new(format!("owner/repo{}", i))
+                .created_at(created)
+                .stars(u64::from(i) * 3);
+            fake.add_repo(repo);
+        }
+
+        let gh = Github::with_pool(Arc::new(TokenPool::single("bot")), &fake.url()).unwrap();
+        let query = Query::builder()
+            .search_for(SearchFor::Repository)
+            .created(Bounds::Between(
+                NaiveDate::from_ymd(2018, 8, 1),
+                NaiveDate::from_ymd(2018, 8, 5),
+            ));
+        let windows = split_windows(&gh, query, 3).unwrap();
+        assert!(windows.len() > 1);
+
+        let mut names = Vec::new();
+        for window in windows {
+            let result = search::<Value>(&gh, window.count(100).build().unwrap()).unwrap();
+            assert!(result.total_count() <= 3);
+            names.extend(
+                result
+                    .nodes
+                    .iter()
+                    .map(|node| node["nameWithOwner"].as_str().unwrap().to_string()),
+            );
+        }
+
+        names.sort();
+        let mut expected: Vec<String> = (0..10).map(|i| format!("owner/repo{}", i)).collect();
+        expected.sort();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn raw_ranges_are_respected() {
+        let date = |d| NaiveDate::from_ymd(2018, 8, d);
+
+        // Parsed out of the raw qualifier and bisected in place
+        let query = Query::builder()
+            .raw_query("language:Rust created:2018-08-01..2018-08-04")
+            .normalize();
+        let (lower, upper) = bisect(&query).unwrap();
+        assert_eq!(
+            lower.to_string(),
+            "language:Rust created:2018-08-01..2018-08-02"
+        );
+        assert_eq!(
+            upper.to_string(),
+            "language:Rust created:2018-08-03..2018-08-04"
+        );
+        assert!(lower
+            .qualifiers()
+            .contains(&Qualifier::Created(Bounds::Between(date(1), date(2)))));
+
+        // Unparsed ranges are left alone, only stars are split
+        let query = Query::builder().raw_query("created:>2018-08-01T12:00:00Z");
+        let (lower, _) = bisect(&query).unwrap();
+        assert_eq!(
+            lower.to_string(),
+            "created:>2018-08-01T12:00:00Z stars:0..1"
+        );
+
+        let query = Query::builder().raw_query("created:>2018-08-01T12:00:00Z stars:>10x");
+        assert!(bisect(&query).is_none());
+    }
+}
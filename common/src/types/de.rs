@@ -0,0 +1,188 @@
+use json::{self, Value};
+use serde::de::DeserializeOwned;
+
+use std::fmt;
+
+/// Deserialization failure along with the path of the offending value, e.g. `.parent.sshUrl`
+#[derive(Debug)]
+pub struct PathError {
+    pub path: String,
+    pub error: json::Error,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at `{}`: {}", self.path, self.error)
+    }
+}
+
+pub fn from_value<T: DeserializeOwned>(json: &Value) -> Result<T, PathError> {
+    let error = match json::from_value(json.clone()) {
+        Ok(value) => return Ok(value),
+        Err(error) => error,
+    };
+
+    // Values carry no positions, so the failure is reproduced on the pretty-printed JSON:
+    // every value starts on its own line there, and the line of the error gives the path.
+    let pretty = match json::to_string_pretty(json) {
+        Ok(pretty) => pretty,
+        Err(_) => {
+            return Err(PathError {
+                path: ".".to_string(),
+                error,
+            })
+        }
+    };
+    match json::from_str::<T>(&pretty) {
+        Err(located) => Err(PathError {
+            path: path_at(&pretty, located.line()),
+            error: located,
+        }),
+        Ok(_) => Err(PathError {
+            path: ".".to_string(),
+            error,
+        }),
+    }
+}
+
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+struct Container {
+    is_array: bool,
+    /// Entry being parsed
+    current: Option<Segment>,
+}
+
+/// Path of the value at the 1-based `line` of JSON printed by `to_string_pretty`
+fn path_at(pretty: &str, line: usize) -> String {
+    let mut stack: Vec<Container> = Vec::new();
+
+    for (number, text) in pretty.lines().enumerate() {
+        let text = text.trim();
+        let text = text.trim_right_matches(',');
+        let is_error_line = number + 1 == line;
+
+        // Closing brackets point at the container itself, e.g. for missing fields
+        if text.starts_with('}') || text.starts_with(']') {
+            if is_error_line {
+                if let Some(top) = stack.last_mut() {
+                    top.current = None;
+                }
+                break;
+            }
+            stack.pop();
+            continue;
+        }
+
+        let value = match stack.last_mut() {
+            Some(ref mut top) if top.is_array => {
+                let index = match top.current {
+                    Some(Segment::Index(index)) => index + 1,
+                    _ => 0,
+                };
+                top.current = Some(Segment::Index(index));
+                text
+            }
+            Some(ref mut top) => match split_key(text) {
+                Some((key, value)) => {
+                    top.current = Some(Segment::Key(key));
+                    value
+                }
+                None => text,
+            },
+            None => text,
+        };
+
+        if is_error_line {
+            break;
+        }
+        if value == "{" || value == "[" {
+            stack.push(Container {
+                is_array: value == "[",
+                current: None,
+            });
+        }
+    }
+
+    let path: String = stack
+        .iter()
+        .filter_map(|container| container.current.as_ref())
+        .map(|segment| match *segment {
+            Segment::Key(ref key) => format!(".{}", key),
+            Segment::Index(index) => format!("[{}]", index),
+        })
+        .collect();
+
+    if path.is_empty() {
+        ".".to_string()
+    } else {
+        path
+    }
+}
+
+/// Split `"key": value` into the unescaped key and the value
+fn split_key(text: &str) -> Option<(String, &str)> {
+    if !text.starts_with('"') {
+        return None;
+    }
+
+    let mut escaped = false;
+    let end = text[1..].char_indices().find(|&(_, c)| {
+        let found = c == '"' && !escaped;
+        escaped = c == '\\' && !escaped;
+        found
+    })?;
+    let end = end.0 + 2;
+
+    let key = json::from_str(&text[..end]).ok()?;
+    let value = text[end..].trim_left_matches(':').trim();
+    Some((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Outer {
+        name: String,
+        inner: Vec<Inner>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Inner {
+        size: u64,
+        tags: Option<Vec<String>>,
+    }
+
+    fn path_of(json: Value) -> String {
+        from_value::<Outer>(&json).unwrap_err().path
+    }
+
+    #[test]
+    fn error_paths() {
+        assert_eq!(path_of(json!({ "inner": [] })), ".");
+        assert_eq!(path_of(json!({ "name": 1, "inner": [] })), ".name");
+        assert_eq!(
+            path_of(json!({ "name": "x", "inner": [{ "size": 1 }, { "size": "big" }] })),
+            ".inner[1].size"
+        );
+        assert_eq!(
+            path_of(json!({ "name": "x", "inner": [{ "size": 1, "tags": ["a", 2] }] })),
+            ".inner[0].tags[1]"
+        );
+        assert_eq!(
+            path_of(json!({ "name": "x", "inner": [{ "size": 1 }, {}] })),
+            ".inner[1]"
+        );
+        assert_eq!(
+            path_of(json!({ "name": "x", "inner": [{ "size": 1, "tags": {} }] })),
+            ".inner[0].tags"
+        );
+    }
+}
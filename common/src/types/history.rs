@@ -0,0 +1,186 @@
+use chrono::{DateTime, Utc};
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use types::Repository;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Stage {
+    Fork,
+    Format,
+    PullRequest,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StageOutcome {
+    Success,
+    Failure,
+    /// Nothing to do, e.g. the PR already exists
+    Skipped,
+}
+
+/// Single run of a pipeline stage, appended to `Repository::history`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StageRecord {
+    pub stage: Stage,
+    pub started_at: DateTime<Utc>,
+    /// `<service>/<version>` that ran the stage
+    pub version: String,
+    pub outcome: StageOutcome,
+    pub duration_ms: u64,
+    pub branch: Option<String>,
+    pub error: Option<String>,
+}
+
+impl StageRecord {
+    /// Stage started at `started_at` and finished just now
+    pub fn finished(
+        stage: Stage,
+        version: &str,
+        started_at: DateTime<Utc>,
+        outcome: StageOutcome,
+    ) -> Self {
+        let duration = Utc::now().signed_duration_since(started_at);
+        StageRecord {
+            stage,
+            started_at,
+            version: version.to_string(),
+            outcome,
+            duration_ms: duration.num_milliseconds().max(0) as u64,
+            branch: None,
+            error: None,
+        }
+    }
+
+    pub fn branch(mut self, branch: impl Into<String>) -> Self {
+        self.branch = Some(branch.into());
+        self
+    }
+
+    /// Marks the run failed
+    pub fn error(mut self, error: impl Display) -> Self {
+        self.outcome = StageOutcome::Failure;
+        self.error = Some(error.to_string());
+        self
+    }
+}
+
+/// Stage history of a repository, kept in a compacted topic keyed by `name_with_owner`.
+///
+/// Compaction leaves only the latest snapshot of each repository, so the records are
+/// accumulated in `Repository::history` as it goes through the stages, not in the topic.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryHistory {
+    /// The bot forks carry the history of the upstream, so it's kept under the upstream name
+    pub name_with_owner: String,
+    pub history: Vec<StageRecord>,
+}
+
+impl RepositoryHistory {
+    /// Every event carries the whole history so far, the latest one replaces the previous
+    pub fn of(repo: &Repository) -> Self {
+        let upstream = repo.parent.as_ref().map(|parent| &parent.name_with_owner);
+        RepositoryHistory {
+            name_with_owner: upstream.unwrap_or(&repo.name_with_owner).clone(),
+            history: repo.history.clone(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SuccessRate {
+    /// Skipped runs aren't counted
+    pub attempts: u64,
+    pub successes: u64,
+}
+
+impl SuccessRate {
+    pub fn rate(&self) -> Option<f64> {
+        if self.attempts == 0 {
+            None
+        } else {
+            Some(self.successes as f64 / self.attempts as f64)
+        }
+    }
+}
+
+/// Success rates of the stages over the records, e.g. histories of all the repositories
+pub fn success_rates<'a, I>(records: I) -> HashMap<Stage, SuccessRate>
+where
+    I: IntoIterator<Item = &'a StageRecord>,
+{
+    let mut rates: HashMap<Stage, SuccessRate> = HashMap::new();
+    for record in records {
+        let rate = rates
+            .entry(record.stage)
+            .or_insert_with(SuccessRate::default);
+        match record.outcome {
+            StageOutcome::Success => {
+                rate.attempts += 1;
+                rate.successes += 1;
+            }
+            StageOutcome::Failure => rate.attempts += 1,
+            StageOutcome::Skipped => (),
+        }
+    }
+    rates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::RepositoryParent;
+
+    #[test]
+    fn rates_across_reruns() {
+        let mut repo: Repository = json::from_value(json!({
+            "id": "1",
+            "name_with_owner": "owner/repo",
+            "description": null,
+            "ssh_url": "git@github.com:owner/repo.git",
+            "url": "https://github.com/owner/repo",
+            "default_branch": "master",
+            "created_at": "2018-08-10T12:00:00Z",
+            "parent": null,
+            "has_issues_enabled": true,
+            "is_fork": false,
+            "stats": null,
+        }))
+        .unwrap();
+        assert!(repo.history.is_empty());
+
+        let run =
+            |outcome| StageRecord::finished(Stage::Format, "formatter/0.1.0", Utc::now(), outcome);
+        repo.record_stage(run(StageOutcome::Success).error("cargo fmt failed"));
+        repo.record_stage(run(StageOutcome::Success).branch("fmt"));
+        repo.record_stage(run(StageOutcome::Skipped));
+        repo.record_stage(StageRecord::finished(
+            Stage::PullRequest,
+            "github/0.1.0",
+            Utc::now(),
+            StageOutcome::Success,
+        ));
+
+        assert_eq!(repo.history[0].outcome, StageOutcome::Failure);
+        assert_eq!(
+            repo.last_stage(Stage::Format).map(|run| run.outcome),
+            Some(StageOutcome::Skipped)
+        );
+
+        let rates = success_rates(&repo.history);
+        assert_eq!(rates[&Stage::Format].attempts, 2);
+        assert_eq!(rates[&Stage::Format].rate(), Some(0.5));
+        assert_eq!(rates[&Stage::PullRequest].rate(), Some(1.0));
+        assert!(rates.get(&Stage::Fork).is_none());
+
+        let mut fork = repo.clone();
+        fork.name_with_owner = "rustyrobot/repo".into();
+        fork.parent = Some(RepositoryParent {
+            name_with_owner: "owner/repo".into(),
+            ssh_url: "git@github.com:owner/repo.git".into(),
+            url: "https://github.com/owner/repo".into(),
+        });
+        assert_eq!(RepositoryHistory::of(&fork), RepositoryHistory::of(&repo));
+        assert_eq!(RepositoryHistory::of(&fork).name_with_owner, "owner/repo");
+    }
+}
@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+use failure::Error;
+use json::{self, Value};
+use search::NodeType;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Issue {
+    pub id: String,
+    pub number: i64,
+    pub title: String,
+    pub state: IssueState,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    /// `None` if the account was deleted
+    pub author: Option<Actor>,
+    pub repository: RepositoryRef,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum IssueState {
+    Open,
+    Closed,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Actor {
+    pub login: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepositoryRef {
+    pub name_with_owner: String,
+}
+
+impl NodeType for Issue {
+    fn from_value(json: Value) -> Result<Self, Error> {
+        let issue = json::from_value(json)?;
+        Ok(issue)
+    }
+}
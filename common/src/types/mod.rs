@@ -1,5 +1,9 @@
+pub mod de;
+pub mod history;
+pub mod issue;
+pub mod notification;
+pub mod pr;
 pub mod repo;
+pub mod user;
+pub use self::notification::Notification;
 pub use self::repo::*;
-
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-pub struct Notification {}
@@ -0,0 +1,111 @@
+use chrono::{DateTime, Utc};
+
+/// Notification thread, as returned by `GET /notifications`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    /// Thread id
+    pub id: String,
+    pub reason: NotificationReason,
+    pub unread: bool,
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_read_at: Option<DateTime<Utc>>,
+    pub subject: Subject,
+    pub repository: NotificationRepository,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationReason {
+    Assign,
+    Author,
+    Comment,
+    CiActivity,
+    Invitation,
+    Manual,
+    Mention,
+    ReviewRequested,
+    SecurityAlert,
+    StateChange,
+    Subscribed,
+    TeamMention,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Subject {
+    pub title: String,
+    #[serde(rename = "type")]
+    pub subject_type: SubjectType,
+    /// API url of the issue, PR, commit or release
+    pub url: Option<String>,
+    pub latest_comment_url: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubjectType {
+    Issue,
+    PullRequest,
+    Commit,
+    Release,
+    Discussion,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NotificationRepository {
+    pub full_name: String,
+    pub html_url: String,
+}
+
+impl Notification {
+    /// Number of the PR the thread is about
+    pub fn pull_number(&self) -> Option<i64> {
+        if self.subject.subject_type != SubjectType::PullRequest {
+            return None;
+        }
+        self.subject.url.as_ref()?.rsplit('/').next()?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{FakeGithub, FakeNotification};
+    use github::tokens::TokenPool;
+    use github::v3::{ExecutorExt, Github};
+    use reqwest::StatusCode;
+    use std::sync::Arc;
+
+    #[test]
+    fn parse_notifications() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_notification(
+            FakeNotification::new("owner/repo", "Format the code with rustfmt")
+                .pull(3)
+                .reason("review_requested"),
+        );
+        fake.add_notification(FakeNotification::new("owner/repo", "Bug").reason("new_reason"));
+        let gh = Github::with_pool(Arc::new(TokenPool::single("bot")), &fake.url()).unwrap();
+
+        let notifications: Vec<Notification> = gh
+            .get()
+            .custom_endpoint("notifications")
+            .send(&[StatusCode::OK])
+            .unwrap();
+
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(notifications[0].reason, NotificationReason::ReviewRequested);
+        assert_eq!(
+            notifications[0].subject.subject_type,
+            SubjectType::PullRequest
+        );
+        assert_eq!(notifications[0].pull_number(), Some(3));
+        assert_eq!(notifications[0].repository.full_name, "owner/repo");
+        assert!(notifications[0].unread);
+        assert_eq!(notifications[1].reason, NotificationReason::Other);
+        assert_eq!(notifications[1].pull_number(), None);
+    }
+}
@@ -0,0 +1,370 @@
+use chrono::{DateTime, Utc};
+use failure::Error;
+use json::{self, Value};
+use search::NodeType;
+use std::collections::HashMap;
+use types::issue::{Actor, RepositoryRef};
+use types::repo::{PRStatus, PR};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequest {
+    pub id: String,
+    pub number: i64,
+    pub title: String,
+    pub state: PullRequestState,
+    pub url: String,
+    pub created_at: DateTime<Utc>,
+    pub head_ref_name: String,
+    /// `None` if the account was deleted
+    pub author: Option<Actor>,
+    pub repository: RepositoryRef,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PullRequestState {
+    Open,
+    Closed,
+    Merged,
+}
+
+impl NodeType for PullRequest {
+    fn from_value(json: Value) -> Result<Self, Error> {
+        let pr = json::from_value(json)?;
+        Ok(pr)
+    }
+}
+
+impl From<PullRequestState> for PRStatus {
+    fn from(state: PullRequestState) -> PRStatus {
+        match state {
+            PullRequestState::Open => PRStatus::Open,
+            PullRequestState::Closed => PRStatus::Closed,
+            PullRequestState::Merged => PRStatus::Merged,
+        }
+    }
+}
+
+/// Summary recorded in `Stats::prs`
+impl From<PullRequest> for PR {
+    fn from(pr: PullRequest) -> PR {
+        PR {
+            title: pr.title,
+            number: pr.number,
+            status: pr.state.into(),
+            url: Some(pr.url),
+            head_branch: Some(pr.head_ref_name),
+            head_sha: None,
+            base_branch: None,
+            created_at: Some(pr.created_at),
+            merged_at: None,
+            closed_at: None,
+            merged_by: None,
+            review_state: None,
+            comments: 0,
+            mergeable_state: None,
+            checks: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReviewState {
+    Approved,
+    ChangesRequested,
+    Commented,
+    Dismissed,
+    Pending,
+}
+
+impl ReviewState {
+    /// Overall verdict: the latest review of every reviewer counts,
+    /// a single request for changes outweighs any number of approvals
+    pub fn summarize(reviews: &[v3::Review]) -> Option<ReviewState> {
+        let mut latest: HashMap<&str, ReviewState> = HashMap::new();
+        for review in reviews {
+            match review.state {
+                // Neither changes the verdict of the reviewer
+                ReviewState::Commented | ReviewState::Pending => {
+                    latest
+                        .entry(review.user.login.as_str())
+                        .or_insert(review.state);
+                }
+                state => {
+                    latest.insert(review.user.login.as_str(), state);
+                }
+            }
+        }
+
+        let has = |state| latest.values().any(|s| *s == state);
+        if has(ReviewState::ChangesRequested) {
+            Some(ReviewState::ChangesRequested)
+        } else if has(ReviewState::Approved) {
+            Some(ReviewState::Approved)
+        } else if has(ReviewState::Commented) {
+            Some(ReviewState::Commented)
+        } else if has(ReviewState::Dismissed) {
+            Some(ReviewState::Dismissed)
+        } else if has(ReviewState::Pending) {
+            Some(ReviewState::Pending)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeableState {
+    Clean,
+    /// Has merge conflicts
+    Dirty,
+    /// Failing checks
+    Unstable,
+    /// Blocked by branch protection
+    Blocked,
+    Behind,
+    HasHooks,
+    Draft,
+    /// Not computed yet
+    Unknown,
+}
+
+impl<'a> From<&'a str> for MergeableState {
+    fn from(state: &str) -> MergeableState {
+        match state {
+            "clean" => MergeableState::Clean,
+            "dirty" => MergeableState::Dirty,
+            "unstable" => MergeableState::Unstable,
+            "blocked" => MergeableState::Blocked,
+            "behind" => MergeableState::Behind,
+            "has_hooks" => MergeableState::HasHooks,
+            "draft" => MergeableState::Draft,
+            _ => MergeableState::Unknown,
+        }
+    }
+}
+
+/// Combined state of the commit statuses
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pending,
+    Success,
+    Failure,
+    Error,
+}
+
+pub mod v3 {
+    use super::{CheckStatus, ReviewState};
+    use chrono::{DateTime, Utc};
+    use types::repo::{PRStatus, PR};
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct PullRequest {
+        pub number: i64,
+        pub title: String,
+        pub html_url: String,
+        /// `open` or `closed`, merged PRs are closed as well
+        pub state: String,
+        pub created_at: DateTime<Utc>,
+        pub closed_at: Option<DateTime<Utc>>,
+        pub merged_at: Option<DateTime<Utc>>,
+        #[serde(default)]
+        pub merged_by: Option<User>,
+        /// Only sent for a single PR
+        #[serde(default)]
+        pub comments: u64,
+        #[serde(default)]
+        pub mergeable_state: Option<String>,
+        pub head: Ref,
+        pub base: Ref,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Ref {
+        #[serde(rename = "ref")]
+        pub name: String,
+        pub sha: String,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct User {
+        pub login: String,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Review {
+        pub user: User,
+        pub state: ReviewState,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CombinedStatus {
+        pub state: CheckStatus,
+        pub total_count: u64,
+    }
+
+    impl CombinedStatus {
+        /// GitHub reports `pending` for commits no CI has reported to
+        pub fn checks(&self) -> Option<CheckStatus> {
+            if self.total_count > 0 {
+                Some(self.state)
+            } else {
+                None
+            }
+        }
+    }
+
+    impl PullRequest {
+        pub fn status(&self) -> PRStatus {
+            if self.merged_at.is_some() {
+                PRStatus::Merged
+            } else if self.state == "open" {
+                PRStatus::Open
+            } else {
+                PRStatus::Closed
+            }
+        }
+    }
+
+    /// Reviews and checks are served by separate endpoints and left `None`
+    impl From<PullRequest> for PR {
+        fn from(pr: PullRequest) -> PR {
+            PR {
+                status: pr.status(),
+                title: pr.title,
+                number: pr.number,
+                url: Some(pr.html_url),
+                head_branch: Some(pr.head.name),
+                head_sha: Some(pr.head.sha),
+                base_branch: Some(pr.base.name),
+                created_at: Some(pr.created_at),
+                merged_at: pr.merged_at,
+                closed_at: pr.closed_at,
+                merged_by: pr.merged_by.map(|user| user.login),
+                review_state: None,
+                comments: pr.comments,
+                mergeable_state: pr
+                    .mergeable_state
+                    .as_ref()
+                    .map(|state| state.as_str().into()),
+                checks: None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{FakeGithub, FakePull, FakeRepo};
+    use github::tokens::TokenPool;
+    use github::v3::{ExecutorExt, Github};
+    use reqwest::StatusCode;
+    use std::sync::Arc;
+
+    fn fetch(gh: &Github, number: i64) -> PR {
+        let pr: v3::PullRequest = gh
+            .get()
+            .custom_endpoint(&format!("repos/owner/repo/pulls/{}", number))
+            .send(&[StatusCode::OK])
+            .unwrap();
+        PR::from(pr)
+    }
+
+    #[test]
+    fn merged_pull_request() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo"));
+        fake.add_pull(FakePull::new("owner/repo", "rustyrobot:fmt"));
+        fake.add_pull(FakePull::new("owner/repo", "rustyrobot:fix"));
+        fake.state()
+            .pull_mut("owner/repo", 1)
+            .unwrap()
+            .merge("owner");
+        fake.state().pull_mut("owner/repo", 2).unwrap().close();
+        let gh = Github::with_pool(Arc::new(TokenPool::single("bot")), &fake.url()).unwrap();
+
+        let merged = fetch(&gh, 1);
+        assert_eq!(merged.status, PRStatus::Merged);
+        assert_eq!(merged.merged_by.as_ref().map(String::as_str), Some("owner"));
+        assert!(merged.merged_at.is_some());
+        assert_eq!(merged.head_branch.as_ref().map(String::as_str), Some("fmt"));
+        assert_eq!(
+            merged.base_branch.as_ref().map(String::as_str),
+            Some("master")
+        );
+
+        let closed = fetch(&gh, 2);
+        assert_eq!(closed.status, PRStatus::Closed);
+        assert!(closed.merged_by.is_none());
+        assert!(closed.closed_at.is_some());
+    }
+
+    #[test]
+    fn reviews_and_checks() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo"));
+        fake.add_pull(FakePull::new("owner/repo", "rustyrobot:fmt"));
+        let gh = Github::with_pool(Arc::new(TokenPool::single("bot")), &fake.url()).unwrap();
+
+        let status = |sha: &str| -> v3::CombinedStatus {
+            gh.get()
+                .custom_endpoint(&format!("repos/owner/repo/commits/{}/status", sha))
+                .send(&[StatusCode::OK])
+                .unwrap()
+        };
+        let sha = fetch(&gh, 1).head_sha.unwrap();
+        assert_eq!(status(&sha).checks(), None);
+
+        {
+            let mut state = fake.state();
+            let pull = state.pull_mut("owner/repo", 1).unwrap();
+            pull.review("owner", "CHANGES_REQUESTED");
+            pull.report_status("success");
+            pull.report_status("failure");
+        }
+
+        let reviews: Vec<v3::Review> = gh
+            .get()
+            .custom_endpoint("repos/owner/repo/pulls/1/reviews")
+            .paginate()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            ReviewState::summarize(&reviews),
+            Some(ReviewState::ChangesRequested)
+        );
+        assert_eq!(status(&sha).checks(), Some(CheckStatus::Failure));
+    }
+
+    #[test]
+    fn summarize_reviews() {
+        let review = |user: &str, state| v3::Review {
+            user: v3::User {
+                login: user.to_string(),
+            },
+            state,
+        };
+
+        assert_eq!(ReviewState::summarize(&[]), None);
+        assert_eq!(
+            ReviewState::summarize(&[
+                review("alice", ReviewState::ChangesRequested),
+                review("bob", ReviewState::Approved),
+                review("alice", ReviewState::Commented),
+                review("alice", ReviewState::Approved),
+            ]),
+            Some(ReviewState::Approved)
+        );
+        assert_eq!(
+            ReviewState::summarize(&[
+                review("alice", ReviewState::Approved),
+                review("bob", ReviewState::ChangesRequested),
+            ]),
+            Some(ReviewState::ChangesRequested)
+        );
+    }
+}
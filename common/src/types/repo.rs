@@ -1,11 +1,13 @@
 use chrono::{DateTime, Utc};
-use failure::{err_msg, Error};
+use failure::Error;
 use json::Value;
-use log::error;
 use search;
 use std::collections::HashMap;
+use types::de::{self, PathError};
+use types::history::{Stage, StageRecord};
+use types::pr::{CheckStatus, MergeableState, ReviewState};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Repository {
     pub id: String,
     pub name_with_owner: String,
@@ -17,21 +19,114 @@ pub struct Repository {
     pub parent: Option<RepositoryParent>,
     pub has_issues_enabled: bool,
     pub is_fork: bool,
+    /// Known if requested with `RepositoryField::Stars`
+    #[serde(default)]
+    pub stars: Option<u64>,
+    #[serde(default)]
+    pub forks: u64,
+    /// Kilobytes
+    #[serde(default)]
+    pub disk_usage: Option<u64>,
+    #[serde(default)]
+    pub is_archived: bool,
+    /// Not exposed by the v3 API
+    #[serde(default)]
+    pub is_locked: Option<bool>,
+    /// Known if requested with `RepositoryField::License`
+    #[serde(default)]
+    pub license: Option<License>,
+    /// Known if requested with `RepositoryField::Topics`
+    #[serde(default)]
+    pub topics: Option<Vec<String>>,
+    /// Known if requested with `RepositoryField::LatestPush`, `None` for empty repositories
+    #[serde(default)]
+    pub pushed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub owner_type: Option<OwnerType>,
+    /// Known if requested with `RepositoryField::Languages`
+    #[serde(default)]
+    pub primary_language: Option<PrimaryLanguage>,
+    /// Known if requested with `RepositoryField::RustfmtConfig`
+    #[serde(default)]
+    pub has_rustfmt_config: Option<bool>,
     pub stats: Option<Stats>,
+    /// Every run of the pipeline stages, oldest first
+    #[serde(default)]
+    pub history: Vec<StageRecord>,
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+impl Repository {
+    pub fn record_stage(&mut self, record: StageRecord) {
+        self.history.push(record);
+    }
+
+    pub fn last_stage(&self, stage: Stage) -> Option<&StageRecord> {
+        self.history
+            .iter()
+            .rev()
+            .find(|record| record.stage == stage)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct License {
+    pub key: String,
+    pub name: Option<String>,
+    pub spdx_id: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OwnerType {
+    User,
+    Organization,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PrimaryLanguage {
+    pub name: String,
+    /// Size of the code in the language
+    pub bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Stats {
     pub format: Option<FormatStats>,
     pub fix: Option<FixStats>,
     pub prs: Vec<PR>,
     pub aux: HashMap<String, Value>,
 }
+/// Lifecycle fields are `None` for the records stored before they were tracked
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PR {
     pub title: String,
     pub number: i64,
     pub status: PRStatus,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub head_branch: Option<String>,
+    #[serde(default)]
+    pub head_sha: Option<String>,
+    #[serde(default)]
+    pub base_branch: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub merged_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub closed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub merged_by: Option<String>,
+    /// `None` until someone reviews the PR
+    #[serde(default)]
+    pub review_state: Option<ReviewState>,
+    #[serde(default)]
+    pub comments: u64,
+    #[serde(default)]
+    pub mergeable_state: Option<MergeableState>,
+    /// `None` if no CI reports to the head commit
+    #[serde(default)]
+    pub checks: Option<CheckStatus>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -41,21 +136,7 @@ pub enum PRStatus {
     Closed,
 }
 
-impl PRStatus {
-    pub fn from_str(from: &str) -> Option<Self> {
-        match from.to_ascii_lowercase().as_ref() {
-            "open" => Some(PRStatus::Open),
-            "merged" => Some(PRStatus::Merged),
-            "closed" => Some(PRStatus::Closed),
-            other => {
-                error!("couldn't parse {:?} as PRStatus", other);
-                None
-            }
-        }
-    }
-}
-
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct FormatStats {
     pub files_changed: u64,
     pub lines_added: u64,
@@ -63,7 +144,7 @@ pub struct FormatStats {
     pub branch: String,
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct FixStats {
     pub files_changed: u64,
     pub lines_added: u64,
@@ -76,32 +157,70 @@ pub struct FixStats {
     pub branch: String,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct RepositoryParent {
     pub name_with_owner: String,
     pub ssh_url: String,
     pub url: String,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RepositoryFormat {
+    V3,
+    V4,
+}
+
+impl RepositoryFormat {
+    /// Guess the format by the fields unique to it
+    pub fn detect(json: &Value) -> Option<Self> {
+        let has = |field: &str| json.get(field).is_some();
+        match (has("nameWithOwner"), has("full_name")) {
+            (true, false) => Some(RepositoryFormat::V4),
+            (false, true) => Some(RepositoryFormat::V3),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(
+    display = "failed to deserialize Repository, as v4 {}; as v3 {}",
+    v4, v3
+)]
+pub struct RepositoryFormatError {
+    /// Format suggested by the marker fields
+    pub detected: Option<RepositoryFormat>,
+    pub v4: PathError,
+    pub v3: PathError,
+}
+
 impl search::NodeType for Repository {
     fn from_value(json: Value) -> Result<Self, Error> {
-        let repo = match v4::Repository::from_value(json.clone()) {
-            Ok(repo) => Repository::from(repo),
-            Err(e) => {
-                warn!("failed to deserialize repo as Github v4 API repo: {}", e);
-                match v3::Repository::from_value(json) {
-                    Ok(repo) => Repository::from(repo),
-                    Err(e) => {
-                        error!("failed to deserialize repo as Github v3 API repo: {}", e);
-                        bail!(err_msg(
-                            "failed to deserialize Repository: not v4 nor v3 format"
-                        ));
-                    }
-                }
-            }
+        let detected = RepositoryFormat::detect(&json);
+        let as_v4 = || de::from_value::<v4::Repository>(&json).map(Repository::from);
+        let as_v3 = || de::from_value::<v3::Repository>(&json).map(Repository::from);
+
+        // The detected format goes first, the other one is tried for the diagnostics
+        let (v4, v3) = match detected {
+            Some(RepositoryFormat::V3) => match as_v3() {
+                Ok(repo) => return Ok(repo),
+                Err(v3) => (as_v4(), Err(v3)),
+            },
+            _ => match as_v4() {
+                Ok(repo) => return Ok(repo),
+                Err(v4) => (Err(v4), as_v3()),
+            },
         };
 
-        Ok(repo)
+        match (v4, v3) {
+            (Ok(repo), _) | (_, Ok(repo)) => {
+                if let Some(detected) = detected {
+                    warn!("{} isn't in {:?} format", repo.name_with_owner, detected);
+                }
+                Ok(repo)
+            }
+            (Err(v4), Err(v3)) => raise!(RepositoryFormatError { detected, v4, v3 }),
+        }
     }
 }
 
@@ -110,7 +229,9 @@ pub mod v4 {
     use failure::Error;
     use json::{self, Value};
     use search::NodeType;
+    use serde::{Deserialize, Deserializer};
 
+    /// Optional field groups are `None` unless requested with `RepositoryField`
     #[derive(Clone, Debug, Serialize, Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub struct Repository {
@@ -121,9 +242,65 @@ pub mod v4 {
         pub url: String,
         pub default_branch_ref: BranchRef,
         pub created_at: DateTime<Utc>,
-        pub parent: Option<super::RepositoryParent>,
+        pub parent: Option<RepositoryParent>,
         pub has_issues_enabled: bool,
         pub is_fork: bool,
+        /// Kilobytes, unknown for repositories being created
+        #[serde(default)]
+        pub disk_usage: Option<u64>,
+        #[serde(default)]
+        pub fork_count: u64,
+        #[serde(default)]
+        pub is_archived: bool,
+        #[serde(default)]
+        pub is_locked: bool,
+        #[serde(default)]
+        pub viewer_permission: Option<String>,
+        #[serde(default)]
+        pub stargazers: Option<TotalCount>,
+        #[serde(default)]
+        pub license_info: Option<License>,
+        #[serde(default)]
+        pub repository_topics: Option<Nodes<TopicNode>>,
+        #[serde(default)]
+        pub primary_language: Option<Language>,
+        #[serde(default)]
+        pub languages: Option<Languages>,
+        #[serde(default)]
+        pub pushed_at: Option<DateTime<Utc>>,
+        #[serde(default)]
+        pub owner: Option<Owner>,
+        /// Outer `None` if not requested, inner if the file doesn't exist
+        #[serde(
+            default,
+            deserialize_with = "requested",
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub rustfmt_toml: Option<Option<GitObject>>,
+        #[serde(
+            default,
+            deserialize_with = "requested",
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub dot_rustfmt_toml: Option<Option<GitObject>>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RepositoryParent {
+        pub name_with_owner: String,
+        pub ssh_url: String,
+        pub url: String,
+    }
+
+    impl From<RepositoryParent> for super::RepositoryParent {
+        fn from(parent: RepositoryParent) -> super::RepositoryParent {
+            super::RepositoryParent {
+                name_with_owner: parent.name_with_owner,
+                ssh_url: parent.ssh_url,
+                url: parent.url,
+            }
+        }
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -132,6 +309,121 @@ pub mod v4 {
         pub name: String,
     }
 
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct TotalCount {
+        pub total_count: u64,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Nodes<T> {
+        pub nodes: Vec<T>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct License {
+        pub key: String,
+        pub name: Option<String>,
+        pub spdx_id: Option<String>,
+    }
+
+    impl From<License> for super::License {
+        fn from(license: License) -> super::License {
+            super::License {
+                key: license.key,
+                name: license.name,
+                spdx_id: license.spdx_id,
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Owner {
+        #[serde(rename = "__typename")]
+        pub typename: super::OwnerType,
+        pub login: String,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct TopicNode {
+        pub topic: Topic,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Topic {
+        pub name: String,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Language {
+        pub name: String,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Languages {
+        pub total_size: u64,
+        pub edges: Vec<LanguageEdge>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct LanguageEdge {
+        pub size: u64,
+        pub node: Language,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct GitObject {
+        pub id: String,
+    }
+
+    /// Tells a field that is `null` from a missing one
+    fn requested<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de>,
+    {
+        Option::<T>::deserialize(deserializer).map(Some)
+    }
+
+    impl Repository {
+        pub fn stars(&self) -> Option<u64> {
+            self.stargazers
+                .as_ref()
+                .map(|stargazers| stargazers.total_count)
+        }
+
+        pub fn topics(&self) -> Option<Vec<&str>> {
+            self.repository_topics.as_ref().map(|topics| {
+                topics
+                    .nodes
+                    .iter()
+                    .map(|node| node.topic.name.as_str())
+                    .collect()
+            })
+        }
+
+        pub fn primary_language(&self) -> Option<super::PrimaryLanguage> {
+            let name = self.primary_language.as_ref()?.name.clone();
+            let bytes = self.languages.as_ref().and_then(|languages| {
+                languages
+                    .edges
+                    .iter()
+                    .find(|edge| edge.node.name == name)
+                    .map(|edge| edge.size)
+            });
+            Some(super::PrimaryLanguage { name, bytes })
+        }
+
+        pub fn has_rustfmt_config(&self) -> Option<bool> {
+            match (&self.rustfmt_toml, &self.dot_rustfmt_toml) {
+                (Some(toml), Some(dot_toml)) => Some(toml.is_some() || dot_toml.is_some()),
+                _ => None,
+            }
+        }
+    }
+
     impl NodeType for Repository {
         fn from_value(json: Value) -> Result<Self, Error> {
             let repo = json::from_value(json)?;
@@ -141,6 +433,12 @@ pub mod v4 {
 
     impl From<Repository> for super::Repository {
         fn from(v4: Repository) -> super::Repository {
+            let stars = v4.stars();
+            let topics = v4
+                .topics()
+                .map(|topics| topics.into_iter().map(String::from).collect());
+            let primary_language = v4.primary_language();
+            let has_rustfmt_config = v4.has_rustfmt_config();
             super::Repository {
                 id: v4.id,
                 name_with_owner: v4.name_with_owner,
@@ -149,10 +447,22 @@ pub mod v4 {
                 url: v4.url,
                 default_branch: v4.default_branch_ref.name,
                 created_at: v4.created_at,
-                parent: v4.parent,
+                parent: v4.parent.map(Into::into),
                 has_issues_enabled: v4.has_issues_enabled,
                 is_fork: v4.is_fork,
+                stars,
+                forks: v4.fork_count,
+                disk_usage: v4.disk_usage,
+                is_archived: v4.is_archived,
+                is_locked: Some(v4.is_locked),
+                license: v4.license_info.map(super::License::from),
+                topics,
+                pushed_at: v4.pushed_at,
+                owner_type: v4.owner.map(|owner| owner.typename),
+                primary_language,
+                has_rustfmt_config,
                 stats: None,
+                history: Vec::new(),
             }
         }
     }
@@ -167,6 +477,8 @@ pub mod v3 {
     #[derive(Clone, Debug, Serialize, Deserialize)]
     pub struct Repository {
         pub id: i64,
+        /// Same as the GraphQL id
+        pub node_id: String,
         pub full_name: String,
         pub description: Option<String>,
         pub ssh_url: String,
@@ -176,6 +488,24 @@ pub mod v3 {
         pub parent: Option<RepositoryParent>,
         pub has_issues: bool,
         pub fork: bool,
+        pub owner: Owner,
+        pub stargazers_count: u64,
+        pub forks_count: u64,
+        /// Kilobytes
+        pub size: u64,
+        pub archived: bool,
+        pub license: Option<super::License>,
+        /// Only sent with the `mercy` preview media type
+        #[serde(default)]
+        pub topics: Option<Vec<String>>,
+        pub pushed_at: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct Owner {
+        pub login: String,
+        #[serde(rename = "type")]
+        pub owner_type: super::OwnerType,
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -195,7 +525,7 @@ pub mod v3 {
     impl From<Repository> for super::Repository {
         fn from(v3: Repository) -> super::Repository {
             super::Repository {
-                id: format!("{}", v3.id),
+                id: v3.node_id,
                 name_with_owner: v3.full_name,
                 description: v3.description,
                 ssh_url: v3.ssh_url,
@@ -209,8 +539,292 @@ pub mod v3 {
                 }),
                 has_issues_enabled: v3.has_issues,
                 is_fork: v3.fork,
+                stars: Some(v3.stargazers_count),
+                forks: v3.forks_count,
+                disk_usage: Some(v3.size),
+                is_archived: v3.archived,
+                is_locked: None,
+                license: v3.license,
+                topics: v3.topics,
+                pushed_at: v3.pushed_at,
+                owner_type: Some(v3.owner.owner_type),
+                primary_language: None,
+                has_rustfmt_config: None,
                 stats: None,
+                history: Vec::new(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use fake_github::{FakeGithub, FakeRepo};
+    use github::tokens::TokenPool;
+    use github::v3::{ExecutorExt, Github as GithubV3};
+    use github::v4::Github as GithubV4;
+    use quickcheck::{Arbitrary, Gen, StdThreadGen};
+    use reqwest::StatusCode;
+    use search::fields::FieldSet;
+    use search::query::{Query, SearchFor};
+    use search::NodeType;
+    use std::sync::Arc;
+
+    #[test]
+    fn v3_and_v4_agree() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(
+            FakeRepo::new("owner/repo")
+                .stars(42)
+                .forks(7)
+                .size(128)
+                .topic("rust")
+                .license("MIT")
+                .archived(true),
+        );
+        let pool = Arc::new(TokenPool::single("bot"));
+
+        let v3: v3::Repository = GithubV3::with_pool(pool.clone(), &fake.url())
+            .unwrap()
+            .get()
+            .custom_endpoint("repos/owner/repo")
+            .send(&[StatusCode::OK])
+            .unwrap();
+        let v3 = Repository::from(v3);
+
+        let query = Query::builder()
+            .search_for(SearchFor::Repository)
+            .raw_query("user:owner")
+            .fields(FieldSet::all())
+            .build()
+            .unwrap();
+        let gh = GithubV4::with_pool(pool, &fake.url()).unwrap();
+        let v4 = search::search::<v4::Repository>(&gh, query)
+            .unwrap()
+            .nodes
+            .remove(0);
+        let v4 = Repository::from(v4);
+
+        for repo in &[v3, v4] {
+            assert_eq!(repo.stars, Some(42));
+            assert_eq!(repo.forks, 7);
+            assert_eq!(repo.disk_usage, Some(128));
+            assert!(repo.is_archived);
+            assert_eq!(repo.license.as_ref().unwrap().key, "mit");
+            assert_eq!(repo.topics, Some(vec!["rust".to_string()]));
+            assert!(repo.pushed_at.is_some());
+            assert_eq!(repo.owner_type, Some(OwnerType::User));
+        }
+    }
+
+    fn date<G: Gen>(g: &mut G) -> DateTime<Utc> {
+        Utc.timestamp(i64::from(u32::arbitrary(g)), 0)
+    }
+
+    fn maybe<G: Gen, T, F: FnOnce(&mut G) -> T>(g: &mut G, f: F) -> Option<T> {
+        if bool::arbitrary(g) {
+            Some(f(g))
+        } else {
+            None
+        }
+    }
+
+    /// Fields both formats carry
+    fn repository<G: Gen>(g: &mut G) -> Repository {
+        Repository {
+            id: String::arbitrary(g),
+            name_with_owner: String::arbitrary(g),
+            description: Option::arbitrary(g),
+            ssh_url: String::arbitrary(g),
+            url: String::arbitrary(g),
+            default_branch: String::arbitrary(g),
+            created_at: date(g),
+            parent: maybe(g, |g| RepositoryParent {
+                name_with_owner: String::arbitrary(g),
+                ssh_url: String::arbitrary(g),
+                url: String::arbitrary(g),
+            }),
+            has_issues_enabled: bool::arbitrary(g),
+            is_fork: bool::arbitrary(g),
+            stars: Option::arbitrary(g),
+            forks: u64::arbitrary(g),
+            disk_usage: Option::arbitrary(g),
+            is_archived: bool::arbitrary(g),
+            is_locked: None,
+            license: maybe(g, |g| License {
+                key: String::arbitrary(g),
+                name: Option::arbitrary(g),
+                spdx_id: Option::arbitrary(g),
+            }),
+            topics: Option::arbitrary(g),
+            pushed_at: maybe(g, date),
+            owner_type: maybe(g, |g| {
+                if bool::arbitrary(g) {
+                    OwnerType::User
+                } else {
+                    OwnerType::Organization
+                }
+            }),
+            primary_language: None,
+            has_rustfmt_config: None,
+            stats: None,
+            history: Vec::new(),
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct V4Repository(Repository);
+
+    impl Arbitrary for V4Repository {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let mut repo = repository(g);
+            repo.is_locked = Some(bool::arbitrary(g));
+            repo.primary_language = maybe(g, |g| PrimaryLanguage {
+                name: String::arbitrary(g),
+                bytes: Option::arbitrary(g),
+            });
+            repo.has_rustfmt_config = Option::arbitrary(g);
+            V4Repository(repo)
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct V3Repository(Repository);
+
+    impl Arbitrary for V3Repository {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let mut repo = repository(g);
+            repo.id = format!("MDEwOlJlcG9zaXRvcnk{}", u32::arbitrary(g));
+            repo.stars = Some(u64::arbitrary(g));
+            repo.disk_usage = Some(u64::arbitrary(g));
+            repo.owner_type = Some(OwnerType::User);
+            V3Repository(repo)
+        }
+    }
+
+    fn to_v4(repo: &Repository) -> Value {
+        let mut json = json!({
+            "id": repo.id,
+            "nameWithOwner": repo.name_with_owner,
+            "description": repo.description,
+            "sshUrl": repo.ssh_url,
+            "url": repo.url,
+            "defaultBranchRef": { "name": repo.default_branch },
+            "createdAt": repo.created_at,
+            "parent": repo.parent.as_ref().map(|parent| json!({
+                "nameWithOwner": parent.name_with_owner,
+                "sshUrl": parent.ssh_url,
+                "url": parent.url,
+            })),
+            "hasIssuesEnabled": repo.has_issues_enabled,
+            "isFork": repo.is_fork,
+            "diskUsage": repo.disk_usage,
+            "forkCount": repo.forks,
+            "isArchived": repo.is_archived,
+            "isLocked": repo.is_locked.unwrap(),
+            "owner": repo.owner_type.map(|owner_type| json!({
+                "__typename": owner_type,
+                "login": "owner",
+            })),
+            "pushedAt": repo.pushed_at,
+        });
+
+        // Field groups are missing unless requested
+        if let Some(stars) = repo.stars {
+            json["stargazers"] = json!({ "totalCount": stars });
+        }
+        if let Some(ref license) = repo.license {
+            json["licenseInfo"] = json!({
+                "key": license.key,
+                "name": license.name,
+                "spdxId": license.spdx_id,
+            });
+        }
+        if let Some(ref topics) = repo.topics {
+            let nodes: Vec<Value> = topics
+                .iter()
+                .map(|topic| json!({ "topic": { "name": topic } }))
+                .collect();
+            json["repositoryTopics"] = json!({ "nodes": nodes });
+        }
+        if let Some(ref language) = repo.primary_language {
+            json["primaryLanguage"] = json!({ "name": language.name });
+            if let Some(bytes) = language.bytes {
+                json["languages"] = json!({
+                    "totalSize": bytes,
+                    "edges": [{ "size": bytes, "node": { "name": language.name } }],
+                });
             }
         }
+        if let Some(has_config) = repo.has_rustfmt_config {
+            json["rustfmtToml"] = if has_config {
+                json!({ "id": "ab1c" })
+            } else {
+                Value::Null
+            };
+            json["dotRustfmtToml"] = Value::Null;
+        }
+
+        json
+    }
+
+    fn to_v3(repo: &Repository) -> Value {
+        json!({
+            "id": 1,
+            "node_id": repo.id,
+            "full_name": repo.name_with_owner,
+            "description": repo.description,
+            "ssh_url": repo.ssh_url,
+            "html_url": repo.url,
+            "default_branch": repo.default_branch,
+            "created_at": repo.created_at,
+            "parent": repo.parent.as_ref().map(|parent| json!({
+                "full_name": parent.name_with_owner,
+                "ssh_url": parent.ssh_url,
+                "html_url": parent.url,
+            })),
+            "has_issues": repo.has_issues_enabled,
+            "fork": repo.is_fork,
+            "owner": { "login": "owner", "type": repo.owner_type },
+            "stargazers_count": repo.stars,
+            "forks_count": repo.forks,
+            "size": repo.disk_usage,
+            "archived": repo.is_archived,
+            "license": repo.license,
+            "topics": repo.topics,
+            "pushed_at": repo.pushed_at,
+        })
+    }
+
+    quickcheck! {
+        fn v4_round_trip(repo: V4Repository) -> bool {
+            Repository::from_value(to_v4(&repo.0)).unwrap() == repo.0
+        }
+
+        fn v3_round_trip(repo: V3Repository) -> bool {
+            Repository::from_value(to_v3(&repo.0)).unwrap() == repo.0
+        }
+    }
+
+    #[test]
+    fn format_error() {
+        let mut g = StdThreadGen::new(10);
+
+        let mut json = to_v4(&V4Repository::arbitrary(&mut g).0);
+        json["defaultBranchRef"] = json!({ "id": 1 });
+        let error = Repository::from_value(json).unwrap_err();
+        let error = error.downcast::<RepositoryFormatError>().unwrap();
+        assert_eq!(error.detected, Some(RepositoryFormat::V4));
+        assert_eq!(error.v4.path, ".defaultBranchRef");
+        assert_eq!(error.v3.path, ".id");
+
+        let mut json = to_v3(&V3Repository::arbitrary(&mut g).0);
+        json["owner"]["type"] = json!("Enterprise");
+        let error = Repository::from_value(json).unwrap_err();
+        let error = error.downcast::<RepositoryFormatError>().unwrap();
+        assert_eq!(error.detected, Some(RepositoryFormat::V3));
+        assert_eq!(error.v3.path, ".owner.type");
     }
 }
@@ -0,0 +1,19 @@
+use failure::Error;
+use json::{self, Value};
+use search::NodeType;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub id: String,
+    pub login: String,
+    pub name: Option<String>,
+    pub url: String,
+}
+
+impl NodeType for User {
+    fn from_value(json: Value) -> Result<Self, Error> {
+        let user = json::from_value(json)?;
+        Ok(user)
+    }
+}
@@ -15,6 +15,7 @@ use rustyrobot::{
         GithubRequest,
     },
     shutdown::{GracefulShutdown, GracefulShutdownHandle},
+    types::history::RepositoryHistory,
 };
 
 use chrono::{Duration, Utc};
@@ -59,6 +60,26 @@ fn main() {
 
     // TODO
     // start_notification_fetch_loop(shutdown.thread_handle());
+
+    // Blocks until shutdown, the loops above run on their own threads
+    run_history_consumer(shutdown.thread_handle()).expect("event handler failed");
+}
+
+/// Keep the latest stage history of every repository, failed runs included
+fn run_history_consumer(shutdown: GracefulShutdownHandle) -> Result<(), Error> {
+    HandlingConsumer::builder()
+        .subscribe(topic::EVENT)
+        .respond_to(topic::REPOSITORY_HISTORY)
+        .group(group::HISTORY)
+        .key_from(|history: &RepositoryHistory| history.name_with_owner.clone().into_bytes())
+        .handler(|event: Event, callback| {
+            if let Some(repo) = event.repository() {
+                callback(RepositoryHistory::of(repo));
+            }
+            Ok(())
+        })
+        .build()?
+        .start(shutdown)
 }
 
 fn start_notification_fetch_loop(shutdown: GracefulShutdownHandle) -> Result<(), Error> {
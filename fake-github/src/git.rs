@@ -0,0 +1,105 @@
+//! Local bare repositories standing in for GitHub remotes in git-based tests.
+
+use failure::Error;
+use tempfile::TempDir;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub struct GitRemote {
+    _dir: TempDir,
+    path: PathBuf,
+}
+
+impl GitRemote {
+    /// Create a bare repository `<name>.git` with a single commit on `master`
+    /// containing a small, badly formatted cargo project.
+    pub fn new(name: &str) -> Result<Self, Error> {
+        let dir = TempDir::new()?;
+        let path = dir.path().join(format!("{}.git", name));
+        let work = dir.path().join("work");
+
+        git(dir.path(), &["init", "--bare", path_str(&path)?])?;
+        git(&path, &["symbolic-ref", "HEAD", "refs/heads/master"])?;
+
+        git(dir.path(), &["init", path_str(&work)?])?;
+        git(&work, &["checkout", "-b", "master"])?;
+        fs::create_dir_all(work.join("src"))?;
+        fs::write(
+            work.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nauthors = []\n",
+                name.replace('.', "-")
+            ),
+        )?;
+        fs::write(
+            work.join("src/main.rs"),
+            "fn main( ) {\nprintln!(\"hello\") ;\n}\n",
+        )?;
+        git(&work, &["add", "."])?;
+        git(&work, &["commit", "-m", "initial commit"])?;
+        git(&work, &["remote", "add", "origin", path_str(&path)?])?;
+        git(&work, &["push", "origin", "master"])?;
+        fs::remove_dir_all(&work)?;
+
+        Ok(GitRemote { _dir: dir, path })
+    }
+
+    /// Url suitable for `git clone`
+    pub fn url(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn path_str(path: &Path) -> Result<&str, Error> {
+    path.to_str()
+        .ok_or_else(|| format_err!("path is not a utf8 string: {:?}", path))
+}
+
+fn git(cwd: &Path, args: &[&str]) -> Result<(), Error> {
+    let output = Command::new("git")
+        .current_dir(cwd)
+        .args(&[
+            "-c",
+            "user.name=rustyrobot",
+            "-c",
+            "user.email=rustyrobot@localhost",
+        ])
+        .args(args)
+        .output()?;
+
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_is_clonable() {
+        let remote = GitRemote::new("fixture").unwrap();
+        let target = TempDir::new().unwrap();
+        let clone = target.path().join("clone");
+
+        git(
+            target.path(),
+            &["clone", &remote.url(), path_str(&clone).unwrap()],
+        )
+        .unwrap();
+        assert!(clone.join("Cargo.toml").exists());
+        assert!(clone.join("src/main.rs").exists());
+    }
+}
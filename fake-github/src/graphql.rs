@@ -0,0 +1,505 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use json::{Map, Value};
+
+use http::{Request, Response};
+use state::{FakePull, FakeRepo, PullState, State};
+
+use std::collections::BTreeSet;
+
+pub fn handle(state: &mut State, request: &Request) -> Response {
+    if !state.graphql_limit.consume() {
+        return Response::message(403, "API rate limit exceeded for user ID 1.");
+    }
+
+    let body = match request.json() {
+        Ok(body) => body,
+        Err(_) => return Response::message(400, "Problems parsing JSON"),
+    };
+
+    let query = match body["query"].as_str() {
+        Some(query) => query.replace("\\\"", "\""),
+        None => return Response::message(400, "A query attribute must be specified"),
+    };
+
+    let mut data = Map::new();
+
+    if has_root_field(&query, "viewer") {
+        data.insert("viewer".into(), json!({ "login": state.login }));
+    }
+
+    if has_root_field(&query, "rateLimit") {
+        let limit = state.graphql_limit;
+        data.insert(
+            "rateLimit".into(),
+            json!({
+                "cost": 1,
+                "limit": limit.limit,
+                "remaining": limit.remaining,
+                "resetAt": limit.reset_at,
+            }),
+        );
+    }
+
+    if let Some(args) = field_arguments(&query, "search") {
+        match search(state, &args) {
+            Ok(result) => {
+                data.insert("search".into(), result);
+            }
+            Err(message) => {
+                return Response::json(
+                    200,
+                    json!({
+                        "data": null,
+                        "errors": [{
+                            "message": message,
+                            "type": "INVALID_ARGUMENTS",
+                            "path": ["search"],
+                        }],
+                    }),
+                )
+            }
+        }
+    }
+
+    Response::json(200, json!({ "data": data }))
+}
+
+/// Check if the query selects `name { ... }`, not just a field prefixed with `name`
+fn has_root_field(query: &str, name: &str) -> bool {
+    query.match_indices(name).any(|(idx, _)| {
+        let before = query[..idx].chars().next_back();
+        let after = query[idx + name.len()..].trim_left().chars().next();
+        let boundary = before.map(|c| !c.is_alphanumeric()).unwrap_or(true);
+        boundary && (after == Some('{') || after == Some('('))
+    })
+}
+
+/// Extract arguments of the `name(...)` field as key-value pairs
+fn field_arguments(query: &str, name: &str) -> Option<Vec<(String, String)>> {
+    let start = query
+        .match_indices(name)
+        .map(|(idx, _)| idx + name.len())
+        .find(|idx| query[*idx..].trim_left().starts_with('('))?;
+    let start = start + query[start..].find('(')? + 1;
+
+    let mut args = Vec::new();
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut in_value = false;
+    let mut in_string = false;
+
+    for c in query[start..].chars() {
+        match c {
+            '"' => in_string = !in_string,
+            ')' if !in_string => break,
+            ',' if !in_string => {
+                args.push((key.trim().to_string(), value.trim().to_string()));
+                key.clear();
+                value.clear();
+                in_value = false;
+            }
+            ':' if !in_string && !in_value => in_value = true,
+            c if in_value => value.push(c),
+            c => key.push(c),
+        }
+    }
+
+    if !key.trim().is_empty() {
+        args.push((key.trim().to_string(), value.trim().to_string()));
+    }
+
+    Some(args)
+}
+
+fn search(state: &State, args: &[(String, String)]) -> Result<Value, String> {
+    let arg = |name: &str| {
+        args.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let first = match arg("first") {
+        Some(first) => first
+            .parse::<usize>()
+            .map_err(|_| format!("invalid value for first: {}", first))?,
+        None => return Err("first or last argument is required".to_string()),
+    };
+
+    if first > 100 {
+        return Err(format!(
+            "Requesting {} records on the connection exceeds the `first` limit of 100 records.",
+            first
+        ));
+    }
+
+    let offset = match arg("after") {
+        Some(cursor) => {
+            decode_cursor(cursor).ok_or_else(|| format!("invalid cursor {}", cursor))?
+        }
+        None => 0,
+    };
+
+    let query = arg("query").unwrap_or("");
+    let filter = Filter::parse(query);
+
+    let (count_field, matched): (&str, Vec<Value>) = match arg("type") {
+        Some("REPOSITORY") => (
+            "repositoryCount",
+            state
+                .repos
+                .values()
+                .filter(|r| filter.matches(r))
+                .map(|repo| repo_v4(state, repo))
+                .collect(),
+        ),
+        // There are no plain issues in the fake, only pull requests
+        Some("ISSUE") => (
+            "issueCount",
+            state
+                .pulls
+                .iter()
+                .filter(|p| filter.matches_pull(p))
+                .map(pull_v4)
+                .collect(),
+        ),
+        Some("USER") => {
+            let owners: BTreeSet<&str> = state.repos.values().map(FakeRepo::owner).collect();
+            (
+                "userCount",
+                owners
+                    .into_iter()
+                    .filter(|login| filter.matches_user(login))
+                    .map(user_v4)
+                    .collect(),
+            )
+        }
+        Some(other) => return Err(format!("unknown search type {}", other)),
+        None => return Err("type argument is required".to_string()),
+    };
+
+    // Search never reaches past the cap, even though the count reports every match
+    let count = matched.len();
+    let reachable = count.min(state.search_cap);
+    let start = offset.min(reachable);
+    let end = (offset + first).min(reachable);
+    let nodes = matched[start..end].to_vec();
+
+    Ok(json!({
+        count_field: count,
+        "pageInfo": {
+            "startCursor": if nodes.is_empty() { Value::Null } else { encode_cursor(start + 1).into() },
+            "endCursor": if nodes.is_empty() { Value::Null } else { encode_cursor(end).into() },
+            "hasNextPage": end < reachable,
+            "hasPreviousPage": offset > 0,
+        },
+        "nodes": nodes,
+    }))
+}
+
+fn encode_cursor(offset: usize) -> String {
+    format!("Y3Vyc29yOj{}", offset)
+}
+
+fn decode_cursor(cursor: &str) -> Option<usize> {
+    cursor
+        .trim_matches('"')
+        .trim_left_matches("Y3Vyc29yOj")
+        .parse()
+        .ok()
+}
+
+pub fn repo_v4(state: &State, repo: &FakeRepo) -> Value {
+    let parent = repo
+        .parent
+        .as_ref()
+        .and_then(|parent| state.repos.get(parent))
+        .map(|parent| {
+            json!({
+                "nameWithOwner": parent.full_name,
+                "sshUrl": parent.ssh_url(),
+                "url": parent.html_url(),
+            })
+        });
+
+    json!({
+        "id": repo.node_id(),
+        "databaseId": repo.id,
+        "nameWithOwner": repo.full_name,
+        "description": repo.description,
+        "createdAt": repo.created_at,
+        "pushedAt": repo.pushed_at,
+        "defaultBranchRef": {
+            "id": format!("MDM6UmVm{}", repo.id),
+            "name": repo.default_branch,
+            "prefix": "refs/heads/",
+        },
+        "diskUsage": repo.size,
+        "forkCount": repo.forks,
+        "stargazers": { "totalCount": repo.stars },
+        "hasIssuesEnabled": repo.has_issues,
+        "isArchived": repo.is_archived,
+        "isFork": repo.parent.is_some(),
+        "isLocked": false,
+        "isPrivate": repo.is_private,
+        "primaryLanguage": repo.language.as_ref().map(|name| json!({ "name": name })),
+        // The whole repository is in its primary language
+        "languages": {
+            "totalSize": repo.size * 1024,
+            "edges": repo.language.as_ref().map(|name| vec![json!({
+                "size": repo.size * 1024,
+                "node": { "name": name },
+            })]).unwrap_or_default(),
+        },
+        "licenseInfo": repo.license.as_ref().map(|license| json!({
+            "key": license.to_lowercase(),
+            "spdxId": license,
+        })),
+        "repositoryTopics": {
+            "nodes": repo.topics.iter().map(|topic| json!({ "topic": { "name": topic } })).collect::<Vec<_>>(),
+        },
+        "owner": {
+            "__typename": "User",
+            "login": repo.owner(),
+        },
+        "parent": parent,
+        "sshUrl": repo.ssh_url(),
+        "url": repo.html_url(),
+        "viewerHasStarred": false,
+        "viewerPermission": "READ",
+        "viewerSubscription": "UNSUBSCRIBED",
+        // Aliases of `object(expression: "HEAD:<path>")` used by the client
+        "rustfmtToml": object(repo, "rustfmt.toml"),
+        "dotRustfmtToml": object(repo, ".rustfmt.toml"),
+    })
+}
+
+fn object(repo: &FakeRepo, path: &str) -> Value {
+    if repo.files.iter().any(|file| file == path) {
+        json!({ "id": format!("MDQ6QmxvYj{}:{}", repo.id, path) })
+    } else {
+        Value::Null
+    }
+}
+
+pub fn pull_v4(pull: &FakePull) -> Value {
+    let state = match pull.state {
+        PullState::Open => "OPEN",
+        PullState::Closed => "CLOSED",
+        PullState::Merged => "MERGED",
+    };
+
+    json!({
+        "__typename": "PullRequest",
+        "id": format!("MDExOlB1bGxSZXF1ZXN0{}", pull.number),
+        "number": pull.number,
+        "title": pull.title,
+        "state": state,
+        "url": format!("https://github.com/{}/pull/{}", pull.repo, pull.number),
+        "createdAt": pull.created_at,
+        "updatedAt": pull.updated_at,
+        "closedAt": pull.closed_at,
+        "mergedAt": pull.merged_at,
+        "headRefName": pull.head_ref(),
+        "baseRefName": pull.base,
+        "author": { "login": pull.user },
+        "repository": { "nameWithOwner": pull.repo },
+    })
+}
+
+fn user_v4(login: &str) -> Value {
+    json!({
+        "__typename": "User",
+        "id": format!("MDQ6VXNlcj{}", login),
+        "login": login,
+        "name": null,
+        "url": format!("https://github.com/{}", login),
+    })
+}
+
+/// Subset of the GitHub search syntax understood by the fake
+struct Filter {
+    terms: Vec<(bool, String, String)>,
+    words: Vec<String>,
+}
+
+impl Filter {
+    fn parse(query: &str) -> Self {
+        let mut terms = Vec::new();
+        let mut words = Vec::new();
+
+        for token in query.split_whitespace() {
+            let (negated, token) = if token.starts_with('-') {
+                (true, &token[1..])
+            } else {
+                (false, token)
+            };
+
+            match token.find(':') {
+                Some(idx) => terms.push((
+                    negated,
+                    token[..idx].to_lowercase(),
+                    token[idx + 1..].to_string(),
+                )),
+                None => words.push(token.to_lowercase()),
+            }
+        }
+
+        Filter { terms, words }
+    }
+
+    fn matches(&self, repo: &FakeRepo) -> bool {
+        let forks_allowed = self
+            .terms
+            .iter()
+            .find(|(_, key, _)| key == "fork")
+            .map(|(_, _, value)| value.as_str());
+
+        let fork_ok = match forks_allowed {
+            Some("true") => true,
+            Some("only") => repo.parent.is_some(),
+            _ => repo.parent.is_none(),
+        };
+
+        let words_ok = self.words.iter().all(|word| {
+            repo.full_name.to_lowercase().contains(word)
+                || repo
+                    .description
+                    .as_ref()
+                    .map(|d| d.to_lowercase().contains(word))
+                    .unwrap_or(false)
+        });
+
+        fork_ok
+            && words_ok
+            && self
+                .terms
+                .iter()
+                .all(|(negated, key, value)| self.term_matches(repo, key, value) != *negated)
+    }
+
+    fn matches_pull(&self, pull: &FakePull) -> bool {
+        let words_ok = self
+            .words
+            .iter()
+            .all(|word| pull.title.to_lowercase().contains(word));
+
+        words_ok
+            && self.terms.iter().all(|(negated, key, value)| {
+                let matches = match key.as_str() {
+                    "author" => pull.user.eq_ignore_ascii_case(value),
+                    "repo" => pull.repo.eq_ignore_ascii_case(value),
+                    "user" | "org" => pull
+                        .repo
+                        .split('/')
+                        .next()
+                        .map(|owner| owner.eq_ignore_ascii_case(value))
+                        .unwrap_or(false),
+                    "is" | "state" => match value.as_str() {
+                        "pr" => true,
+                        "issue" => false,
+                        "open" => pull.state == PullState::Open,
+                        "closed" => pull.state != PullState::Open,
+                        "merged" => pull.state == PullState::Merged,
+                        "unmerged" => pull.state == PullState::Closed,
+                        _ => true,
+                    },
+                    _ => true,
+                };
+                matches != *negated
+            })
+    }
+
+    fn matches_user(&self, login: &str) -> bool {
+        let login = login.to_lowercase();
+        self.words.iter().all(|word| login.contains(word))
+            && self
+                .terms
+                .iter()
+                .all(|(negated, key, value)| match key.as_str() {
+                    "user" => login.eq_ignore_ascii_case(value) != *negated,
+                    _ => true,
+                })
+    }
+
+    fn term_matches(&self, repo: &FakeRepo, key: &str, value: &str) -> bool {
+        match key {
+            "language" => repo
+                .language
+                .as_ref()
+                .map(|l| l.eq_ignore_ascii_case(value))
+                .unwrap_or(false),
+            "user" | "org" => repo.owner().eq_ignore_ascii_case(value),
+            "repo" => repo.full_name.eq_ignore_ascii_case(value),
+            "stars" => range_matches(repo.stars, value, parse_number),
+            "forks" => range_matches(repo.forks, value, parse_number),
+            "size" => range_matches(repo.size, value, parse_number),
+            "created" => range_matches(repo.created_at, value, parse_date),
+            "pushed" => range_matches(repo.pushed_at, value, parse_date),
+            "topic" => repo.topics.iter().any(|t| t.eq_ignore_ascii_case(value)),
+            "license" => repo
+                .license
+                .as_ref()
+                .map(|l| l.eq_ignore_ascii_case(value))
+                .unwrap_or(false),
+            "archived" => repo.is_archived == (value == "true"),
+            "is" => match value {
+                "public" => !repo.is_private,
+                "private" => repo.is_private,
+                _ => true,
+            },
+            // Handled separately or not affecting repository matching
+            _ => true,
+        }
+    }
+}
+
+/// Parse a date bound into a half-open interval, whole day for `YYYY-MM-DD`
+fn parse_date(value: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    if let Ok(datetime) = value.parse::<DateTime<Utc>>() {
+        return Some((datetime, datetime + Duration::seconds(1)));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|date| {
+            let start = DateTime::from_utc(date.and_hms(0, 0, 0), Utc);
+            (start, start + Duration::days(1))
+        })
+}
+
+fn parse_number(value: &str) -> Option<(u64, u64)> {
+    value.parse::<u64>().ok().map(|n| (n, n + 1))
+}
+
+/// Match `actual` against range syntax: `n`, `>n`, `>=n`, `<n`, `<=n`, `a..b`, `a..*`, `*..b`
+///
+/// `parse` maps a bound into the half-open interval `[start, end)` it denotes.
+fn range_matches<T, F>(actual: T, range: &str, parse: F) -> bool
+where
+    T: PartialOrd,
+    F: Fn(&str) -> Option<(T, T)>,
+{
+    let check = |bound: &str, op: &Fn(&T, &T, &T) -> bool| {
+        parse(bound)
+            .map(|(start, end)| op(&actual, &start, &end))
+            .unwrap_or(false)
+    };
+
+    if let Some(idx) = range.find("..") {
+        let (low, high) = (&range[..idx], &range[idx + 2..]);
+        let low_ok = low == "*" || check(low, &|a, start, _| a >= start);
+        let high_ok = high == "*" || check(high, &|a, _, end| a < end);
+        return low_ok && high_ok;
+    }
+
+    if range.starts_with(">=") {
+        check(&range[2..], &|a, start, _| a >= start)
+    } else if range.starts_with("<=") {
+        check(&range[2..], &|a, _, end| a < end)
+    } else if range.starts_with('>') {
+        check(&range[1..], &|a, _, end| a >= end)
+    } else if range.starts_with('<') {
+        check(&range[1..], &|a, start, _| a < start)
+    } else {
+        check(range, &|a, start, end| a >= start && a < end)
+    }
+}
@@ -0,0 +1,240 @@
+use failure::Error;
+use json::{self, Value};
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::str;
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn read_from(stream: &mut TcpStream) -> Result<Self, Error> {
+        let mut reader = BufReader::new(stream);
+
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut tokens = request_line.split_whitespace();
+        let method = tokens
+            .next()
+            .ok_or(HttpError::MalformedRequestLine)?
+            .to_string();
+        let target = tokens.next().ok_or(HttpError::MalformedRequestLine)?;
+
+        let (path, query) = match target.find('?') {
+            Some(idx) => (&target[..idx], parse_query_string(&target[idx + 1..])),
+            None => (target, Vec::new()),
+        };
+
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            let line = line.trim_right();
+            if line.is_empty() {
+                break;
+            }
+            let idx = line.find(':').ok_or(HttpError::MalformedHeader)?;
+            headers.push((
+                line[..idx].trim().to_ascii_lowercase(),
+                line[idx + 1..].trim().to_string(),
+            ));
+        }
+
+        let content_length = match headers.iter().find(|(name, _)| name == "content-length") {
+            Some((_, value)) => value.parse::<usize>()?,
+            None => 0,
+        };
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+
+        Ok(Request {
+            method,
+            path: path.to_string(),
+            query,
+            headers,
+            body,
+        })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> Result<Value, Error> {
+        if self.body.is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(json::from_slice(&self.body)?)
+    }
+
+    pub fn segments(&self) -> Vec<&str> {
+        self.path.split('/').filter(|s| !s.is_empty()).collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Value>,
+}
+
+impl Response {
+    pub fn json(status: u16, body: Value) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Some(body),
+        }
+    }
+
+    pub fn empty(status: u16) -> Self {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn message(status: u16, message: &str) -> Self {
+        Self::json(
+            status,
+            json!({
+                "message": message,
+                "documentation_url": "https://developer.github.com/v3",
+            }),
+        )
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn write_to(&self, stream: &mut TcpStream) -> Result<(), Error> {
+        let body = match self.body {
+            Some(ref body) => json::to_vec(body)?,
+            None => Vec::new(),
+        };
+
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nServer: fake-github\r\nConnection: close\r\nContent-Length: {}\r\n",
+            self.status,
+            reason_phrase(self.status),
+            body.len()
+        );
+        if self.body.is_some() {
+            head.push_str("Content-Type: application/json; charset=utf-8\r\n");
+        }
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&body)?;
+        stream.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Fail)]
+enum HttpError {
+    #[fail(display = "malformed request line")]
+    MalformedRequestLine,
+    #[fail(display = "malformed header")]
+    MalformedHeader,
+}
+
+fn parse_query_string(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.find('=') {
+            Some(idx) => (
+                percent_decode(&pair[..idx]),
+                percent_decode(&pair[idx + 1..]),
+            ),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'+' => decoded.push(b' '),
+            b'%' if idx + 2 < bytes.len() => {
+                let hex = str::from_utf8(&bytes[idx + 1..idx + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        idx += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+pub fn percent_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        205 => "Reset Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        422 => "Unprocessable Entity",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,542 @@
+//! In-process emulation of the subset of GitHub REST and GraphQL APIs used by rustyrobot.
+//!
+//! The server state is fully scriptable: repositories, pull requests, notifications and
+//! rate limits can be seeded and inspected from tests, and failures can be injected per route.
+
+extern crate chrono;
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_json as json;
+extern crate tempfile;
+
+pub mod git;
+mod graphql;
+mod http;
+mod rest;
+mod state;
+
+pub use state::{
+    Failure, FakeNotification, FakePull, FakeRepo, PullState, RateLimit, RecordedRequest, State,
+};
+
+use failure::Error;
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use http::{Request, Response};
+
+pub struct FakeGithub {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl FakeGithub {
+    /// Start the server on a random local port
+    pub fn start() -> Result<Self, Error> {
+        Self::start_with_state(State::default())
+    }
+
+    pub fn start_with_state(state: State) -> Result<Self, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(state));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let acceptor = {
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            let base_url = format!("http://{}", addr);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            warn!("fake github failed to accept connection: {}", e);
+                            continue;
+                        }
+                    };
+                    let state = state.clone();
+                    let base_url = base_url.clone();
+                    thread::spawn(move || serve(stream, &state, &base_url));
+                }
+            })
+        };
+
+        info!("fake github listening on {}", addr);
+
+        Ok(FakeGithub {
+            addr,
+            state,
+            shutdown,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    /// Base url of the REST API, e.g. `http://127.0.0.1:40000`
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Url of the GraphQL endpoint
+    pub fn graphql_url(&self) -> String {
+        format!("{}/graphql", self.url())
+    }
+
+    pub fn state(&self) -> MutexGuard<State> {
+        self.state.lock().unwrap()
+    }
+
+    pub fn add_repo(&self, repo: FakeRepo) {
+        self.state().add_repo(repo)
+    }
+
+    pub fn add_pull(&self, pull: FakePull) {
+        self.state().add_pull(pull)
+    }
+
+    pub fn add_notification(&self, notification: FakeNotification) {
+        self.state().add_notification(notification)
+    }
+
+    pub fn fail_next(&self, route: &str, failure: Failure) {
+        self.state().fail(route, 1, failure)
+    }
+
+    pub fn fail_times(&self, route: &str, times: usize, failure: Failure) {
+        self.state().fail(route, times, failure)
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state().requests.clone()
+    }
+}
+
+impl Drop for FakeGithub {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the acceptor blocked in accept()
+        TcpStream::connect(self.addr).ok();
+        if let Some(acceptor) = self.acceptor.take() {
+            acceptor
+                .join()
+                .unwrap_or_else(|_| error!("fake github acceptor thread have panicked"));
+        }
+    }
+}
+
+fn serve(mut stream: TcpStream, state: &Mutex<State>, base_url: &str) {
+    let request = match Request::read_from(&mut stream) {
+        Ok(request) => request,
+        Err(e) => {
+            debug!("fake github received malformed request: {}", e);
+            return;
+        }
+    };
+
+    debug!("fake github: {} {}", request.method, request.path);
+    let response = dispatch(&mut state.lock().unwrap(), base_url, &request);
+
+    if let Err(e) = response.write_to(&mut stream) {
+        warn!("fake github failed to write response: {}", e);
+    }
+}
+
+fn dispatch(state: &mut State, base_url: &str, request: &Request) -> Response {
+    state.requests.push(RecordedRequest::from(request));
+
+    // App endpoints are authenticated with JWT, not with a token
+    let app_request = request.path.starts_with("/app/");
+
+    if let (Some(ref tokens), false) = (&state.tokens, app_request) {
+        let authorized = request
+            .header("authorization")
+            .and_then(|auth| auth.split_whitespace().nth(1))
+            .map(|token| tokens.iter().any(|t| t == token))
+            .unwrap_or(false);
+
+        if !authorized {
+            return Response::message(401, "Bad credentials");
+        }
+    }
+
+    if let Some(failure) = state.take_failure(request) {
+        return failure;
+    }
+
+    if request.method == "POST" && request.path == "/graphql" {
+        graphql::handle(state, request)
+    } else {
+        rest::handle(state, base_url, request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use json::Value;
+    use std::io::{Read, Write};
+
+    fn send(gh: &FakeGithub, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+        let mut stream = TcpStream::connect(gh.addr).unwrap();
+        let body = body.map(|b| json::to_vec(&b).unwrap()).unwrap_or_default();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nAuthorization: token test\r\nContent-Length: {}\r\n\r\n",
+            method,
+            path,
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(&body).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            json::from_str(body).unwrap()
+        };
+        (status, body)
+    }
+
+    fn graphql(gh: &FakeGithub, query: &str) -> Value {
+        let (status, body) = send(gh, "POST", "/graphql", Some(json!({ "query": query })));
+        assert_eq!(status, 200);
+        body
+    }
+
+    #[test]
+    fn viewer_and_rate_limit() {
+        let gh = FakeGithub::start().unwrap();
+        let body = graphql(
+            &gh,
+            "query { viewer { login } rateLimit { limit remaining resetAt } }",
+        );
+        assert_eq!(body["data"]["viewer"]["login"], "rustyrobot");
+        assert_eq!(body["data"]["rateLimit"]["limit"], 5000);
+        assert_eq!(body["data"]["rateLimit"]["remaining"], 4999);
+    }
+
+    #[test]
+    fn search_pages_through_results() {
+        let gh = FakeGithub::start().unwrap();
+        for i in 0..5 {
+            gh.add_repo(FakeRepo::new(format!("owner/repo{}", i)));
+        }
+        gh.add_repo(FakeRepo::new("owner/python").language(Some("Python")));
+        gh.add_repo(FakeRepo::new("other/rust"));
+
+        let query = |after: &str| {
+            format!(
+                "query {{ search(type: REPOSITORY, first: 2, query: \\\"language:Rust user:owner\\\"{}) {{ nodes {{ ... on Repository {{ nameWithOwner viewerHasStarred }} }} }} }}",
+                after
+            )
+        };
+
+        let mut names = Vec::new();
+        let mut after = String::new();
+        loop {
+            let body = graphql(&gh, &query(&after));
+            let search = &body["data"]["search"];
+            assert_eq!(search["repositoryCount"], 5);
+            for node in search["nodes"].as_array().unwrap() {
+                names.push(node["nameWithOwner"].as_str().unwrap().to_string());
+            }
+            if !search["pageInfo"]["hasNextPage"].as_bool().unwrap() {
+                break;
+            }
+            after = format!(
+                ", after: \\\"{}\\\"",
+                search["pageInfo"]["endCursor"].as_str().unwrap()
+            );
+        }
+
+        assert_eq!(
+            names,
+            (0..5)
+                .map(|i| format!("owner/repo{}", i))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn search_pull_requests() {
+        let gh = FakeGithub::start().unwrap();
+        gh.add_repo(FakeRepo::new("owner/repo"));
+        gh.add_pull(FakePull::new("owner/repo", "rustyrobot:fmt"));
+        gh.add_pull(FakePull::new("owner/repo", "rustyrobot:fix"));
+        let mut foreign = FakePull::new("owner/repo", "someone:feature");
+        foreign.user = "someone".to_string();
+        gh.add_pull(foreign);
+        gh.state().pull_mut("owner/repo", 2).unwrap().merge("owner");
+
+        let body = graphql(&gh, "query { search(type: ISSUE, first: 10, query: \"is:pr is:open author:rustyrobot\") { issueCount nodes { ... on PullRequest { number state } } } }");
+        let search = &body["data"]["search"];
+        assert_eq!(search["issueCount"], 1);
+        assert_eq!(search["nodes"][0]["number"], 1);
+        assert_eq!(search["nodes"][0]["state"], "OPEN");
+
+        let body = graphql(&gh, "query { search(type: USER, first: 10, query: \"owner\") { userCount nodes { ... on User { login } } } }");
+        assert_eq!(body["data"]["search"]["nodes"][0]["login"], "owner");
+    }
+
+    #[test]
+    fn search_is_capped() {
+        let gh = FakeGithub::start().unwrap();
+        gh.state().search_cap = 3;
+        for i in 0..5 {
+            gh.add_repo(FakeRepo::new(format!("owner/repo{}", i)));
+        }
+
+        let body = graphql(&gh, "query { search(type: REPOSITORY, first: 10, query: \"language:Rust\") { nodes { ... on Repository { id } } } }");
+        assert_eq!(body["data"]["search"]["repositoryCount"], 5);
+        assert_eq!(body["data"]["search"]["nodes"].as_array().unwrap().len(), 3);
+        assert_eq!(body["data"]["search"]["pageInfo"]["hasNextPage"], false);
+    }
+
+    #[test]
+    fn search_by_created_range() {
+        let gh = FakeGithub::start().unwrap();
+        let day = |d| chrono::Utc.ymd(2018, 8, d).and_hms(23, 0, 0);
+        gh.add_repo(FakeRepo::new("owner/a").created_at(day(9)));
+        gh.add_repo(FakeRepo::new("owner/b").created_at(day(10)));
+        gh.add_repo(FakeRepo::new("owner/c").created_at(day(11)));
+        gh.add_repo(FakeRepo::new("owner/d").created_at(day(12)));
+
+        let body = graphql(&gh, "query { search(type: REPOSITORY, first: 10, query: \"created:2018-08-10..2018-08-11\") { nodes { ... on Repository { nameWithOwner } } } }");
+        let names: Vec<&str> = body["data"]["search"]["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|n| n["nameWithOwner"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["owner/b", "owner/c"]);
+    }
+
+    #[test]
+    fn fork_and_delete() {
+        let gh = FakeGithub::start().unwrap();
+        gh.add_repo(FakeRepo::new("owner/repo"));
+
+        let (status, fork) = send(&gh, "POST", "/repos/owner/repo/forks", None);
+        assert_eq!(status, 202);
+        assert_eq!(fork["full_name"], "rustyrobot/repo");
+        assert_eq!(fork["fork"], true);
+        assert_eq!(fork["parent"]["full_name"], "owner/repo");
+
+        let (status, _) = send(&gh, "DELETE", "/repos/rustyrobot/repo", None);
+        assert_eq!(status, 204);
+        let (status, _) = send(&gh, "DELETE", "/repos/rustyrobot/repo", None);
+        assert_eq!(status, 404);
+    }
+
+    #[test]
+    fn notifications_mark_read() {
+        let gh = FakeGithub::start().unwrap();
+        let day = |d| chrono::Utc.ymd(2018, 8, d).and_hms(12, 0, 0);
+        gh.add_notification(FakeNotification::new("owner/repo", "old").updated_at(day(10)));
+        gh.add_notification(
+            FakeNotification::new("owner/repo", "new")
+                .pull(1)
+                .updated_at(day(12)),
+        );
+
+        let (_, all) = send(&gh, "GET", "/notifications", None);
+        assert_eq!(all.as_array().unwrap().len(), 2);
+        let (_, since) = send(
+            &gh,
+            "GET",
+            "/notifications?since=2018-08-11T00:00:00Z",
+            None,
+        );
+        assert_eq!(since[0]["subject"]["title"], "new");
+        assert_eq!(since[0]["subject"]["type"], "PullRequest");
+        assert_eq!(since.as_array().unwrap().len(), 1);
+
+        let id = since[0]["id"].as_str().unwrap().to_string();
+        let (status, _) = send(
+            &gh,
+            "PATCH",
+            &format!("/notifications/threads/{}", id),
+            None,
+        );
+        assert_eq!(status, 205);
+        let (_, unread) = send(&gh, "GET", "/notifications", None);
+        assert_eq!(unread[0]["subject"]["title"], "old");
+        assert_eq!(unread.as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn pull_request_lifecycle() {
+        let gh = FakeGithub::start().unwrap();
+        gh.add_repo(FakeRepo::new("owner/repo"));
+
+        let pr = json!({ "title": "fmt", "body": "", "head": "rustyrobot:fmt", "base": "master" });
+        let (status, created) = send(&gh, "POST", "/repos/owner/repo/pulls", Some(pr.clone()));
+        assert_eq!(status, 201);
+        assert_eq!(created["number"], 1);
+
+        let (status, _) = send(&gh, "POST", "/repos/owner/repo/pulls", Some(pr));
+        assert_eq!(status, 422);
+
+        let (_, list) = send(
+            &gh,
+            "GET",
+            "/repos/owner/repo/pulls?head=rustyrobot%3Afmt",
+            None,
+        );
+        assert_eq!(list.as_array().unwrap().len(), 1);
+
+        gh.state().pull_mut("owner/repo", 1).unwrap().merge("owner");
+        let (status, pull) = send(&gh, "GET", "/repos/owner/repo/pulls/1", None);
+        assert_eq!(status, 200);
+        assert_eq!(pull["state"], "closed");
+        assert_eq!(pull["merged"], true);
+    }
+
+    #[test]
+    fn injected_failures() {
+        let gh = FakeGithub::start().unwrap();
+        gh.add_repo(FakeRepo::new("owner/repo"));
+        gh.fail_times("POST /repos/*/*/forks", 2, Failure::status(502));
+
+        assert_eq!(send(&gh, "POST", "/repos/owner/repo/forks", None).0, 502);
+        assert_eq!(send(&gh, "POST", "/repos/owner/repo/forks", None).0, 502);
+        assert_eq!(send(&gh, "POST", "/repos/owner/repo/forks", None).0, 202);
+        assert_eq!(gh.requests().len(), 3);
+    }
+
+    #[test]
+    fn rest_rate_limit_exhaustion() {
+        let gh = FakeGithub::start().unwrap();
+        gh.state().core_limit.remaining = 1;
+
+        assert_eq!(send(&gh, "GET", "/user", None).0, 200);
+        let (status, body) = send(&gh, "GET", "/user", None);
+        assert_eq!(status, 403);
+        assert!(body["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("API rate limit exceeded"));
+
+        let (status, limits) = send(&gh, "GET", "/rate_limit", None);
+        assert_eq!(status, 200);
+        assert_eq!(limits["resources"]["core"]["remaining"], 0);
+    }
+
+    #[test]
+    fn installation_tokens() {
+        let gh = FakeGithub::start().unwrap();
+        gh.state().installations.push(42);
+        gh.state().tokens = Some(vec![]);
+
+        let (status, _) = send(&gh, "POST", "/app/installations/42/access_tokens", None);
+        assert_eq!(status, 401, "token auth must be refused for app endpoints");
+
+        let issue = |installation| {
+            let mut stream = TcpStream::connect(gh.addr).unwrap();
+            write!(
+                stream,
+                "POST /app/installations/{}/access_tokens HTTP/1.1\r\nAuthorization: Bearer a.b.c\r\n\r\n",
+                installation
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        assert!(issue(7).starts_with("HTTP/1.1 404"));
+        assert!(issue(42).starts_with("HTTP/1.1 201"));
+        assert_eq!(gh.state().tokens, Some(vec!["v1.42.1".to_string()]));
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let gh = FakeGithub::start().unwrap();
+        gh.add_repo(FakeRepo::new("owner/repo"));
+
+        let get = |etag: &str| {
+            let mut stream = TcpStream::connect(gh.addr).unwrap();
+            write!(
+                stream,
+                "GET /repos/owner/repo HTTP/1.1\r\nIf-None-Match: {}\r\n\r\n",
+                etag
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get("none");
+        assert!(response.starts_with("HTTP/1.1 200"));
+        let etag = response
+            .lines()
+            .find(|line| line.starts_with("ETag: "))
+            .unwrap()[6..]
+            .to_string();
+
+        assert!(get(&etag).starts_with("HTTP/1.1 304"));
+        assert_eq!(gh.state().core_limit.remaining, 4999);
+
+        gh.state().repos.get_mut("owner/repo").unwrap().stars = 10;
+        assert!(get(&etag).starts_with("HTTP/1.1 200"));
+    }
+
+    #[test]
+    fn list_pagination() {
+        let gh = FakeGithub::start().unwrap();
+        gh.add_repo(FakeRepo::new("owner/repo"));
+        for i in 0..5 {
+            gh.add_pull(FakePull::new(
+                "owner/repo",
+                format!("rustyrobot:branch{}", i),
+            ));
+        }
+
+        let (_, page) = send(
+            &gh,
+            "GET",
+            "/repos/owner/repo/pulls?per_page=2&page=3",
+            None,
+        );
+        assert_eq!(page.as_array().unwrap().len(), 1);
+        assert_eq!(page[0]["number"], 5);
+
+        let mut stream = TcpStream::connect(gh.addr).unwrap();
+        write!(
+            stream,
+            "GET /repos/owner/repo/pulls?per_page=2 HTTP/1.1\r\n\r\n"
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.contains(&format!(
+            "<{}/repos/owner/repo/pulls?per_page=2&page=2>; rel=\"next\"",
+            gh.url()
+        )));
+    }
+
+    #[test]
+    fn bad_credentials() {
+        let gh = FakeGithub::start().unwrap();
+        gh.state().tokens = Some(vec!["other".to_string()]);
+        assert_eq!(send(&gh, "GET", "/user", None).0, 401);
+    }
+}
@@ -0,0 +1,495 @@
+use chrono::{DateTime, Utc};
+use json::Value;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use http::{percent_encode, Request, Response};
+use state::{FakeNotification, FakePull, FakeRepo, PullState, State};
+
+pub fn handle(state: &mut State, base_url: &str, request: &Request) -> Response {
+    // Conditional requests answered with 304 don't count against the rate limit.
+    // GET routes have no side effects, so it's fine to route the request twice.
+    if let (true, Some(expected)) = (request.method == "GET", request.header("if-none-match")) {
+        let response = route(state, base_url, request);
+        if response.status == 200 && etag(&response) == expected {
+            return with_limits(state, Response::empty(304).with_header("ETag", expected));
+        }
+    }
+
+    if request.path != "/rate_limit" && !state.core_limit.consume() {
+        return Response::message(403, "API rate limit exceeded for user ID 1.");
+    }
+
+    let mut response = route(state, base_url, request);
+    if request.method == "GET" && response.status == 200 {
+        let etag = etag(&response);
+        response = response.with_header("ETag", etag);
+    }
+
+    with_limits(state, response)
+}
+
+fn with_limits(state: &State, response: Response) -> Response {
+    let limit = state.core_limit;
+    response
+        .with_header("X-RateLimit-Limit", limit.limit.to_string())
+        .with_header("X-RateLimit-Remaining", limit.remaining.to_string())
+        .with_header("X-RateLimit-Reset", limit.reset_at.timestamp().to_string())
+}
+
+/// Weak validator derived from the response body
+fn etag(response: &Response) -> String {
+    let mut hasher = DefaultHasher::new();
+    if let Some(ref body) = response.body {
+        body.to_string().hash(&mut hasher);
+    }
+    format!("W/\"{:016x}\"", hasher.finish())
+}
+
+fn route(state: &mut State, base_url: &str, request: &Request) -> Response {
+    let segments = request.segments();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["user"]) => Response::json(
+            200,
+            json!({
+                "login": state.login,
+                "id": 1,
+                "type": "User",
+            }),
+        ),
+        ("GET", ["rate_limit"]) => rate_limit(state),
+        ("POST", ["app", "installations", id, "access_tokens"]) => {
+            installation_token(state, id, request)
+        }
+        ("GET", ["notifications"]) => notifications(state, base_url, request),
+        ("PATCH", ["notifications", "threads", id]) => mark_thread_read(state, id),
+        ("GET", ["repos", owner, name]) => match state.repos.get(&full_name(owner, name)) {
+            Some(repo) => Response::json(200, repo_v3(state, repo)),
+            None => Response::message(404, "Not Found"),
+        },
+        ("DELETE", ["repos", owner, name]) => match state.repos.remove(&full_name(owner, name)) {
+            Some(_) => Response::empty(204),
+            None => Response::message(404, "Not Found"),
+        },
+        ("POST", ["repos", owner, name, "forks"]) => fork(state, &full_name(owner, name)),
+        ("GET", ["repos", owner, name, "pulls"]) => {
+            list_pulls(state, base_url, &full_name(owner, name), request)
+        }
+        ("POST", ["repos", owner, name, "pulls"]) => {
+            create_pull(state, base_url, &full_name(owner, name), request)
+        }
+        ("GET", ["repos", owner, name, "pulls", number]) => {
+            let repo = full_name(owner, name);
+            let pull = number
+                .parse::<u64>()
+                .ok()
+                .and_then(|number| state.pull_mut(&repo, number).map(|pull| pull.clone()));
+            match pull {
+                Some(pull) => Response::json(200, pull_v3(base_url, &pull)),
+                None => Response::message(404, "Not Found"),
+            }
+        }
+        ("GET", ["repos", owner, name, "pulls", number, "reviews"]) => {
+            let repo = full_name(owner, name);
+            let pull = number
+                .parse::<u64>()
+                .ok()
+                .and_then(|number| state.pull_mut(&repo, number).map(|pull| pull.clone()));
+            match pull {
+                Some(pull) => {
+                    let reviews = pull
+                        .reviews
+                        .iter()
+                        .map(|(user, state)| json!({ "user": { "login": user }, "state": state }))
+                        .collect();
+                    paginate(base_url, request, reviews)
+                }
+                None => Response::message(404, "Not Found"),
+            }
+        }
+        ("GET", ["repos", owner, name, "commits", sha, "status"]) => {
+            let repo = full_name(owner, name);
+            let pull = state
+                .pulls
+                .iter()
+                .find(|pull| pull.repo == repo && pull.head_sha == *sha);
+            let (combined, statuses) = match pull {
+                Some(pull) => (pull.combined_status(), pull.statuses.clone()),
+                None => ("pending", Vec::new()),
+            };
+            let statuses: Vec<Value> = statuses
+                .iter()
+                .map(|state| json!({ "state": state, "context": "ci" }))
+                .collect();
+            Response::json(
+                200,
+                json!({
+                    "state": combined,
+                    "sha": sha,
+                    "total_count": statuses.len(),
+                    "statuses": statuses,
+                }),
+            )
+        }
+        _ => Response::message(404, "Not Found"),
+    }
+}
+
+fn full_name(owner: &str, name: &str) -> String {
+    format!("{}/{}", owner, name)
+}
+
+fn rate_limit(state: &State) -> Response {
+    let resource = |limit: &::state::RateLimit| {
+        json!({
+            "limit": limit.limit,
+            "remaining": limit.remaining,
+            "reset": limit.reset_at.timestamp(),
+        })
+    };
+
+    Response::json(
+        200,
+        json!({
+            "resources": {
+                "core": resource(&state.core_limit),
+                "graphql": resource(&state.graphql_limit),
+            },
+            "rate": resource(&state.core_limit),
+        }),
+    )
+}
+
+fn installation_token(state: &mut State, id: &str, request: &Request) -> Response {
+    // Signature is not verified, but the credentials must look like a JWT
+    let jwt = request
+        .header("authorization")
+        .and_then(|auth| {
+            let mut auth = auth.split_whitespace();
+            match (auth.next(), auth.next()) {
+                (Some(scheme), Some(jwt)) if scheme.eq_ignore_ascii_case("bearer") => Some(jwt),
+                _ => None,
+            }
+        })
+        .filter(|jwt| jwt.split('.').count() == 3);
+
+    if jwt.is_none() {
+        return Response::message(401, "A JSON web token could not be decoded");
+    }
+
+    let id = match id.parse::<u64>() {
+        Ok(id) if state.installations.contains(&id) => id,
+        _ => return Response::message(404, "Not Found"),
+    };
+
+    let token = format!("v1.{}.{}", id, state.next_id());
+    if let Some(ref mut tokens) = state.tokens {
+        tokens.push(token.clone());
+    }
+
+    Response::json(
+        201,
+        json!({
+            "token": token,
+            "expires_at": Utc::now() + state.installation_token_ttl,
+        }),
+    )
+}
+
+fn notifications(state: &State, base_url: &str, request: &Request) -> Response {
+    let all = request.query_param("all") == Some("true");
+    let since = request
+        .query_param("since")
+        .and_then(|since| DateTime::parse_from_rfc3339(since).ok());
+
+    let notifications: Vec<Value> = state
+        .notifications
+        .iter()
+        .filter(|n| all || n.unread)
+        .filter(|n| since.map(|since| n.updated_at > since).unwrap_or(true))
+        .map(|n| notification_v3(base_url, n))
+        .collect();
+
+    paginate(base_url, request, notifications)
+}
+
+fn mark_thread_read(state: &mut State, id: &str) -> Response {
+    let notification = id.parse().ok().and_then(|id| state.notification_mut(id));
+    match notification {
+        Some(notification) => {
+            notification.mark_read();
+            Response::empty(205)
+        }
+        None => Response::message(404, "Not Found"),
+    }
+}
+
+fn fork(state: &mut State, parent_name: &str) -> Response {
+    let parent = match state.repos.get(parent_name) {
+        Some(parent) => parent.clone(),
+        None => return Response::message(404, "Not Found"),
+    };
+
+    let fork_name = full_name(&state.login, parent.name());
+    if !state.repos.contains_key(&fork_name) {
+        let mut fork = parent.clone();
+        fork.id = state.next_id();
+        fork.full_name = fork_name.clone();
+        fork.parent = Some(parent.full_name.clone());
+        fork.stars = 0;
+        fork.forks = 0;
+        fork.created_at = Utc::now();
+        state.repos.insert(fork_name.clone(), fork);
+    }
+
+    let fork = &state.repos[&fork_name];
+    Response::json(202, repo_v3(state, fork))
+}
+
+fn list_pulls(state: &State, base_url: &str, repo: &str, request: &Request) -> Response {
+    let filter_state = request.query_param("state").unwrap_or("open");
+    let head = request.query_param("head");
+    let base = request.query_param("base");
+
+    let pulls: Vec<Value> = state
+        .pulls
+        .iter()
+        .filter(|pull| pull.repo == repo)
+        .filter(|pull| match filter_state {
+            "all" => true,
+            "closed" => pull.state != PullState::Open,
+            _ => pull.state == PullState::Open,
+        })
+        .filter(|pull| head.map(|head| pull.head == head).unwrap_or(true))
+        .filter(|pull| base.map(|base| pull.base == base).unwrap_or(true))
+        .map(|pull| pull_v3(base_url, pull))
+        .collect();
+
+    paginate(base_url, request, pulls)
+}
+
+/// Slice the list according to `page` and `per_page`, linking the neighbour pages like GitHub does
+fn paginate(base_url: &str, request: &Request, items: Vec<Value>) -> Response {
+    let param = |name, default: usize| {
+        request
+            .query_param(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+    let per_page = param("per_page", 30).max(1).min(100);
+    let page = param("page", 1).max(1);
+    let last = ((items.len() + per_page - 1) / per_page).max(1);
+
+    let page_url = |page: usize| {
+        let mut query: Vec<String> = request
+            .query
+            .iter()
+            .filter(|(key, _)| key != "page")
+            .map(|(key, value)| format!("{}={}", percent_encode(key), percent_encode(value)))
+            .collect();
+        query.push(format!("page={}", page));
+        format!("{}{}?{}", base_url, request.path, query.join("&"))
+    };
+
+    let mut links = Vec::new();
+    if page < last {
+        links.push(format!("<{}>; rel=\"next\"", page_url(page + 1)));
+        links.push(format!("<{}>; rel=\"last\"", page_url(last)));
+    }
+    if page > 1 {
+        links.push(format!("<{}>; rel=\"first\"", page_url(1)));
+        links.push(format!("<{}>; rel=\"prev\"", page_url(page - 1)));
+    }
+
+    let items: Vec<Value> = items
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .collect();
+
+    let response = Response::json(200, Value::from(items));
+    if links.is_empty() {
+        response
+    } else {
+        response.with_header("Link", links.join(", "))
+    }
+}
+
+fn create_pull(state: &mut State, base_url: &str, repo: &str, request: &Request) -> Response {
+    if !state.repos.contains_key(repo) {
+        return Response::message(404, "Not Found");
+    }
+
+    let body = match request.json() {
+        Ok(body) => body,
+        Err(_) => return Response::message(400, "Problems parsing JSON"),
+    };
+
+    let field = |name: &str| body[name].as_str().map(ToOwned::to_owned);
+    let (head, base) = match (field("head"), field("base")) {
+        (Some(head), Some(base)) => (head, base),
+        _ => return validation_failed("PullRequest", "head", "missing_field"),
+    };
+
+    let exists = state
+        .pulls
+        .iter()
+        .any(|pull| pull.repo == repo && pull.head == head && pull.state == PullState::Open);
+    if exists {
+        return Response::json(
+            422,
+            json!({
+                "message": "Validation Failed",
+                "errors": [{
+                    "resource": "PullRequest",
+                    "code": "custom",
+                    "message": format!("A pull request already exists for {}.", head),
+                }],
+                "documentation_url": "https://developer.github.com/v3/pulls/#create-a-pull-request",
+            }),
+        );
+    }
+
+    let mut pull = FakePull::new(repo, head);
+    pull.number = state.next_pull_number(repo);
+    pull.title = field("title").unwrap_or_default();
+    pull.body = field("body").unwrap_or_default();
+    pull.base = base;
+    pull.user = state.login.clone();
+
+    let response = Response::json(201, pull_v3(base_url, &pull));
+    state.pulls.push(pull);
+    response
+}
+
+fn validation_failed(resource: &str, field: &str, code: &str) -> Response {
+    Response::json(
+        422,
+        json!({
+            "message": "Validation Failed",
+            "errors": [{ "resource": resource, "field": field, "code": code }],
+            "documentation_url": "https://developer.github.com/v3",
+        }),
+    )
+}
+
+pub fn repo_v3(state: &State, repo: &FakeRepo) -> Value {
+    let parent = repo
+        .parent
+        .as_ref()
+        .and_then(|parent| state.repos.get(parent))
+        .map(|parent| {
+            json!({
+                "id": parent.id,
+                "full_name": parent.full_name,
+                "ssh_url": parent.ssh_url(),
+                "html_url": parent.html_url(),
+            })
+        });
+
+    json!({
+        "id": repo.id,
+        "node_id": repo.node_id(),
+        "name": repo.name(),
+        "full_name": repo.full_name,
+        "owner": {
+            "login": repo.owner(),
+            "type": "User",
+        },
+        "private": repo.is_private,
+        "description": repo.description,
+        "fork": repo.parent.is_some(),
+        "html_url": repo.html_url(),
+        "ssh_url": repo.ssh_url(),
+        "created_at": repo.created_at,
+        "pushed_at": repo.pushed_at,
+        "size": repo.size,
+        "stargazers_count": repo.stars,
+        "forks_count": repo.forks,
+        "language": repo.language,
+        "has_issues": repo.has_issues,
+        "archived": repo.is_archived,
+        "topics": repo.topics,
+        "license": repo.license.as_ref().map(|license| json!({
+            "key": license.to_lowercase(),
+            "spdx_id": license,
+        })),
+        "default_branch": repo.default_branch,
+        "parent": parent,
+    })
+}
+
+pub fn notification_v3(base_url: &str, notification: &FakeNotification) -> Value {
+    let kind = match notification.subject_type.as_str() {
+        "PullRequest" => "pulls",
+        _ => "issues",
+    };
+    let subject_url = notification.number.map(|number| {
+        format!(
+            "{}/repos/{}/{}/{}",
+            base_url, notification.repo, kind, number
+        )
+    });
+
+    json!({
+        "id": notification.id.to_string(),
+        "repository": {
+            "full_name": notification.repo,
+            "html_url": format!("https://github.com/{}", notification.repo),
+        },
+        "subject": {
+            "title": notification.title,
+            "url": subject_url,
+            "latest_comment_url": subject_url,
+            "type": notification.subject_type,
+        },
+        "reason": notification.reason,
+        "unread": notification.unread,
+        "updated_at": notification.updated_at,
+        "last_read_at": notification.last_read_at,
+        "url": format!("{}/notifications/threads/{}", base_url, notification.id),
+    })
+}
+
+/// The fake doesn't track the base branches, their tip is the same for every PR
+const BASE_SHA: &str = "553c2077f0edc3d5dc5d17262f6aa498e69d6f8e";
+
+pub fn pull_v3(base_url: &str, pull: &FakePull) -> Value {
+    let url = format!("{}/repos/{}/pulls/{}", base_url, pull.repo, pull.number);
+    let state = match pull.state {
+        PullState::Open => "open",
+        PullState::Closed | PullState::Merged => "closed",
+    };
+
+    json!({
+        "url": url,
+        "html_url": format!("https://github.com/{}/pull/{}", pull.repo, pull.number),
+        "number": pull.number,
+        "state": state,
+        "title": pull.title,
+        "body": pull.body,
+        "user": { "login": pull.user },
+        "created_at": pull.created_at,
+        "updated_at": pull.updated_at,
+        "closed_at": pull.closed_at,
+        "merged_at": pull.merged_at,
+        "merged": pull.state == PullState::Merged,
+        "merged_by": pull.merged_by.as_ref().map(|login| json!({ "login": login })),
+        "comments": pull.comments,
+        "mergeable": pull.state == PullState::Open,
+        "mergeable_state": if pull.state == PullState::Open { "clean" } else { "unknown" },
+        "head": {
+            "label": pull.head,
+            "ref": pull.head_ref(),
+            "sha": pull.head_sha,
+            "user": { "login": pull.head_owner() },
+        },
+        "base": {
+            "label": format!("{}:{}", pull.repo.split('/').next().unwrap_or(""), pull.base),
+            "ref": pull.base,
+            "sha": BASE_SHA,
+            "repo": { "full_name": pull.repo },
+        },
+    })
+}
@@ -0,0 +1,580 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use json::Value;
+
+use std::collections::BTreeMap;
+
+use http::{Request, Response};
+
+pub struct State {
+    /// Login of the authenticated user (`viewer` in GraphQL, `/user` in REST)
+    pub login: String,
+    pub repos: BTreeMap<String, FakeRepo>,
+    pub pulls: Vec<FakePull>,
+    pub notifications: Vec<FakeNotification>,
+    pub core_limit: RateLimit,
+    pub graphql_limit: RateLimit,
+    /// Maximum number of results reachable through a single search query
+    pub search_cap: usize,
+    /// If set, requests without one of these tokens are rejected with 401
+    pub tokens: Option<Vec<String>>,
+    /// Ids of the GitHub App installations access tokens can be requested for
+    pub installations: Vec<u64>,
+    /// Lifetime of the issued installation access tokens
+    pub installation_token_ttl: Duration,
+    pub requests: Vec<RecordedRequest>,
+    pub(crate) failures: Vec<ScriptedFailure>,
+    pub(crate) next_id: u64,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            login: "rustyrobot".to_string(),
+            repos: BTreeMap::new(),
+            pulls: Vec::new(),
+            notifications: Vec::new(),
+            core_limit: RateLimit::new(5000),
+            graphql_limit: RateLimit::new(5000),
+            search_cap: 1000,
+            tokens: None,
+            installations: Vec::new(),
+            installation_token_ttl: Duration::hours(1),
+            requests: Vec::new(),
+            failures: Vec::new(),
+            next_id: 1,
+        }
+    }
+}
+
+impl State {
+    pub fn add_repo(&mut self, mut repo: FakeRepo) {
+        if repo.id == 0 {
+            repo.id = self.next_id();
+        }
+        self.repos.insert(repo.full_name.clone(), repo);
+    }
+
+    pub fn add_pull(&mut self, mut pull: FakePull) {
+        if pull.number == 0 {
+            pull.number = self.next_pull_number(&pull.repo);
+        }
+        self.pulls.push(pull);
+    }
+
+    pub fn add_notification(&mut self, mut notification: FakeNotification) {
+        if notification.id == 0 {
+            notification.id = self.next_id();
+        }
+        self.notifications.push(notification);
+    }
+
+    pub fn notification_mut(&mut self, id: u64) -> Option<&mut FakeNotification> {
+        self.notifications
+            .iter_mut()
+            .find(|notification| notification.id == id)
+    }
+
+    pub fn pull_mut(&mut self, repo: &str, number: u64) -> Option<&mut FakePull> {
+        self.pulls
+            .iter_mut()
+            .find(|pull| pull.repo == repo && pull.number == number)
+    }
+
+    /// Make the next `times` requests matching `route` fail with `failure`.
+    ///
+    /// Route is `"<METHOD> <path>"` where every path segment may be a `*` wildcard,
+    /// e.g. `"POST /repos/*/*/forks"` or `"POST /graphql"`.
+    pub fn fail(&mut self, route: &str, times: usize, failure: Failure) {
+        self.failures.push(ScriptedFailure {
+            route: route.to_string(),
+            times,
+            failure,
+        });
+    }
+
+    pub(crate) fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub(crate) fn next_pull_number(&self, repo: &str) -> u64 {
+        self.pulls
+            .iter()
+            .filter(|pull| pull.repo == repo)
+            .map(|pull| pull.number)
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    pub(crate) fn take_failure(&mut self, request: &Request) -> Option<Response> {
+        let position = self
+            .failures
+            .iter()
+            .position(|scripted| scripted.times > 0 && scripted.matches(request))?;
+
+        let response = self.failures[position].failure.to_response();
+        self.failures[position].times -= 1;
+        if self.failures[position].times == 0 {
+            self.failures.remove(position);
+        }
+
+        Some(response)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FakeRepo {
+    pub id: u64,
+    pub full_name: String,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub default_branch: String,
+    pub created_at: DateTime<Utc>,
+    pub pushed_at: DateTime<Utc>,
+    pub stars: u64,
+    pub forks: u64,
+    /// Disk usage in kilobytes
+    pub size: u64,
+    pub topics: Vec<String>,
+    pub license: Option<String>,
+    pub is_archived: bool,
+    pub is_private: bool,
+    pub has_issues: bool,
+    /// Full name of the parent repository if this is a fork
+    pub parent: Option<String>,
+    /// Paths of the files in the default branch, only looked up by `object(expression:)`
+    pub files: Vec<String>,
+}
+
+impl FakeRepo {
+    pub fn new(full_name: impl Into<String>) -> Self {
+        let created_at = Utc.ymd(2018, 8, 10).and_hms(12, 0, 0);
+        FakeRepo {
+            id: 0,
+            full_name: full_name.into(),
+            description: None,
+            language: Some("Rust".to_string()),
+            default_branch: "master".to_string(),
+            created_at,
+            pushed_at: created_at + Duration::days(1),
+            stars: 0,
+            forks: 0,
+            size: 100,
+            topics: Vec::new(),
+            license: None,
+            is_archived: false,
+            is_private: false,
+            has_issues: true,
+            parent: None,
+            files: Vec::new(),
+        }
+    }
+
+    pub fn id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn language(mut self, language: Option<&str>) -> Self {
+        self.language = language.map(ToOwned::to_owned);
+        self
+    }
+
+    pub fn created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = created_at;
+        self
+    }
+
+    pub fn pushed_at(mut self, pushed_at: DateTime<Utc>) -> Self {
+        self.pushed_at = pushed_at;
+        self
+    }
+
+    pub fn stars(mut self, stars: u64) -> Self {
+        self.stars = stars;
+        self
+    }
+
+    pub fn forks(mut self, forks: u64) -> Self {
+        self.forks = forks;
+        self
+    }
+
+    pub fn size(mut self, size: u64) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn topic(mut self, topic: impl Into<String>) -> Self {
+        self.topics.push(topic.into());
+        self
+    }
+
+    pub fn license(mut self, license: impl Into<String>) -> Self {
+        self.license = Some(license.into());
+        self
+    }
+
+    pub fn archived(mut self, is_archived: bool) -> Self {
+        self.is_archived = is_archived;
+        self
+    }
+
+    pub fn fork_of(mut self, parent: impl Into<String>) -> Self {
+        self.parent = Some(parent.into());
+        self
+    }
+
+    pub fn file(mut self, path: impl Into<String>) -> Self {
+        self.files.push(path.into());
+        self
+    }
+
+    pub fn owner(&self) -> &str {
+        self.full_name.split('/').next().unwrap_or("")
+    }
+
+    pub fn name(&self) -> &str {
+        self.full_name.split('/').nth(1).unwrap_or("")
+    }
+
+    pub fn ssh_url(&self) -> String {
+        format!("git@github.com:{}.git", self.full_name)
+    }
+
+    pub fn html_url(&self) -> String {
+        format!("https://github.com/{}", self.full_name)
+    }
+
+    pub fn node_id(&self) -> String {
+        format!("MDEwOlJlcG9zaXRvcnl7{}", self.id)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FakePull {
+    pub number: u64,
+    /// Full name of the base repository
+    pub repo: String,
+    pub title: String,
+    pub body: String,
+    /// `owner:branch` of the head
+    pub head: String,
+    pub head_sha: String,
+    pub base: String,
+    pub user: String,
+    pub state: PullState,
+    pub merged_by: Option<String>,
+    pub comments: u64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub merged_at: Option<DateTime<Utc>>,
+    /// `(reviewer, state)` in submission order, e.g. `("owner", "APPROVED")`
+    pub reviews: Vec<(String, String)>,
+    /// States of the commit statuses reported to the head
+    pub statuses: Vec<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PullState {
+    Open,
+    Closed,
+    Merged,
+}
+
+impl FakePull {
+    pub fn new(repo: impl Into<String>, head: impl Into<String>) -> Self {
+        let now = Utc::now();
+        FakePull {
+            number: 0,
+            repo: repo.into(),
+            title: String::new(),
+            body: String::new(),
+            head: head.into(),
+            head_sha: "6dcb09b5b57875f334f61aebed695e2e4193db5e".to_string(),
+            base: "master".to_string(),
+            user: "rustyrobot".to_string(),
+            state: PullState::Open,
+            merged_by: None,
+            comments: 0,
+            created_at: now,
+            updated_at: now,
+            closed_at: None,
+            merged_at: None,
+            reviews: Vec::new(),
+            statuses: Vec::new(),
+        }
+    }
+
+    pub fn review(&mut self, reviewer: impl Into<String>, state: &str) {
+        self.reviews.push((reviewer.into(), state.to_string()));
+    }
+
+    /// Report a commit status (`pending`, `success`, `failure` or `error`) to the head
+    pub fn report_status(&mut self, state: &str) {
+        self.statuses.push(state.to_string());
+    }
+
+    /// Combined state as computed by GitHub
+    pub fn combined_status(&self) -> &'static str {
+        let has = |state| self.statuses.iter().any(|s| s == state);
+        if has("error") || has("failure") {
+            "failure"
+        } else if self.statuses.is_empty() || has("pending") {
+            "pending"
+        } else {
+            "success"
+        }
+    }
+
+    pub fn close(&mut self) {
+        let now = Utc::now();
+        self.state = PullState::Closed;
+        self.closed_at = Some(now);
+        self.updated_at = now;
+    }
+
+    pub fn merge(&mut self, by: impl Into<String>) {
+        let now = Utc::now();
+        self.state = PullState::Merged;
+        self.merged_by = Some(by.into());
+        self.merged_at = Some(now);
+        self.closed_at = Some(now);
+        self.updated_at = now;
+    }
+
+    pub fn head_ref(&self) -> &str {
+        self.head.split(':').nth(1).unwrap_or(&self.head)
+    }
+
+    pub fn head_owner(&self) -> &str {
+        self.head.split(':').next().unwrap_or("")
+    }
+}
+
+/// Notification thread of the authenticated user
+#[derive(Clone, Debug)]
+pub struct FakeNotification {
+    pub id: u64,
+    /// Full name of the repository
+    pub repo: String,
+    pub title: String,
+    /// `Issue`, `PullRequest`, `Commit`, `Release`, ...
+    pub subject_type: String,
+    /// Number of the issue or PR the thread is about
+    pub number: Option<u64>,
+    /// `subscribed`, `mention`, `review_requested`, ...
+    pub reason: String,
+    pub unread: bool,
+    pub updated_at: DateTime<Utc>,
+    pub last_read_at: Option<DateTime<Utc>>,
+}
+
+impl FakeNotification {
+    pub fn new(repo: impl Into<String>, title: impl Into<String>) -> Self {
+        FakeNotification {
+            id: 0,
+            repo: repo.into(),
+            title: title.into(),
+            subject_type: "Issue".to_string(),
+            number: None,
+            reason: "subscribed".to_string(),
+            unread: true,
+            updated_at: Utc::now(),
+            last_read_at: None,
+        }
+    }
+
+    pub fn issue(mut self, number: u64) -> Self {
+        self.subject_type = "Issue".to_string();
+        self.number = Some(number);
+        self
+    }
+
+    pub fn pull(mut self, number: u64) -> Self {
+        self.subject_type = "PullRequest".to_string();
+        self.number = Some(number);
+        self
+    }
+
+    pub fn reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = reason.into();
+        self
+    }
+
+    pub fn updated_at(mut self, updated_at: DateTime<Utc>) -> Self {
+        self.updated_at = updated_at;
+        self
+    }
+
+    pub fn mark_read(&mut self) {
+        self.unread = false;
+        self.last_read_at = Some(Utc::now());
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_at: DateTime<Utc>,
+}
+
+impl RateLimit {
+    pub fn new(limit: u64) -> Self {
+        RateLimit {
+            limit,
+            remaining: limit,
+            reset_at: Utc::now() + Duration::hours(1),
+        }
+    }
+
+    /// Consume one request, returns false if the budget is exhausted
+    pub(crate) fn consume(&mut self) -> bool {
+        if Utc::now() >= self.reset_at {
+            *self = RateLimit::new(self.limit);
+        }
+
+        if self.remaining == 0 {
+            false
+        } else {
+            self.remaining -= 1;
+            true
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Failure {
+    pub status: u16,
+    pub body: Option<Value>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Failure {
+    pub fn status(status: u16) -> Self {
+        Failure {
+            status,
+            body: Some(json!({ "message": "Server Error" })),
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Failure {
+            status: 404,
+            body: Some(json!({
+                "message": "Not Found",
+                "documentation_url": "https://developer.github.com/v3",
+            })),
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn rate_limited() -> Self {
+        Failure {
+            status: 403,
+            body: Some(json!({
+                "message": "API rate limit exceeded for user ID 1.",
+                "documentation_url": "https://developer.github.com/v3/#rate-limiting",
+            })),
+            headers: vec![("X-RateLimit-Remaining".to_string(), "0".to_string())],
+        }
+    }
+
+    /// Abuse detection, not tied to the token quota
+    pub fn secondary_rate_limited() -> Self {
+        Failure {
+            status: 403,
+            body: Some(json!({
+                "message": "You have exceeded a secondary rate limit. Please wait a few minutes before you try again.",
+                "documentation_url": "https://developer.github.com/v3/#secondary-rate-limits",
+            })),
+            headers: vec![("Retry-After".to_string(), "60".to_string())],
+        }
+    }
+
+    pub fn body(mut self, body: Value) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    fn to_response(&self) -> Response {
+        Response {
+            status: self.status,
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+        }
+    }
+}
+
+pub(crate) struct ScriptedFailure {
+    route: String,
+    times: usize,
+    failure: Failure,
+}
+
+impl ScriptedFailure {
+    fn matches(&self, request: &Request) -> bool {
+        let mut route = self.route.splitn(2, ' ');
+        let method = route.next().unwrap_or("");
+        let path = route.next().unwrap_or("");
+
+        if !method.eq_ignore_ascii_case(&request.method) {
+            return false;
+        }
+
+        let pattern: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let segments = request.segments();
+
+        pattern.len() == segments.len()
+            && pattern
+                .iter()
+                .zip(segments.iter())
+                .all(|(pattern, segment)| *pattern == "*" || pattern == segment)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl<'a> From<&'a Request> for RecordedRequest {
+    fn from(request: &'a Request) -> Self {
+        RecordedRequest {
+            method: request.method.clone(),
+            path: request.path.clone(),
+            query: request.query.clone(),
+            headers: request.headers.clone(),
+            body: request.body.clone(),
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use failure::{err_msg, Error};
+use json;
+use serde::{Deserialize, Deserializer};
+
+use rustyrobot::github::tokens::TokenPool;
+use rustyrobot::github::transport::Transport;
+use rustyrobot::github::utils::{
+    load_response_cache, load_revisit_interval, load_token_pool, load_transport,
+};
+use rustyrobot::github::v3::Github as GithubV3;
+use rustyrobot::github::v4::Github as GithubV4;
+use rustyrobot::kafka::topic;
+use rustyrobot::kafka::util::producer::ThreadedProducerHandle;
+use rustyrobot::kafka::util::seen::SeenIndex;
+use rustyrobot::search::fields::FieldSet;
+use rustyrobot::search::query::{IncompleteQuery, SearchFor};
+
+use schedule::Schedule;
+use strategy::{
+    Chain, DateWindow, PushedWindow, RoundRobin, SeedList, Simple, StarWindow, Strategy,
+};
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Fetcher configuration, read from the JSON file named by FETCH_CONFIG
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    pub jobs: Vec<JobConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobConfig {
+    /// Scope of the job state keys, must be unique
+    pub name: String,
+    /// Search string as typed on GitHub, the default query is used if missing
+    #[serde(default)]
+    pub query: Option<String>,
+    #[serde(deserialize_with = "deserialize_schedule")]
+    pub schedule: Schedule,
+    pub strategy: StrategyConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StrategyConfig {
+    Simple,
+    Date {
+        #[serde(default = "one")]
+        days_per_request: u64,
+        #[serde(default)]
+        start_date: Option<NaiveDate>,
+        #[serde(default)]
+        end_date: Option<NaiveDate>,
+        #[serde(default)]
+        reverse: bool,
+    },
+    Pushed {
+        #[serde(default = "one")]
+        days_per_request: u64,
+        #[serde(default)]
+        lookback_days: Option<u64>,
+    },
+    Stars {
+        #[serde(default)]
+        top_stars: Option<u64>,
+        #[serde(default)]
+        min_stars: Option<u64>,
+    },
+    Seed {
+        path: PathBuf,
+    },
+    Chain {
+        strategies: Vec<StrategyConfig>,
+    },
+    RoundRobin {
+        strategies: Vec<StrategyConfig>,
+    },
+}
+
+impl Config {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let config: Config = json::from_reader(file)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Jobs sharing a name would share the state as well
+    fn validate(&self) -> Result<(), Error> {
+        let mut names = HashSet::new();
+        for job in &self.jobs {
+            if !names.insert(&job.name) {
+                return Err(err_msg(format!("duplicate job name {:?}", job.name)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Single unscoped job running every 20 minutes, FETCH_STRATEGY is one of "date" (default),
+    /// "pushed", "stars" or "seed", which reads the list from FETCH_SEED_LIST
+    pub fn from_env() -> Result<Self, Error> {
+        let strategy = match env::var("FETCH_STRATEGY") {
+            Ok(ref name) if name == "seed" => StrategyConfig::Seed {
+                path: env::var("FETCH_SEED_LIST")
+                    .map_err(|_| err_msg("FETCH_SEED_LIST is not set"))?
+                    .into(),
+            },
+            Ok(ref name) if name == "stars" => StrategyConfig::Stars {
+                top_stars: None,
+                min_stars: None,
+            },
+            Ok(ref name) if name == "pushed" => StrategyConfig::Pushed {
+                days_per_request: 1,
+                lookback_days: None,
+            },
+            Ok(ref name) if name != "date" => {
+                return Err(err_msg(format!("unknown FETCH_STRATEGY {:?}", name)))
+            }
+            _ => StrategyConfig::Date {
+                days_per_request: 1,
+                start_date: Some(NaiveDate::from_ymd(2018, 8, 10)),
+                end_date: None,
+                reverse: false,
+            },
+        };
+
+        Ok(Config {
+            jobs: vec![JobConfig {
+                name: String::new(),
+                query: None,
+                schedule: "*/20 * * * *".parse()?,
+                strategy,
+            }],
+        })
+    }
+}
+
+fn one() -> u64 {
+    1
+}
+
+fn deserialize_schedule<'de, D>(deserializer: D) -> Result<Schedule, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+    let schedule = String::deserialize(deserializer)?;
+    schedule.parse().map_err(D::Error::custom)
+}
+
+/// GitHub clients and producers shared by the strategies, created once needed.
+///
+/// Both clients go through the same transport and token pool, so they share the concurrency
+/// bound, the cassette and the rate limits with each other.
+pub struct Clients {
+    events: ThreadedProducerHandle,
+    github: Option<(Transport, Arc<TokenPool>)>,
+    v3: Option<Arc<GithubV3>>,
+    v4: Option<Arc<GithubV4>>,
+    seen: Option<Arc<Mutex<SeenIndex>>>,
+}
+
+impl Clients {
+    pub fn new(events: ThreadedProducerHandle) -> Self {
+        Clients {
+            events,
+            github: None,
+            v3: None,
+            v4: None,
+            seen: None,
+        }
+    }
+
+    fn github(&mut self) -> Result<(Transport, Arc<TokenPool>), Error> {
+        if self.github.is_none() {
+            let transport = load_transport()?;
+            let tokens = load_token_pool(&transport)?;
+            self.github = Some((transport, Arc::new(tokens)));
+        }
+        Ok(self.github.clone().unwrap())
+    }
+
+    fn v3(&mut self) -> Result<Arc<GithubV3>, Error> {
+        if self.v3.is_none() {
+            let (transport, tokens) = self.github()?;
+            let cache = load_response_cache()?;
+            let v3 = GithubV3::with_transport(tokens, transport).with_cache(Arc::new(cache));
+            self.v3 = Some(Arc::new(v3));
+        }
+        Ok(self.v3.clone().unwrap())
+    }
+
+    fn v4(&mut self) -> Result<Arc<GithubV4>, Error> {
+        if self.v4.is_none() {
+            let (transport, tokens) = self.github()?;
+            self.v4 = Some(Arc::new(GithubV4::with_transport(tokens, transport)?));
+        }
+        Ok(self.v4.clone().unwrap())
+    }
+
+    /// Visits go to the topic of the github service, each one reads the other's on start
+    fn seen(&mut self) -> Result<Arc<Mutex<SeenIndex>>, Error> {
+        if self.seen.is_none() {
+            let seen = SeenIndex::new(topic::GITHUB_SEEN, load_revisit_interval()?)?;
+            self.seen = Some(Arc::new(Mutex::new(seen)));
+        }
+        Ok(self.seen.clone().unwrap())
+    }
+}
+
+impl StrategyConfig {
+    pub fn build(&self, clients: &mut Clients) -> Result<Box<dyn Strategy>, Error> {
+        let strategy: Box<dyn Strategy> = match *self {
+            StrategyConfig::Simple => Box::new(Simple),
+            StrategyConfig::Date {
+                days_per_request,
+                start_date,
+                end_date,
+                reverse,
+            } => Box::new(DateWindow {
+                days_per_request,
+                start_date,
+                end_date,
+                reverse,
+                ..Default::default()
+            }),
+            StrategyConfig::Pushed {
+                days_per_request,
+                lookback_days,
+            } => {
+                let mut strategy = PushedWindow {
+                    days_per_request,
+                    ..Default::default()
+                };
+                if let Some(lookback_days) = lookback_days {
+                    strategy.lookback_days = lookback_days;
+                }
+                Box::new(strategy)
+            }
+            StrategyConfig::Stars {
+                top_stars,
+                min_stars,
+            } => {
+                let mut strategy = StarWindow::new(clients.v4()?);
+                if let Some(top_stars) = top_stars {
+                    strategy.top_stars = top_stars;
+                }
+                if let Some(min_stars) = min_stars {
+                    strategy.min_stars = min_stars;
+                }
+                Box::new(strategy)
+            }
+            StrategyConfig::Seed { ref path } => Box::new(SeedList::new(
+                clients.v3()?,
+                clients.events.clone(),
+                clients.seen()?,
+                path.clone(),
+            )),
+            StrategyConfig::Chain { ref strategies } => Box::new(Chain {
+                strategies: build_all(strategies, clients)?,
+            }),
+            StrategyConfig::RoundRobin { ref strategies } => Box::new(RoundRobin {
+                strategies: build_all(strategies, clients)?,
+            }),
+        };
+        Ok(strategy)
+    }
+}
+
+fn build_all(
+    configs: &[StrategyConfig],
+    clients: &mut Clients,
+) -> Result<Vec<Box<dyn Strategy>>, Error> {
+    configs.iter().map(|config| config.build(clients)).collect()
+}
+
+/// Strategy running on its own schedule
+pub struct Job {
+    pub name: String,
+    pub query: IncompleteQuery,
+    pub schedule: Schedule,
+    pub strategy: Box<dyn Strategy>,
+    /// None once the schedule has no runs left
+    pub next_run: Option<DateTime<Utc>>,
+}
+
+impl JobConfig {
+    pub fn build(
+        &self,
+        default_query: &IncompleteQuery,
+        clients: &mut Clients,
+    ) -> Result<Job, Error> {
+        let query = match self.query {
+            Some(ref query) => query.parse::<IncompleteQuery>()?,
+            None => default_query.clone(),
+        };
+
+        // The forker and pr-issuer policies decide on the optional repository fields
+        let query = query
+            .search_for(SearchFor::Repository)
+            .fields(FieldSet::all())
+            .count(100);
+
+        Ok(Job {
+            name: self.name.clone(),
+            query,
+            schedule: self.schedule.clone(),
+            strategy: self.strategy.build(clients)?,
+            // Every job runs right after start
+            next_run: Some(Utc::now()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config: Config = json::from_value(json!({
+            "jobs": [
+                {
+                    "name": "daily",
+                    "schedule": "*/20 * * * *",
+                    "strategy": { "type": "date", "start_date": "2018-08-10" }
+                },
+                {
+                    "name": "backfill",
+                    "query": "language:rust stars:>10",
+                    "schedule": "@daily",
+                    "strategy": {
+                        "type": "round_robin",
+                        "strategies": [
+                            { "type": "pushed", "lookback_days": 7 },
+                            { "type": "simple" }
+                        ]
+                    }
+                }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(config.jobs[0].schedule.to_string(), "*/20 * * * *");
+        match config.jobs[0].strategy {
+            StrategyConfig::Date {
+                days_per_request,
+                start_date,
+                end_date,
+                reverse,
+            } => {
+                assert_eq!(days_per_request, 1);
+                assert_eq!(start_date, Some(NaiveDate::from_ymd(2018, 8, 10)));
+                assert_eq!(end_date, None);
+                assert!(!reverse);
+            }
+            ref other => panic!("unexpected strategy {:?}", other),
+        }
+        match config.jobs[1].strategy {
+            StrategyConfig::RoundRobin { ref strategies } => assert_eq!(strategies.len(), 2),
+            ref other => panic!("unexpected strategy {:?}", other),
+        }
+
+        let invalid = json::from_value::<Config>(json!({
+            "jobs": [{ "name": "x", "schedule": "every day", "strategy": { "type": "simple" } }]
+        }));
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn job_names_are_unique() {
+        let job =
+            json!({ "name": "daily", "schedule": "@daily", "strategy": { "type": "simple" } });
+        let config: Config =
+            json::from_value(json!({ "jobs": [job.clone(), job.clone()] })).unwrap();
+        assert!(config.validate().is_err());
+
+        let config: Config = json::from_value(json!({ "jobs": [job] })).unwrap();
+        assert!(config.validate().is_ok());
+    }
+}
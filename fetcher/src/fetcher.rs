@@ -11,6 +11,30 @@ pub struct FetcherState<'a> {
     pub shutdown: GracefulShutdownHandle,
     pub state: &'a mut StateHandler,
     pub producer: ThreadedProducerHandle,
+    /// Prefix of the state keys, so that strategies sharing the state don't clash
+    pub scope: String,
+}
+
+impl<'a> FetcherState<'a> {
+    /// State key of the strategy, e.g. `daily.last_date` in the `daily` scope
+    pub fn key(&self, key: &str) -> String {
+        if self.scope.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.scope, key)
+        }
+    }
+
+    /// State of the nested strategy, keyed under `child` in the current scope
+    pub fn nested(&mut self, child: &str) -> FetcherState {
+        let scope = self.key(child);
+        FetcherState {
+            shutdown: self.shutdown.clone(),
+            state: &mut *self.state,
+            producer: self.producer.clone(),
+            scope,
+        }
+    }
 }
 
 pub struct Fetcher<'a, S: Strategy> {
@@ -48,11 +72,18 @@ impl<'a, S: Strategy> Fetcher<'a, S> {
                 shutdown,
                 state,
                 producer,
+                scope: String::new(),
             },
             strategy,
         }
     }
 
+    /// Keep the strategy state under `scope`, unscoped keys are used by default
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.state.scope = scope.into();
+        self
+    }
+
     pub fn fetch(&mut self, base_query: IncompleteQuery) -> Result<(), Error> {
         self.strategy.execute(&mut self.state, base_query)?;
         Ok(())
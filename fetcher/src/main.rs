@@ -3,19 +3,24 @@ extern crate rustyrobot;
 extern crate chrono;
 extern crate fern;
 extern crate serde;
+#[macro_use]
 extern crate serde_derive;
+#[cfg_attr(test, macro_use)]
 extern crate serde_json as json;
 #[macro_use]
 extern crate log;
+extern crate csv;
 extern crate ctrlc;
+#[macro_use]
 extern crate failure;
 
+mod config;
 mod fetcher;
+mod schedule;
 mod strategy;
 
 use failure::Error;
 
-use chrono::Duration;
 use std::time::Duration as StdDuration;
 
 fn init_fern() -> Result<(), Error> {
@@ -47,15 +52,16 @@ use rustyrobot::{
     },
     search::{
         query::SearchFor,
-        query::{Lang, Query},
+        query::{IncompleteQuery, Lang, Query},
     },
     shutdown::GracefulShutdown,
 };
 
-use chrono::{NaiveDate, Utc};
+use chrono::Utc;
+use config::{Clients, Config, Job};
 use fetcher::Fetcher;
+use std::env;
 use std::thread;
-use strategy::DateWindow;
 
 fn main() {
     init_fern().unwrap();
@@ -79,42 +85,61 @@ fn main() {
     let producer = ThreadedProducer::new(topic::GITHUB_REQUEST, shutdown.thread_handle())
         .expect("couldn't start producer");
 
-    // Create base query
-    let query = Query::builder()
-        .lang(Lang::Rust)
-        .search_for(SearchFor::Repository)
-        .owner("mersinvald")
-        .count(100);
-
-    // Setup fetching strategy
-    let mut strategy = DateWindow {
-        days_per_request: 1,
-        start_date: Some(NaiveDate::from_ymd(2018, 8, 10)),
-        ..Default::default()
+    // Create base query, FETCH_QUERY takes a search string as typed on GitHub
+    let query = match env::var("FETCH_QUERY") {
+        Ok(query) => query
+            .parse::<IncompleteQuery>()
+            .expect("invalid FETCH_QUERY"),
+        Err(_) => Query::builder().lang(Lang::Rust).owner("mersinvald"),
     };
+    let query = query.search_for(SearchFor::Repository).count(100);
+    info!("fetching repositories by {:?}", query.to_string());
 
-    let fetch_period = Duration::minutes(20);
-    let mut fetch_time = Utc::now();
+    // Seed lists bypass the github service and emit the fetched repositories themselves
+    let events = ThreadedProducer::new(topic::EVENT, shutdown.thread_handle())
+        .expect("couldn't start producer");
+
+    // Jobs are read from FETCH_CONFIG if set, a single job is set up from the env otherwise
+    let config = match env::var("FETCH_CONFIG") {
+        Ok(path) => Config::load(&path).expect("failed to load FETCH_CONFIG"),
+        Err(_) => Config::from_env().expect("invalid fetcher configuration"),
+    };
+    let mut clients = Clients::new(events.handle());
+    let mut jobs = config
+        .jobs
+        .iter()
+        .map(|job| job.build(&query, &mut clients))
+        .collect::<Result<Vec<Job>, Error>>()
+        .expect("failed to set up fetch jobs");
 
     while !shutdown.thread_handle().should_shutdown() {
-        if Utc::now() >= fetch_time {
+        for job in &mut jobs {
+            match job.next_run {
+                Some(next_run) if Utc::now() >= next_run => (),
+                _ => continue,
+            }
+            if shutdown.thread_handle().should_shutdown() {
+                break;
+            }
+
+            info!("running fetch job {:?}", job.name);
             let mut fetcher = Fetcher::new(
                 &mut state,
                 producer.handle(),
                 shutdown.thread_handle(),
-                strategy.clone(),
-            );
-
-            // Resetting start_date in strategy so we won't start over in next iteration
-            strategy.start_date = None;
+                &mut *job.strategy,
+            )
+            .scope(job.name.clone());
 
-            // If that fails, fetcher will start from last successful data
-            if let Err(e) = fetcher.fetch(query.clone()) {
-                error!("failed to submit fetch requests: {}", e);
+            // If that fails, the job is retried and starts from the last successful data
+            if let Err(e) = fetcher.fetch(job.query.clone()) {
+                error!("job {:?} failed to submit fetch requests: {}", job.name, e);
             } else {
-                // If success, moving fetch_time one period into the future,
-                // next iteration will start from Utc::today()
-                fetch_time = Utc::now() + fetch_period;
+                job.next_run = job.schedule.next_after(Utc::now());
+                match job.next_run {
+                    Some(next_run) => info!("next run of job {:?} at {}", job.name, next_run),
+                    None => warn!("job {:?} has no runs left in {}", job.name, job.schedule),
+                }
             }
         }
         thread::sleep(StdDuration::from_secs(1));
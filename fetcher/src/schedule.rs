@@ -0,0 +1,248 @@
+use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc};
+
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Fail)]
+pub enum ScheduleError {
+    #[fail(display = "expected 5 fields in {:?}", schedule)]
+    FieldCount { schedule: String },
+    #[fail(display = "invalid {} field {:?}", field, value)]
+    InvalidField { field: &'static str, value: String },
+}
+
+/// Cron-like schedule in UTC: `minute hour day-of-month month day-of-week`.
+///
+/// Fields are `*`, numbers, ranges `a-b` and steps `*/n` or `a-b/n`, separated by commas.
+/// Sunday is both 0 and 7 and, like in cron, a day matches if either of the day fields does
+/// when both are restricted. `@hourly`, `@daily`, `@weekly` and `@monthly` are accepted too.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const FIELDS: [(&str, u32, u32); 5] = [
+    ("minute", 0, 59),
+    ("hour", 0, 23),
+    ("day of month", 1, 31),
+    ("month", 1, 12),
+    ("day of week", 0, 7),
+];
+
+impl Schedule {
+    pub fn matches(&self, time: DateTime<Utc>) -> bool {
+        let has = |set: u64, value: u32| set & (1 << value) != 0;
+
+        let day = has(self.days, time.day());
+        let weekday = has(self.weekdays, time.weekday().num_days_from_sunday());
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+
+        day_matches
+            && has(self.minutes, time.minute())
+            && has(self.hours, time.hour())
+            && has(self.months, time.month())
+    }
+
+    /// The first minute strictly after `time` the schedule matches, None if there is none
+    /// within a few years, e.g. for February 30th
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start =
+            Utc.ymd(time.year(), time.month(), time.day())
+                .and_hms(time.hour(), time.minute(), 0);
+        let mut next = start + Duration::minutes(1);
+        let limit = start + Duration::days(5 * 366);
+
+        while next < limit {
+            if self.matches(next) {
+                return Some(next);
+            }
+            // Skip whole days and hours that can't match
+            let day_matches = self.matches_day(next);
+            next = if !day_matches {
+                Utc.ymd(next.year(), next.month(), next.day())
+                    .and_hms(0, 0, 0)
+                    + Duration::days(1)
+            } else if self.hours & (1 << next.hour()) == 0 {
+                next.with_minute(0).unwrap() + Duration::hours(1)
+            } else {
+                next + Duration::minutes(1)
+            };
+        }
+        None
+    }
+
+    fn matches_day(&self, time: DateTime<Utc>) -> bool {
+        let midnight = Utc
+            .ymd(time.year(), time.month(), time.day())
+            .and_hms(0, 0, 0);
+        let whole_day = Schedule {
+            minutes: !0,
+            hours: !0,
+            ..self.clone()
+        };
+        whole_day.matches(midnight)
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ScheduleError;
+
+    fn from_str(schedule: &str) -> Result<Self, Self::Err> {
+        let expanded = match schedule.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != FIELDS.len() {
+            return Err(ScheduleError::FieldCount {
+                schedule: schedule.to_string(),
+            });
+        }
+
+        let mut sets = [0u64; 5];
+        for (idx, &(name, min, max)) in FIELDS.iter().enumerate() {
+            sets[idx] = parse_field(fields[idx], min, max).ok_or(ScheduleError::InvalidField {
+                field: name,
+                value: fields[idx].to_string(),
+            })?;
+        }
+
+        // Sunday is 7 as well as 0
+        let mut weekdays = sets[4];
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+
+        Ok(Schedule {
+            source: schedule.to_string(),
+            minutes: sets[0],
+            hours: sets[1],
+            days: sets[2],
+            months: sets[3],
+            weekdays,
+            any_day: fields[2] == "*",
+            any_weekday: fields[4] == "*",
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+/// Bit set of the values matched by a field
+fn parse_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut set = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.find('/') {
+            Some(idx) => (&item[..idx], item[idx + 1..].parse::<u32>().ok()?),
+            None => (item, 1),
+        };
+        if step == 0 {
+            return None;
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some(idx) = range.find('-') {
+            (range[..idx].parse().ok()?, range[idx + 1..].parse().ok()?)
+        } else {
+            let value = range.parse().ok()?;
+            // `a/n` runs from `a` to the end of the range
+            (value, if item.contains('/') { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+
+        let mut value = start;
+        while value <= end {
+            set |= 1 << value;
+            value += step;
+        }
+    }
+    Some(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: (i32, u32, u32), time: (u32, u32)) -> DateTime<Utc> {
+        Utc.ymd(date.0, date.1, date.2).and_hms(time.0, time.1, 0)
+    }
+
+    #[test]
+    fn next_runs() {
+        let every_20m: Schedule = "*/20 * * * *".parse().unwrap();
+        assert_eq!(
+            every_20m.next_after(at((2018, 8, 10), (12, 0))),
+            Some(at((2018, 8, 10), (12, 20)))
+        );
+        assert_eq!(
+            every_20m.next_after(at((2018, 8, 10), (23, 45))),
+            Some(at((2018, 8, 11), (0, 0)))
+        );
+
+        // 2018-08-10 is a Friday
+        let weekends: Schedule = "30 6 * * 6,7".parse().unwrap();
+        assert_eq!(
+            weekends.next_after(at((2018, 8, 10), (7, 0))),
+            Some(at((2018, 8, 11), (6, 30)))
+        );
+        assert_eq!(
+            weekends.next_after(at((2018, 8, 11), (6, 30))),
+            Some(at((2018, 8, 12), (6, 30)))
+        );
+
+        // Either day field matches when both are restricted
+        let first_or_monday: Schedule = "0 0 1 * 1".parse().unwrap();
+        assert_eq!(
+            first_or_monday.next_after(at((2018, 8, 10), (0, 0))),
+            Some(at((2018, 8, 13), (0, 0)))
+        );
+        assert_eq!(
+            first_or_monday.next_after(at((2018, 8, 27), (0, 0))),
+            Some(at((2018, 9, 1), (0, 0)))
+        );
+
+        let monthly: Schedule = "@monthly".parse().unwrap();
+        assert_eq!(
+            monthly.next_after(at((2018, 12, 31), (23, 59))),
+            Some(at((2019, 1, 1), (0, 0)))
+        );
+
+        let never: Schedule = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(never.next_after(at((2018, 1, 1), (0, 0))), None);
+    }
+
+    #[test]
+    fn invalid_schedules() {
+        for schedule in &[
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(schedule.parse::<Schedule>().is_err(), "{}", schedule);
+        }
+        assert!("0-59/15 1,13 1-31 */2 0-7".parse::<Schedule>().is_ok());
+    }
+}
@@ -0,0 +1,58 @@
+use super::Strategy;
+
+use failure::Error;
+
+use rustyrobot::search::query::IncompleteQuery;
+
+use fetcher::FetcherState;
+
+/// Index of the strategy to run next
+const NEXT_KEY: &str = "round_robin_next";
+
+/// Runs every strategy in turn on the same query.
+///
+/// The state of each strategy is kept under its index, e.g. `daily.1.last_date`.
+pub struct Chain {
+    pub strategies: Vec<Box<dyn Strategy>>,
+}
+
+impl Strategy for Chain {
+    /// Run query using the strategy logic
+    fn execute(&mut self, shared: &mut FetcherState, query: IncompleteQuery) -> Result<(), Error> {
+        for (idx, strategy) in self.strategies.iter_mut().enumerate() {
+            if shared.shutdown.should_shutdown() {
+                break;
+            }
+            strategy.execute(&mut shared.nested(&idx.to_string()), query.clone())?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs one of the strategies per execution, taking turns.
+///
+/// The state of each strategy is kept under its index, like in `Chain`.
+pub struct RoundRobin {
+    pub strategies: Vec<Box<dyn Strategy>>,
+}
+
+impl Strategy for RoundRobin {
+    /// Run query using the strategy logic
+    fn execute(&mut self, shared: &mut FetcherState, query: IncompleteQuery) -> Result<(), Error> {
+        if self.strategies.is_empty() {
+            return Ok(());
+        }
+
+        let next: i64 = shared.state.get_or_default(shared.key(NEXT_KEY));
+        let idx = next as usize % self.strategies.len();
+        self.strategies[idx].execute(&mut shared.nested(&idx.to_string()), query)?;
+
+        // A failed strategy is retried before moving on to the next one
+        shared.state.set(
+            shared.key(NEXT_KEY),
+            ((idx + 1) % self.strategies.len()) as i64,
+        );
+        shared.state.sync()?;
+        Ok(())
+    }
+}
@@ -5,24 +5,31 @@ use chrono::NaiveDate;
 use chrono::Utc;
 use failure::Error;
 
-use rustyrobot::search::query::IncompleteQuery;
+use rustyrobot::search::query::{Bounds, IncompleteQuery};
+use rustyrobot::search::window::EARLIEST_DATE;
 
 use fetcher::FetcherState;
 
+/// Marks the bounded sweep that has been completed, as `<direction>:<end date>`
+const COMPLETED_KEY: &str = "completed";
+
 #[derive(Default, Clone)]
 pub struct DateWindow {
     /// Date step length
     pub days_per_request: u64,
 
-    /// The repo creation date to begin searching from
+    /// The repo creation date to begin searching from, reset to None once used
     /// If None -- the last processed date would be fetched from DB
     /// If DB entry is empty -- Utc::today would be used
     pub start_date: Option<NaiveDate>,
 
-    /// The repo creation date to finish the search
-    /// If None -- Utc::today() is used
+    /// The repo creation date to finish the search, a future one is followed up to today
+    /// If None -- Utc::today() is used, or the GitHub launch date when sweeping backwards
     pub end_date: Option<NaiveDate>,
 
+    /// Sweep from the newest to the oldest dates
+    pub reverse: bool,
+
     pub state: DateWindowState,
 }
 
@@ -39,42 +46,218 @@ impl Default for DateWindowState {
     }
 }
 
+impl DateWindow {
+    /// The date the sweep finishes at, None for the forward sweeps following Utc::today()
+    fn bound(&self) -> Option<NaiveDate> {
+        if self.reverse {
+            let (year, month, day) = EARLIEST_DATE;
+            Some(
+                self.end_date
+                    .unwrap_or_else(|| NaiveDate::from_ymd(year, month, day)),
+            )
+        } else {
+            self.end_date
+        }
+    }
+
+    /// The date this run stops at, forward sweeps don't go past today
+    fn run_end(&self, today: NaiveDate) -> NaiveDate {
+        match self.bound() {
+            Some(bound) if !self.reverse => bound.min(today),
+            Some(bound) => bound,
+            None => today,
+        }
+    }
+
+    /// Inclusive window continuing the sweep from `date`, None once the sweep is past `end`
+    fn window(&self, date: NaiveDate, end: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+        let step = Duration::days(self.days_per_request as i64);
+        if self.reverse {
+            if date < end {
+                None
+            } else {
+                Some(((date - step).max(end), date))
+            }
+        } else if date > end {
+            None
+        } else {
+            Some((date, (date + step).min(end)))
+        }
+    }
+
+    /// The date the sweep continues from after the window
+    fn next_date(&self, (window_start, window_end): (NaiveDate, NaiveDate)) -> NaiveDate {
+        if self.reverse {
+            window_start.pred()
+        } else {
+            window_end.succ()
+        }
+    }
+
+    /// Marker of the sweep completed by this run, None if the sweep goes on after it.
+    ///
+    /// A sweep to a future date is only completed once the date comes.
+    fn completed_marker(&self, today: NaiveDate) -> Option<String> {
+        let direction = if self.reverse { "reverse" } else { "forward" };
+        self.bound()
+            .filter(|bound| *bound == self.run_end(today))
+            .map(|end| format!("{}:{}", direction, end.format("%Y-%m-%d")))
+    }
+}
+
 impl Strategy for DateWindow {
     /// Run query using the strategy logic
     fn execute(&mut self, shared: &mut FetcherState, query: IncompleteQuery) -> Result<(), Error> {
-        let start_date = if let Some(start_date) = self.start_date {
+        let today = Utc::today().naive_utc();
+        let end = self.run_end(today);
+
+        // Finished backfills aren't restarted, unless the sweep is set up differently
+        let marker = self.completed_marker(today);
+        let completed: Option<String> = shared.state.get_or_default(shared.key(COMPLETED_KEY));
+        if marker.is_some() && completed == marker {
+            debug!("sweep to {} is already completed", end);
+            return Ok(());
+        }
+
+        // start_date is only used once, following runs continue from the last processed date
+        let start_date = if let Some(start_date) = self.start_date.take() {
             start_date
         } else {
-            let date: String = shared.state.get_or_default("last_date");
+            let date: String = shared.state.get_or_default(shared.key("last_date"));
             NaiveDate::parse_from_str(&date, "%Y-%m-%d").unwrap_or_else(|e| {
                 error!("failed to parse last_date: {}", e);
                 error!("using Utc::today()");
-                Utc::today().naive_utc()
+                today
             })
         };
 
         self.state.date = start_date;
-        let step = Duration::days(self.days_per_request as i64);
 
-        while self.state.date <= Utc::today().naive_utc() && !shared.shutdown.should_shutdown() {
-            let window_start = self.state.date.format("%Y-%m-%d").to_string();
-            let window_end = self.state.date + step;
-            shared.state.set("last_date", window_start.clone());
-            shared.state.sync()?;
-            self.state.date = window_end.succ();
+        loop {
+            if shared.shutdown.should_shutdown() {
+                return Ok(());
+            }
+
+            let (window_start, window_end) = match self.window(self.state.date, end) {
+                Some(window) => window,
+                None => break,
+            };
 
-            let date_query_segment = format!(
-                "created:{}..{}",
-                window_start,
-                window_end.format("%Y-%m-%d")
+            // The date the sweep continues from after restart
+            let resume_date = if self.reverse {
+                window_end
+            } else {
+                window_start
+            };
+            shared.state.set(
+                shared.key("last_date"),
+                resume_date.format("%Y-%m-%d").to_string(),
             );
-            info!("requesting {}", date_query_segment);
-            let query = query.clone().raw_query(date_query_segment);
+            shared.state.sync()?;
+            self.state.date = self.next_date((window_start, window_end));
+
+            let window = Bounds::Between(window_start, window_end);
+            info!("requesting created:{}", window);
+            let query = query.clone().created(window);
 
             // Reuse simple strategy for making single request
             super::Simple.execute(shared, query)?;
         }
 
+        if let Some(marker) = marker {
+            info!("sweep to {} is completed", end);
+            shared.state.set(shared.key(COMPLETED_KEY), marker);
+            shared.state.sync()?;
+        }
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2018, 8, day)
+    }
+
+    fn sweep(
+        strategy: &DateWindow,
+        from: NaiveDate,
+        today: NaiveDate,
+    ) -> Vec<(NaiveDate, NaiveDate)> {
+        let end = strategy.run_end(today);
+        let mut windows = Vec::new();
+        let mut date = from;
+        while let Some(window) = strategy.window(date, end) {
+            windows.push(window);
+            date = strategy.next_date(window);
+        }
+        windows
+    }
+
+    #[test]
+    fn windows_are_cut_at_the_end_date() {
+        let strategy = DateWindow {
+            days_per_request: 1,
+            end_date: Some(day(14)),
+            ..Default::default()
+        };
+        assert_eq!(
+            sweep(&strategy, day(10), day(20)),
+            vec![(day(10), day(11)), (day(12), day(13)), (day(14), day(14))]
+        );
+        assert_eq!(
+            strategy.completed_marker(day(20)),
+            Some("forward:2018-08-14".to_string())
+        );
+
+        let unbounded = DateWindow {
+            days_per_request: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            sweep(&unbounded, day(10), day(12)),
+            vec![(day(10), day(11)), (day(12), day(12))]
+        );
+        assert_eq!(unbounded.completed_marker(day(12)), None);
+    }
+
+    #[test]
+    fn future_end_date_stops_at_today() {
+        let strategy = DateWindow {
+            days_per_request: 1,
+            end_date: Some(day(20)),
+            ..Default::default()
+        };
+        assert_eq!(
+            sweep(&strategy, day(10), day(12)),
+            vec![(day(10), day(11)), (day(12), day(12))]
+        );
+        assert_eq!(strategy.completed_marker(day(12)), None);
+        assert_eq!(strategy.completed_marker(day(19)), None);
+        assert_eq!(
+            strategy.completed_marker(day(20)),
+            Some("forward:2018-08-20".to_string())
+        );
+    }
+
+    #[test]
+    fn reverse_windows_go_back_to_the_end_date() {
+        let strategy = DateWindow {
+            days_per_request: 1,
+            end_date: Some(day(10)),
+            reverse: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            sweep(&strategy, day(14), day(20)),
+            vec![(day(13), day(14)), (day(11), day(12)), (day(10), day(10))]
+        );
+        assert_eq!(
+            strategy.completed_marker(day(20)),
+            Some("reverse:2018-08-10".to_string())
+        );
+    }
+}
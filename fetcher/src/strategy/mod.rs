@@ -1,8 +1,16 @@
+pub mod chain;
 pub mod datewindow;
+pub mod pushedwindow;
+pub mod seedlist;
 pub mod simple;
+pub mod starwindow;
 
+pub use self::chain::{Chain, RoundRobin};
 pub use self::datewindow::DateWindow;
+pub use self::pushedwindow::PushedWindow;
+pub use self::seedlist::SeedList;
 pub use self::simple::Simple;
+pub use self::starwindow::StarWindow;
 
 use failure::Error;
 
@@ -18,3 +26,13 @@ pub trait Strategy {
         query: IncompleteQuery,
     ) -> Result<(), Error>;
 }
+
+impl<'s, S: Strategy + ?Sized> Strategy for &'s mut S {
+    fn execute<'a>(
+        &mut self,
+        shared: &mut FetcherState<'a>,
+        query: IncompleteQuery,
+    ) -> Result<(), Error> {
+        (**self).execute(shared, query)
+    }
+}
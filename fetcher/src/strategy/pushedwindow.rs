@@ -0,0 +1,134 @@
+use super::Strategy;
+
+use chrono::Duration;
+use chrono::NaiveDate;
+use chrono::Utc;
+use failure::Error;
+
+use rustyrobot::search::query::{Bounds, IncompleteQuery};
+
+use fetcher::FetcherState;
+
+/// Start of the last swept window
+const CURSOR_KEY: &str = "pushed_last_date";
+
+/// Sweeps `pushed:` windows up to today on a rolling basis.
+///
+/// Every run continues from the window the previous one stopped at, so repositories
+/// pushed to since then are fetched again, no matter when they were created.
+#[derive(Clone)]
+pub struct PushedWindow {
+    /// Date step length, windows span `days_per_request + 1` days like in `DateWindow`
+    pub days_per_request: u64,
+
+    /// How far back the first sweep starts, when there is no saved position
+    pub lookback_days: u64,
+}
+
+impl Default for PushedWindow {
+    fn default() -> Self {
+        PushedWindow {
+            days_per_request: 1,
+            lookback_days: 30,
+        }
+    }
+}
+
+impl PushedWindow {
+    /// Inclusive window starting at `date`, None once the sweep is past today
+    fn window(&self, date: NaiveDate, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+        if date > today {
+            return None;
+        }
+        let step = Duration::days(self.days_per_request as i64);
+        Some((date, (date + step).min(today)))
+    }
+}
+
+impl Strategy for PushedWindow {
+    /// Run query using the strategy logic
+    fn execute(&mut self, shared: &mut FetcherState, query: IncompleteQuery) -> Result<(), Error> {
+        let today = Utc::today().naive_utc();
+        let saved: Option<String> = shared.state.get_or_default(shared.key(CURSOR_KEY));
+        let mut date = match saved.map(|date| NaiveDate::parse_from_str(&date, "%Y-%m-%d")) {
+            Some(Ok(date)) => date,
+            Some(Err(e)) => {
+                error!("failed to parse {}: {}", CURSOR_KEY, e);
+                today - Duration::days(self.lookback_days as i64)
+            }
+            None => today - Duration::days(self.lookback_days as i64),
+        };
+
+        while !shared.shutdown.should_shutdown() {
+            let (window_start, window_end) = match self.window(date, today) {
+                Some(window) => window,
+                None => break,
+            };
+            // Today's window keeps growing, the next run starts over from it
+            shared.state.set(
+                shared.key(CURSOR_KEY),
+                window_start.format("%Y-%m-%d").to_string(),
+            );
+            shared.state.sync()?;
+            date = window_end.succ();
+
+            let window = if window_start == window_end {
+                Bounds::Exactly(window_start)
+            } else {
+                Bounds::Between(window_start, window_end)
+            };
+            info!("requesting pushed:{}", window);
+            let query = query.clone().pushed(window);
+
+            // Reuse simple strategy for making single request
+            super::Simple.execute(shared, query)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(
+        strategy: &PushedWindow,
+        from: NaiveDate,
+        today: NaiveDate,
+    ) -> Vec<(NaiveDate, NaiveDate)> {
+        let mut windows = Vec::new();
+        let mut date = from;
+        while let Some(window) = strategy.window(date, today) {
+            windows.push(window);
+            date = window.1.succ();
+        }
+        windows
+    }
+
+    #[test]
+    fn windows_reach_today() {
+        let day = |d| NaiveDate::from_ymd(2018, 8, d);
+        let strategy = PushedWindow {
+            days_per_request: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            sweep(&strategy, day(10), day(14)),
+            vec![(day(10), day(11)), (day(12), day(13)), (day(14), day(14))]
+        );
+
+        let strategy = PushedWindow {
+            days_per_request: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            sweep(&strategy, day(13), day(14)),
+            vec![(day(13), day(13)), (day(14), day(14))]
+        );
+
+        // Resumed at the window of today
+        assert_eq!(sweep(&strategy, day(14), day(14)), vec![(day(14), day(14))]);
+        assert!(sweep(&strategy, day(15), day(14)).is_empty());
+    }
+}
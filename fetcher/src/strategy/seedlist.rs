@@ -0,0 +1,279 @@
+use super::Strategy;
+
+use chrono::Utc;
+use csv;
+use failure::Error;
+use json::Value;
+
+use rustyrobot::github::tokens::Affinity;
+use rustyrobot::github::v3::{ExecutorExt, Github as GithubV3, StatusCode};
+use rustyrobot::github::ErrorKind;
+use rustyrobot::kafka::util::producer::ThreadedProducerHandle;
+use rustyrobot::kafka::util::seen::SeenIndex;
+use rustyrobot::kafka::Event;
+use rustyrobot::search::query::IncompleteQuery;
+use rustyrobot::search::NodeType;
+use rustyrobot::types::Repository;
+
+use fetcher::FetcherState;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// The list the last seed belongs to
+const PATH_KEY: &str = "seed_list_path";
+/// Name of the last seed handled, the list is resumed after it
+const LAST_SEED_KEY: &str = "seed_list_last";
+
+/// Fetches the repositories named in a local file instead of searching for them.
+///
+/// The file is either a plain list of `owner/name` or GitHub URLs, one per line, or a CSV
+/// with a header, like `crates.csv` of the crates.io database dump. The `repository` column
+/// of a CSV is used if there is one, the first column otherwise. Repositories hosted
+/// elsewhere are skipped, so are the ones fetched within the revisit interval.
+#[derive(Clone)]
+pub struct SeedList {
+    gh: Arc<GithubV3>,
+    events: ThreadedProducerHandle,
+    seen: Arc<Mutex<SeenIndex>>,
+    pub path: PathBuf,
+}
+
+impl SeedList {
+    pub fn new(
+        gh: Arc<GithubV3>,
+        events: ThreadedProducerHandle,
+        seen: Arc<Mutex<SeenIndex>>,
+        path: impl Into<PathBuf>,
+    ) -> Self {
+        SeedList {
+            gh,
+            events,
+            seen,
+            path: path.into(),
+        }
+    }
+
+    /// Emit the repository unless it was fetched recently, by the github service as well
+    fn emit(&self, repo: Repository) -> Result<(), Error> {
+        let now = Utc::now();
+        let mut seen = self.seen.lock().unwrap();
+        if !seen.is_due(&repo.id, now) {
+            debug!("{} was fetched recently, skipping", repo.name_with_owner);
+            return Ok(());
+        }
+
+        let id = repo.id.clone();
+        self.events.send(Event::RepositoryFetched(repo))?;
+        seen.visit(&id, now)
+    }
+
+    fn resolve(&self, name: &str) -> Result<Repository, Error> {
+        let value: Value = self
+            .gh
+            .get()
+            .custom_endpoint(&format!("repos/{}", name))
+            .affinity(Affinity::Bot)
+            .send(&[StatusCode::OK])?;
+        Repository::from_value(value)
+    }
+}
+
+impl Strategy for SeedList {
+    /// Run query using the strategy logic
+    fn execute(&mut self, shared: &mut FetcherState, query: IncompleteQuery) -> Result<(), Error> {
+        debug!("seed list ignores the base query {}", query);
+        let seeds = parse_seeds(&fs::read_to_string(&self.path)?)?;
+
+        // Lines appended to the same list are picked up on the next run
+        let path = self.path.to_string_lossy().to_string();
+        let saved_path: Option<String> = shared.state.get_or_default(shared.key(PATH_KEY));
+        let last_seed: Option<String> = if saved_path.as_ref() == Some(&path) {
+            shared.state.get_or_default(shared.key(LAST_SEED_KEY))
+        } else {
+            shared.state.set(shared.key(PATH_KEY), path);
+            None
+        };
+        let position = resume_position(&seeds, last_seed.as_ref().map(String::as_str));
+
+        info!(
+            "{} repositories in {}, {} already fetched",
+            seeds.len(),
+            self.path.display(),
+            position
+        );
+
+        for name in seeds.iter().skip(position) {
+            if shared.shutdown.should_shutdown() {
+                break;
+            }
+
+            match self.resolve(name) {
+                Ok(repo) => self.emit(repo)?,
+                // The next run retries the seed after transient failures
+                Err(e) => match ErrorKind::of(&e) {
+                    ErrorKind::Unauthorized | ErrorKind::Retryable | ErrorKind::Other => {
+                        return Err(e)
+                    }
+                    _ => warn!("skipping {}: {}", name, e),
+                },
+            }
+            shared.state.set(shared.key(LAST_SEED_KEY), name.as_str());
+            shared.state.sync()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Index of the seed following `last_seed`.
+///
+/// The list starts over if the seed is gone, e.g. the list was regenerated, the recently
+/// fetched repositories are skipped by the seen index then.
+fn resume_position(seeds: &[String], last_seed: Option<&str>) -> usize {
+    last_seed
+        .and_then(|last| {
+            seeds
+                .iter()
+                .position(|seed| seed.eq_ignore_ascii_case(last))
+        })
+        .map_or(0, |idx| idx + 1)
+}
+
+/// `owner/name` of every GitHub repository in the list, without duplicates
+pub fn parse_seeds(text: &str) -> Result<Vec<String>, Error> {
+    let first_line = text
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .unwrap_or("");
+
+    let entries: Vec<String> = if first_line.contains(',') {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(text.as_bytes());
+        let column = reader
+            .headers()?
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case("repository"))
+            .unwrap_or(0);
+
+        let mut entries = Vec::new();
+        for record in reader.records() {
+            if let Some(entry) = record?.get(column) {
+                entries.push(entry.to_string());
+            }
+        }
+        entries
+    } else {
+        text.lines().map(|line| line.to_string()).collect()
+    };
+
+    let mut seen = HashSet::new();
+    Ok(entries
+        .iter()
+        .filter_map(|entry| repo_name(entry))
+        .filter(|name| seen.insert(name.to_lowercase()))
+        .collect())
+}
+
+/// `owner/name` of a GitHub repository given either as is or as an URL
+fn repo_name(entry: &str) -> Option<String> {
+    let entry = entry.trim();
+    if entry.is_empty() || entry.starts_with('#') {
+        return None;
+    }
+
+    let path = if let Some(idx) = entry.find("://") {
+        let rest = &entry[idx + 3..];
+        let rest = rest.trim_left_matches("git@").trim_left_matches("www.");
+        if !rest.starts_with("github.com/") {
+            return None;
+        }
+        &rest["github.com/".len()..]
+    } else if entry.starts_with("git@github.com:") {
+        &entry["git@github.com:".len()..]
+    } else if entry.starts_with("github.com/") {
+        &entry["github.com/".len()..]
+    } else {
+        entry
+    };
+
+    let mut segments = path.split(|c| c == '/' || c == '#' || c == '?');
+    let owner = segments.next()?;
+    let name = segments.next()?;
+    let name = if name.ends_with(".git") {
+        &name[..name.len() - 4]
+    } else {
+        name
+    };
+
+    let valid = |segment: &str| {
+        !segment.is_empty()
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+    };
+    if valid(owner) && valid(name) {
+        Some(format!("{}/{}", owner, name))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_list() {
+        let list = "# top crates\n\
+                    serde-rs/serde\n\
+                    \n\
+                    https://github.com/rust-lang/rustfmt\n\
+                    https://github.com/Serde-rs/serde.git\n\
+                    git@github.com:rust-lang/cargo.git\n\
+                    https://gitlab.com/some/crate\n\
+                    not a repository\n";
+        assert_eq!(
+            parse_seeds(list).unwrap(),
+            vec!["serde-rs/serde", "rust-lang/rustfmt", "rust-lang/cargo"]
+        );
+    }
+
+    #[test]
+    fn crates_io_dump() {
+        let dump = "created_at,description,id,name,repository,updated_at\n\
+                    2015-01-01,\"A serialization, framework\",1,serde,https://github.com/serde-rs/serde,2018-01-01\n\
+                    2015-01-01,\"Multi-line\ndescription\",2,serde_json,https://github.com/serde-rs/json/,2018-01-01\n\
+                    2015-01-01,No repository,3,orphan,,2018-01-01\n\
+                    2015-01-01,Workspace member,4,serde_derive,https://github.com/serde-rs/serde,2018-01-01\n\
+                    2015-01-01,Subdirectory,5,tokio-io,https://github.com/tokio-rs/tokio/tree/master/tokio-io,2018-01-01\n";
+        assert_eq!(
+            parse_seeds(dump).unwrap(),
+            vec!["serde-rs/serde", "serde-rs/json", "tokio-rs/tokio"]
+        );
+
+        let csv = "name,stars\nrust-lang/rust,50000\nrust-lang/cargo,6000\n";
+        assert_eq!(
+            parse_seeds(csv).unwrap(),
+            vec!["rust-lang/rust", "rust-lang/cargo"]
+        );
+    }
+
+    #[test]
+    fn resume_after_the_last_seed() {
+        let seeds: Vec<String> = vec!["rust-lang/rust".into(), "serde-rs/serde".into()];
+        assert_eq!(resume_position(&seeds, None), 0);
+        assert_eq!(resume_position(&seeds, Some("rust-lang/rust")), 1);
+        assert_eq!(resume_position(&seeds, Some("Serde-rs/Serde")), 2);
+
+        // Lines inserted before the last seed don't shift the position
+        let mut seeds = seeds;
+        seeds.insert(0, "rust-lang/cargo".into());
+        assert_eq!(resume_position(&seeds, Some("rust-lang/rust")), 2);
+
+        assert_eq!(resume_position(&seeds, Some("gone/repo")), 0);
+    }
+}
@@ -0,0 +1,233 @@
+use super::Strategy;
+
+use failure::Error;
+
+use rustyrobot::github::v4::Github as GithubV4;
+use rustyrobot::search::query::{Bounds, IncompleteQuery};
+use rustyrobot::search::window::{count_results, SEARCH_CAP};
+
+use fetcher::FetcherState;
+use std::sync::Arc;
+
+/// Upper bound of the next band, removed once the walk is over
+const CURSOR_KEY: &str = "star_window_upper";
+
+/// Walks the `stars` bands from the most popular repositories down to `min_stars`.
+///
+/// Every band is narrowed until it matches no more than `cap` repositories, the next band
+/// is widened when the previous one came out half empty.
+#[derive(Clone)]
+pub struct StarWindow {
+    gh: Arc<GithubV4>,
+
+    /// Lower bound of the first, open-ended band
+    pub top_stars: u64,
+
+    /// The star count to finish the search at
+    pub min_stars: u64,
+
+    pub cap: u64,
+}
+
+impl StarWindow {
+    pub fn new(gh: Arc<GithubV4>) -> Self {
+        StarWindow {
+            gh,
+            top_stars: 10_000,
+            min_stars: 0,
+            cap: SEARCH_CAP,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Band {
+    lower: u64,
+    /// None for the open-ended top band
+    upper: Option<u64>,
+}
+
+impl Band {
+    /// Band of `width` star counts ending at `upper`
+    fn below(upper: u64, width: u64, min_stars: u64) -> Self {
+        Band {
+            lower: (upper + 1).saturating_sub(width.max(1)).max(min_stars),
+            upper: Some(upper),
+        }
+    }
+
+    fn bounds(&self) -> Bounds<u64> {
+        match self.upper {
+            None => Bounds::AtLeast(self.lower),
+            Some(upper) if upper == self.lower => Bounds::Exactly(upper),
+            Some(upper) => Bounds::Between(self.lower, upper),
+        }
+    }
+
+    fn width(&self) -> u64 {
+        match self.upper {
+            Some(upper) => upper - self.lower + 1,
+            // Star counts are heavy-tailed, so the bands past the top one start out geometric
+            None => self.lower - self.lower / 2,
+        }
+    }
+
+    /// Raise the lower bound until the band matches no more than `cap` results
+    fn fit<F>(mut self, cap: u64, mut count: F) -> Result<(Self, u64), Error>
+    where
+        F: FnMut(Bounds<u64>) -> Result<u64, Error>,
+    {
+        loop {
+            let matches = count(self.bounds())?;
+            if matches <= cap {
+                return Ok((self, matches));
+            }
+
+            match self.upper {
+                Some(upper) if upper > self.lower => self.lower += (upper - self.lower + 1) / 2,
+                Some(_) => {
+                    warn!(
+                        "{} repositories have {} stars, leaving the window to the github service",
+                        matches, self.lower
+                    );
+                    return Ok((self, matches));
+                }
+                None => self.lower = self.lower.max(1).saturating_mul(2),
+            }
+        }
+    }
+
+    /// Band right below this one, None if this one reaches `min_stars`
+    fn next(&self, matches: u64, cap: u64, min_stars: u64) -> Option<Self> {
+        if self.lower <= min_stars {
+            return None;
+        }
+
+        let width = if matches < cap / 2 {
+            self.width().saturating_mul(2)
+        } else {
+            self.width()
+        };
+        Some(Band::below(self.lower - 1, width, min_stars))
+    }
+}
+
+impl Strategy for StarWindow {
+    /// Run query using the strategy logic
+    fn execute(&mut self, shared: &mut FetcherState, query: IncompleteQuery) -> Result<(), Error> {
+        let cursor: Option<i64> = shared.state.get_or_default(shared.key(CURSOR_KEY));
+        let mut band = match cursor {
+            Some(upper) => {
+                let upper = upper as u64;
+                info!("resuming from {} stars", upper);
+                Band::below(upper, upper - upper / 2, self.min_stars)
+            }
+            None => Band {
+                lower: self.top_stars.max(self.min_stars),
+                upper: None,
+            },
+        };
+
+        while !shared.shutdown.should_shutdown() {
+            let gh = &self.gh;
+            let (fitted, matches) = band.fit(self.cap, |bounds| {
+                count_results(gh, &query.clone().stars(bounds))
+            })?;
+
+            info!("requesting stars:{} ({} results)", fitted.bounds(), matches);
+            let window = query.clone().stars(fitted.bounds());
+
+            // Reuse simple strategy for making single request
+            super::Simple.execute(shared, window)?;
+
+            match fitted.next(matches, self.cap, self.min_stars) {
+                Some(next) => {
+                    shared
+                        .state
+                        .set(shared.key(CURSOR_KEY), next.upper.unwrap_or(0) as i64);
+                    shared.state.sync()?;
+                    band = next;
+                }
+                None => {
+                    info!(
+                        "reached {} stars, starting from the top next time",
+                        self.min_stars
+                    );
+                    shared.state.remove(shared.key(CURSOR_KEY));
+                    shared.state.sync()?;
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contains(bounds: &Bounds<u64>, stars: u64) -> bool {
+        match *bounds {
+            Bounds::Exactly(value) => stars == value,
+            Bounds::AtLeast(lower) => stars >= lower,
+            Bounds::AtMost(upper) => stars <= upper,
+            Bounds::Between(lower, upper) => stars >= lower && stars <= upper,
+        }
+    }
+
+    /// Walk the bands over repositories with the given star counts
+    fn walk(stars: &[u64], top_stars: u64, cap: u64) -> Vec<Bounds<u64>> {
+        let count = |bounds: Bounds<u64>| {
+            Ok(stars.iter().filter(|&&s| contains(&bounds, s)).count() as u64)
+        };
+
+        let mut band = Band {
+            lower: top_stars,
+            upper: None,
+        };
+        let mut windows = Vec::new();
+        loop {
+            let (fitted, matches) = band.fit(cap, count).unwrap();
+            windows.push(fitted.bounds());
+            match fitted.next(matches, cap, 0) {
+                Some(next) => band = next,
+                None => return windows,
+            }
+        }
+    }
+
+    #[test]
+    fn bands_cover_everything_under_the_cap() {
+        let stars: Vec<u64> = (0..500).map(|i| i * i / 100).collect();
+        let windows = walk(&stars, 100, 50);
+
+        assert_eq!(windows[0], Bounds::AtLeast(3200));
+
+        // Descending and disjoint, with no gaps in between
+        let mut next_upper = None;
+        for window in &windows {
+            let (lower, upper) = match *window {
+                Bounds::AtLeast(lower) => (lower, None),
+                Bounds::Exactly(value) => (value, Some(value)),
+                Bounds::Between(lower, upper) => (lower, Some(upper)),
+                Bounds::AtMost(_) => unreachable!(),
+            };
+            assert_eq!(upper, next_upper);
+            next_upper = lower.checked_sub(1);
+        }
+
+        for window in &windows {
+            let matches = stars.iter().filter(|&&s| contains(window, s)).count();
+            assert!(matches <= 50, "{:?} matches {}", window, matches);
+        }
+        assert_eq!(
+            stars
+                .iter()
+                .filter(|&&s| windows.iter().any(|w| contains(w, s)))
+                .count(),
+            stars.len()
+        );
+    }
+}
@@ -7,10 +7,12 @@ extern crate log;
 extern crate chrono;
 extern crate fern;
 
+use chrono::Utc;
 use failure::Error;
 
 use rustyrobot::{
     kafka::{group, topic, util::handler::HandlingConsumer, Event, GithubRequest},
+    policy::Policy,
     shutdown::GracefulShutdown,
 };
 
@@ -50,13 +52,18 @@ fn main() {
     })
     .expect("couldn't register SIGINT handler");
 
+    let policy = Policy::from_env().expect("failed to load the policy");
+
     HandlingConsumer::builder()
         .subscribe(topic::EVENT)
         .respond_to(topic::GITHUB_REQUEST)
         .group(group::FORKER)
-        .handler(|event, callback| {
+        .handler(move |event, callback| {
             match event {
-                Event::RepositoryFetched(repo) => callback(GithubRequest::Fork(repo)),
+                Event::RepositoryFetched(repo) => match policy.check(&repo, Utc::now()) {
+                    Ok(()) => callback(GithubRequest::Fork(repo)),
+                    Err(skip) => info!("skipping {}: {}", repo.name_with_owner, skip),
+                },
                 _ => (),
             }
             Ok(())
@@ -326,6 +326,7 @@ enum GitError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use fake_github::git::GitRemote;
     use std::fs::remove_dir_all;
 
     #[test]
@@ -359,12 +360,13 @@ mod tests {
 
     #[test]
     fn git_clone() {
+        let remote = GitRemote::new("github-rustfmt-bot").unwrap();
         let path = PathBuf::from("/tmp/test_git_clone");
         if path.exists() {
             remove_dir_all(&path).unwrap();
         }
 
-        Git::clone(&path, "git@github.com:mersinvald/github-rustfmt-bot.git").unwrap();
+        Git::clone(&path, &remote.url()).unwrap();
         assert!(path.exists());
 
         remove_dir_all(&path).unwrap();
@@ -397,13 +399,13 @@ mod tests {
 
     #[test]
     fn git_add_remote() {
+        let remote = GitRemote::new("github-rustfmt-bot").unwrap();
         let path = PathBuf::from("/tmp/test_git_add_remote");
         if path.exists() {
             remove_dir_all(&path).unwrap();
         }
 
-        let mut git =
-            Git::clone(&path, "git@github.com:mersinvald/github-rustfmt-bot.git").unwrap();
+        let mut git = Git::clone(&path, &remote.url()).unwrap();
         assert!(path.exists());
         git.add_remote("new_remote", "https://localhost/").unwrap();
         assert_eq!(git.has_remote("new_remote").unwrap(), true);
@@ -413,13 +415,13 @@ mod tests {
 
     #[test]
     fn git_checkout() {
+        let remote = GitRemote::new("github-rustfmt-bot").unwrap();
         let path = PathBuf::from("/tmp/test_git_checkout");
         if path.exists() {
             remove_dir_all(&path).unwrap();
         }
 
-        let mut git =
-            Git::clone(&path, "git@github.com:mersinvald/github-rustfmt-bot.git").unwrap();
+        let mut git = Git::clone(&path, &remote.url()).unwrap();
         assert!(path.exists());
         git.checkout(CheckoutMode::Branch {
             name: "new",
@@ -8,7 +8,6 @@ extern crate log;
 #[macro_use]
 extern crate fern;
 extern crate chrono;
-extern crate github_rs as github_v3;
 #[macro_use]
 extern crate serde_derive;
 extern crate dotenv;
@@ -17,6 +16,9 @@ extern crate serde;
 extern crate serde_json as json;
 extern crate tempdir;
 
+#[cfg(test)]
+extern crate fake_github;
+
 mod git;
 
 use failure::Error;
@@ -86,7 +88,38 @@ fn main() {
         .handler(|event, callback: &mut dyn FnMut(Event)| {
             match event {
                 Event::RepositoryForked(repo) => {
-                    callback(Event::RepositoryFormatted(rustfmt_repo(repo)?));
+                    let started_at = Utc::now();
+                    match rustfmt_repo(repo.clone()) {
+                        Ok(mut formatted) => {
+                            formatted.record_stage(
+                                StageRecord::finished(
+                                    Stage::Format,
+                                    VERSION,
+                                    started_at,
+                                    StageOutcome::Success,
+                                )
+                                .branch(RUSTFMT_BRANCH),
+                            );
+                            callback(Event::RepositoryFormatted(formatted));
+                        }
+                        // Internal errors are retried, the others won't go away on a re-run
+                        Err(HandlerError::Other { error }) => {
+                            let mut repo = repo;
+                            repo.record_stage(
+                                StageRecord::finished(
+                                    Stage::Format,
+                                    VERSION,
+                                    started_at,
+                                    StageOutcome::Failure,
+                                )
+                                .branch(RUSTFMT_BRANCH)
+                                .error(&error),
+                            );
+                            callback(Event::StageFailed(repo));
+                            return Err(HandlerError::Other { error });
+                        }
+                        Err(error) => return Err(error),
+                    }
                 }
                 _ => (),
             }
@@ -98,13 +131,16 @@ fn main() {
         .expect("formatter service failed");
 }
 
+use chrono::Utc;
 use failure::err_msg;
 use git::{CheckoutMode, DirHistory, Git};
+use rustyrobot::types::history::{Stage, StageOutcome, StageRecord};
 use rustyrobot::types::{FormatStats, Stats};
 use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus};
 
 const RUSTFMT_BRANCH: &str = "rustyrobot_suggested_formatting";
+const VERSION: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
 fn rustfmt_repo(mut repo: Repository) -> Result<Repository, HandlerError> {
     let tempdir = tempdir::TempDir::new(&repo.name_with_owner.replace('/', "_"))
@@ -246,8 +282,10 @@ fn format_code(path: &Path) -> Result<(), HandlerError> {
         .status()
         .map_err(HandlerError::internal)?;
 
+    // cargo fmt ran but refused the code, e.g. it doesn't parse. Retrying won't help, so it's
+    // recorded as a failed run instead of stopping the service, a missing cargo fails above
     if !status.success() {
-        Err(HandlerError::internal(err_msg("failed to format repo")))
+        Err(HandlerError::other(err_msg("failed to format repo")))
     } else {
         Ok(())
     }
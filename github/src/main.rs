@@ -6,31 +6,53 @@ extern crate rustyrobot;
 extern crate log;
 extern crate chrono;
 extern crate fern;
-extern crate github_rs as github_v3;
 extern crate serde;
 extern crate serde_derive;
 extern crate serde_json as json;
 
+#[cfg(test)]
+extern crate fake_github;
+#[cfg(test)]
+extern crate tempfile;
+
+use chrono::{DateTime, Utc};
 use failure::Error;
 use std::sync::{Arc, Mutex};
 
 use rustyrobot::{
-    github::utils::{load_token, load_username},
+    github::concurrency::{parallel_map, parallel_stream, ThreadPool},
+    github::tokens::Affinity,
+    github::utils::{
+        load_max_concurrency, load_response_cache, load_revisit_interval, load_token_pool,
+        load_transport,
+    },
     github::v3::Github as GithubV3,
     github::v4::Github as GithubV4,
     kafka::{
         group, topic,
         util::{
             handler::{HandlerError, HandlingConsumer},
+            seen::SeenIndex,
             state::StateHandler,
         },
         Event, GithubRequest,
     },
-    search::{query::IncompleteQuery, query::SearchFor, search},
+    search::{
+        query::IncompleteQuery,
+        query::SearchFor,
+        window::{split_windows, SEARCH_CAP},
+        SearchIter,
+    },
     shutdown::{GracefulShutdown, GracefulShutdownHandle},
-    types::{Notification, PRStatus, Repository, PR},
+    types::{
+        history::{Stage, StageOutcome, StageRecord},
+        pr::{self, ReviewState},
+        Notification, Repository, PR,
+    },
 };
 
+const VERSION: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
 fn init_fern() -> Result<(), Error> {
     fern::Dispatch::new()
         .format(|out, message, record| {
@@ -69,11 +91,28 @@ fn main() {
     state.restore().expect("failed to restore state");
     let state = Arc::new(Mutex::new(state));
 
-    let token = load_token().expect("failed to load token (set GITHUB_TOKEN env)");
-    let username = load_username().expect("failed to load username (set GITHUB_USERNAME env)");
+    // Repositories fetched within the revisit interval aren't emitted again
+    let revisit = load_revisit_interval().expect("invalid GITHUB_REVISIT_HOURS");
+    let seen = SeenIndex::new(topic::GITHUB_SEEN, revisit).expect("failed to open seen index");
+    let seen = Arc::new(Mutex::new(seen));
+
+    let cache = load_response_cache().expect("failed to load response cache");
+    let max_concurrency = load_max_concurrency().expect("invalid GITHUB_MAX_CONCURRENCY");
+    // Both APIs share the bound, GitHub doesn't distinguish them for abuse detection
+    let transport = load_transport().expect("failed to create GitHub transport");
+
+    let tokens = load_token_pool(&transport).expect("failed to load tokens (set GITHUB_TOKEN env)");
+    let tokens = Arc::new(tokens);
+
+    let github_v3 =
+        GithubV3::with_transport(tokens.clone(), transport.clone()).with_cache(Arc::new(cache));
+    let github_v3 = Arc::new(github_v3);
+    let github_v4 = GithubV4::with_transport(tokens, transport)
+        .expect("failed to create GitHub V4 API instance");
+    let github_v4 = Arc::new(github_v4);
 
-    let github_v3 = GithubV3::new(&token).expect("failed to create GitHub V3 API instance");
-    let github_v4 = GithubV4::new(&token).expect("failed to create GitHub V4 API instance");
+    // Lives as long as the service, the parallel parts of every request run on it
+    let workers = ThreadPool::new(max_concurrency);
 
     let handler = {
         let shutdown_handle = shutdown_handle.clone();
@@ -95,20 +134,62 @@ fn main() {
                 GithubRequest::Fetch(query) => match query.search_for {
                     SearchFor::Repository => {
                         increment_stat_counter("repository fetch requests received");
-                        let repos = fetch_all_repos(&github_v4, query, shutdown_handle.clone())?;
-                        for repo in repos {
-                            increment_stat_counter("repositories fetched");
-                            callback(Event::RepositoryFetched(repo))
-                        }
+                        fetch_all_repos(
+                            &github_v4,
+                            &workers,
+                            query,
+                            &state,
+                            &shutdown_handle,
+                            &mut |repo| {
+                                increment_stat_counter("repositories fetched");
+                                let now = Utc::now();
+                                if !seen.lock().unwrap().is_due(&repo.id, now) {
+                                    debug!(
+                                        "{} was fetched recently, skipping",
+                                        repo.name_with_owner
+                                    );
+                                    increment_stat_counter("repositories skipped as seen");
+                                    return;
+                                }
+
+                                let id = repo.id.clone();
+                                let name = repo.name_with_owner.clone();
+                                callback(Event::RepositoryFetched(repo));
+                                if let Err(e) = seen.lock().unwrap().visit(&id, now) {
+                                    error!("failed to record the visit of {}: {}", name, e);
+                                }
+                            },
+                        )?;
                         increment_stat_counter("repository fetch requests handled");
                     }
-                    SearchFor::Undefined => {
-                        panic!("search_for is Undefined: can't fetch an undefined entity")
+                    // Out of scope for the service: there are no events for users, issues or
+                    // pull requests, reconciling the own PRs with `Stats::prs` searches them
+                    // through `search::search` directly. The request is logged and skipped.
+                    other => {
+                        return Err(HandlerError::Other {
+                            error: err_msg(format!("can't fetch {:?} search results", other)),
+                        })
                     }
                 },
                 GithubRequest::Fork(repo) => {
-                    let fork = fork_repo(&github_v3, &repo)?;
-                    callback(Event::RepositoryForked(fork))
+                    let started_at = Utc::now();
+                    let run =
+                        |outcome| StageRecord::finished(Stage::Fork, VERSION, started_at, outcome);
+                    match fork_repo(&github_v3, &repo) {
+                        Ok(mut fork) => {
+                            fork.history = repo.history;
+                            fork.record_stage(run(StageOutcome::Success));
+                            callback(Event::RepositoryForked(fork))
+                        }
+                        Err(error) => {
+                            return Err(stage_failed(
+                                repo,
+                                run(StageOutcome::Failure),
+                                error,
+                                callback,
+                            ))
+                        }
+                    }
                 }
                 GithubRequest::DeleteFork(repo) => {
                     delete_repo(&github_v3, &repo.name_with_owner)?;
@@ -120,18 +201,29 @@ fn main() {
                     title,
                     message,
                 } => {
-                    let repo = create_pr(&github_v3, repo, &branch, &title, &message)?;
-                    callback(Event::PRCreated(repo))
+                    let started_at = Utc::now();
+                    match create_pr(&github_v3, repo.clone(), &branch, &title, &message) {
+                        Ok(repo) => callback(Event::PRCreated(repo)),
+                        Err(error) => {
+                            let run = StageRecord::finished(
+                                Stage::PullRequest,
+                                VERSION,
+                                started_at,
+                                StageOutcome::Failure,
+                            )
+                            .branch(branch);
+                            return Err(stage_failed(repo, run, error, callback));
+                        }
+                    }
                 }
                 GithubRequest::FetchNotifications => {
-                    // TODO
-                    let events = fetch_notifications(&github_v3, &username)?;
-                    for event in events {
-                        callback(Event::Notification(event));
-                    }
+                    fetch_notifications(&github_v3, &state, &mut |notification| {
+                        increment_stat_counter("notifications fetched");
+                        callback(Event::Notification(notification))
+                    })?;
                 }
                 GithubRequest::CheckPRStatus(repo) => {
-                    let repo_none_if_unchanged = fetch_pr_status(&github_v3, repo)?;
+                    let repo_none_if_unchanged = fetch_pr_status(&github_v3, repo, &workers)?;
                     if let Some(repo) = repo_none_if_unchanged {
                         callback(Event::PRStatusChange(repo))
                     }
@@ -156,60 +248,172 @@ fn main() {
 
 use rustyrobot::types::repo;
 
+/// Fetch every repository matched by the query, splitting it into windows under the search cap.
+///
+/// Windows are paged through in parallel, repositories are emitted as soon as they are fetched.
 fn fetch_all_repos(
-    gh: &GithubV4,
+    gh: &Arc<GithubV4>,
+    workers: &ThreadPool,
     query: IncompleteQuery,
-    shutdown: GracefulShutdownHandle,
-) -> Result<Vec<Repository>, HandlerError> {
-    let mut repos = Vec::new();
-
-    let mut page = None;
-    let mut out_of_pages = false;
-
-    while !out_of_pages && !shutdown.should_shutdown() {
-        let query = query.clone();
+    state: &Arc<Mutex<StateHandler>>,
+    shutdown: &GracefulShutdownHandle,
+    emit: &mut dyn FnMut(Repository),
+) -> Result<(), HandlerError> {
+    let windows = split_windows(gh, query, SEARCH_CAP).map_err(handler_error)?;
+    if windows.len() > 1 {
+        info!("query is split into {} windows", windows.len());
+    }
 
-        let query = if let Some(page) = page.take() {
-            query.after(page).build()
-        } else {
-            query.build()
+    let mut checkpoint_error = None;
+    let results = {
+        // Runs on this thread after the repositories fetched before it are emitted, so the
+        // stored cursor never gets ahead of what was produced
+        let mut consume = |fetched: Fetched| match fetched {
+            Fetched::Repository(repo) => emit(repo),
+            Fetched::Checkpoint { key, cursor } => {
+                let mut state = state.lock().unwrap();
+                let synced = match cursor {
+                    Some(cursor) => state.set_and_sync(&key, cursor),
+                    None => {
+                        state.remove(&key);
+                        state.sync()
+                    }
+                };
+                if let Err(e) = synced {
+                    error!("failed to checkpoint the search cursor: {}", e);
+                    checkpoint_error.get_or_insert(e);
+                }
+            }
         };
 
-        let query = query.map_err(|error| HandlerError::Internal { error })?;
-
-        let data = search::<repo::v4::Repository>(&gh, query)
-            .map_err(|error| HandlerError::Internal { error })?;
+        let gh = gh.clone();
+        let state = state.clone();
+        let shutdown = shutdown.clone();
+        parallel_stream(
+            workers,
+            windows,
+            move |window, fetched| {
+                if shutdown.should_shutdown() {
+                    return Ok(());
+                }
+                fetch_window(&gh, window, &state, &shutdown, fetched)
+            },
+            &mut consume,
+        )
+    };
 
-        let page_info = data.page_info;
-        let nodes = data.nodes;
+    if let Some(e) = checkpoint_error {
+        return Err(HandlerError::internal(e));
+    }
+    results.into_iter().collect()
+}
 
-        repos.extend(nodes.into_iter().map(Repository::from));
+/// Yielded by `fetch_window`, in the order they are to be applied
+enum Fetched {
+    Repository(Repository),
+    /// Cursor to resume the window from, `None` once it's complete
+    Checkpoint {
+        key: String,
+        cursor: Option<String>,
+    },
+}
 
-        page = page_info.end_cursor;
-        if !page_info.has_next_page {
-            debug!("reached EOF");
-            out_of_pages = true;
+/// Page through a single window, checkpointing the search cursor to resume from after restart
+fn fetch_window(
+    gh: &GithubV4,
+    query: IncompleteQuery,
+    state: &Mutex<StateHandler>,
+    shutdown: &GracefulShutdownHandle,
+    fetched: &mut dyn FnMut(Fetched),
+) -> Result<(), HandlerError> {
+    let key = format!(
+        "search cursor {}",
+        json::to_string(&query).map_err(HandlerError::internal)?
+    );
+
+    let cursor: Option<String> = state.lock().unwrap().get_or_default(&key);
+    let mut repos = match cursor {
+        Some(cursor) => {
+            info!("resuming search after {}", cursor);
+            SearchIter::<repo::v4::Repository>::resume(gh, query, cursor)
+        }
+        None => SearchIter::new(gh, query),
+    };
+    let mut checkpoint = repos.cursor().map(ToOwned::to_owned);
+
+    while !shutdown.should_shutdown() {
+        let repo = match repos.next() {
+            Some(repo) => repo.map_err(handler_error)?,
+            None => {
+                debug!("reached EOF");
+                // Search is complete, the next request for the same window starts over
+                fetched(Fetched::Checkpoint { key, cursor: None });
+                break;
+            }
         };
+
+        fetched(Fetched::Repository(Repository::from(repo)));
+
+        if let Some(cursor) = repos.cursor() {
+            if checkpoint.as_ref().map(String::as_str) != Some(cursor) {
+                checkpoint = Some(cursor.to_string());
+                fetched(Fetched::Checkpoint {
+                    key: key.clone(),
+                    cursor: checkpoint.clone(),
+                });
+            }
+        }
     }
 
-    Ok(repos)
+    Ok(())
 }
 
 use failure::err_msg;
-use github_v3::StatusCode;
 use json::Value;
-use rustyrobot::github::v3::{EmptyResponse, ExecutorExt};
+use rustyrobot::github::v3::{EmptyResponse, ExecutorExt, StatusCode};
+use rustyrobot::github::ErrorKind;
 use rustyrobot::search::NodeType;
 use std::collections::HashMap;
 
+/// Requests refused by GitHub are skipped, transient and unknown failures stop the service
+/// without commit, so the request is retried after restart. So do the rejected credentials,
+/// every following request would fail the same way.
+fn handler_error(error: Error) -> HandlerError {
+    match ErrorKind::of(&error) {
+        ErrorKind::NotFound | ErrorKind::Permission | ErrorKind::Validation => {
+            HandlerError::Other { error }
+        }
+        ErrorKind::Unauthorized | ErrorKind::Retryable | ErrorKind::Other => {
+            HandlerError::Internal { error }
+        }
+    }
+}
+
+/// Record the failed run, emitting the repository with `Event::StageFailed`.
+///
+/// Internal errors are retried, only the failures that won't go away on a re-run are recorded.
+fn stage_failed(
+    mut repo: Repository,
+    run: StageRecord,
+    error: HandlerError,
+    emit: &mut dyn FnMut(Event),
+) -> HandlerError {
+    if let HandlerError::Other { ref error } = error {
+        repo.record_stage(run.error(error));
+        emit(Event::StageFailed(repo));
+    }
+    error
+}
+
 fn fork_repo(gh: &GithubV3, parent: &Repository) -> Result<Repository, HandlerError> {
     let endpoint = format!("repos/{}/forks", &parent.name_with_owner);
     debug!("fork endpoint: {}", endpoint);
     let value: Value = gh
         .post(())
         .custom_endpoint(&endpoint)
-        .send(&[StatusCode::Accepted])
-        .map_err(|error| HandlerError::Other { error })?;
+        .affinity(Affinity::Bot)
+        .send(&[StatusCode::ACCEPTED])
+        .map_err(handler_error)?;
 
     let fork = Repository::from_value(value).map_err(|error| HandlerError::Internal { error })?;
 
@@ -222,8 +426,9 @@ fn delete_repo(gh: &GithubV3, repo_name: &str) -> Result<(), HandlerError> {
     let _value: EmptyResponse = gh
         .delete(())
         .custom_endpoint(&endpoint)
-        .send(&[StatusCode::NoContent])
-        .map_err(|error| HandlerError::Internal { error })?;
+        .affinity(Affinity::Bot)
+        .send(&[StatusCode::NO_CONTENT])
+        .map_err(handler_error)?;
 
     Ok(())
 }
@@ -241,16 +446,26 @@ fn create_pr(
 
     let owner = repo.name_with_owner.split("/").next().unwrap().to_string();
     let head = format!("{}:{}", owner, branch);
+    let started_at = Utc::now();
 
     if pr_exists(gh, &parent.name_with_owner, &head)? {
         warn!(
             "pull request {} -> {} exists, refusing to create",
             head, parent.name_with_owner
         );
+        repo.record_stage(
+            StageRecord::finished(
+                Stage::PullRequest,
+                VERSION,
+                started_at,
+                StageOutcome::Skipped,
+            )
+            .branch(branch),
+        );
         return Ok(repo);
     }
 
-    let response: Value = {
+    let pr: pr::v3::PullRequest = {
         let endpoint = format!("repos/{}/pulls", parent.name_with_owner);
         let mut body: HashMap<&str, &str> = HashMap::new();
         body.insert("title", title);
@@ -260,24 +475,14 @@ fn create_pr(
 
         gh.post(body)
             .custom_endpoint(&endpoint)
-            .send(&[StatusCode::Created])
-            .map_err(|error| HandlerError::Internal { error })?
+            .affinity(Affinity::Bot)
+            .send(&[StatusCode::CREATED])
+            .map_err(handler_error)?
     };
 
-    let pr_number = response
-        .as_object()
-        .and_then(|obj| obj.get("number"))
-        .and_then(|number| number.as_i64())
-        .ok_or_else(|| HandlerError::Internal {
-            error: err_msg("number is missing for Pull Request"),
-        })?;
-
     let mut stats = repo.stats.take().unwrap_or_default();
-    let pr = PR {
-        title: title.to_owned(),
-        number: pr_number,
-        status: PRStatus::Open,
-    };
+    let pr = PR::from(pr);
+    let pr_number = pr.number;
 
     // Remove previous entry if exists
     if let Some(pos) = stats.prs.iter().position(|pr| pr.number == pr_number) {
@@ -286,73 +491,164 @@ fn create_pr(
 
     stats.prs.push(pr);
     repo.stats = Some(stats);
+    repo.record_stage(
+        StageRecord::finished(
+            Stage::PullRequest,
+            VERSION,
+            started_at,
+            StageOutcome::Success,
+        )
+        .branch(branch),
+    );
 
     Ok(repo)
 }
 
 fn pr_exists(gh: &GithubV3, name_with_owner: &str, head: &str) -> Result<bool, HandlerError> {
     let endpoint = format!("repos/{}/pulls?head={}", name_with_owner, head);
-    let response: Value = gh
+    let first = gh
         .get()
         .custom_endpoint(&endpoint)
-        .send(&[StatusCode::Ok])
-        .map_err(|error| HandlerError::Internal { error })?;
-    Ok(!response.as_array().map(Vec::is_empty).unwrap_or(true))
+        .paginate::<Value>()
+        .next();
+
+    match first {
+        Some(Ok(_)) => Ok(true),
+        Some(Err(error)) => Err(handler_error(error)),
+        None => Ok(false),
+    }
+}
+
+/// Position in the notification inbox.
+///
+/// `since` is inclusive, as several threads can be updated within the same second, so the
+/// threads updated exactly at `since` that were emitted already are remembered.
+#[derive(Debug, Default, PartialEq)]
+struct NotificationCheckpoint {
+    since: Option<DateTime<Utc>>,
+    emitted: Vec<String>,
 }
 
-fn fetch_notifications(gh: &GithubV3, username: &str) -> Result<Vec<Notification>, HandlerError> {
-    let endpoint = "notifications";
-    let response: Value = gh
+impl NotificationCheckpoint {
+    const SINCE_KEY: &'static str = "notifications since";
+    const EMITTED_KEY: &'static str = "notifications emitted";
+
+    fn load(state: &StateHandler) -> Self {
+        let since: Option<String> = state.get_or_default(Self::SINCE_KEY);
+        NotificationCheckpoint {
+            since: since
+                .and_then(|since| DateTime::parse_from_rfc3339(&since).ok())
+                .map(|since| since.with_timezone(&Utc)),
+            emitted: state.get_or_default(Self::EMITTED_KEY),
+        }
+    }
+
+    fn save(&self, state: &mut StateHandler) -> Result<(), Error> {
+        if let Some(since) = self.since {
+            state.set(Self::SINCE_KEY, since.to_rfc3339());
+            state.set(Self::EMITTED_KEY, self.emitted.clone());
+            state.sync()?;
+        }
+        Ok(())
+    }
+
+    fn is_new(&self, notification: &Notification) -> bool {
+        match self.since {
+            Some(since) if notification.updated_at == since => {
+                !self.emitted.contains(&notification.id)
+            }
+            Some(since) => notification.updated_at > since,
+            None => true,
+        }
+    }
+
+    /// Move past the emitted thread, threads are expected oldest first
+    fn advance(&mut self, notification: &Notification) {
+        if self.since != Some(notification.updated_at) {
+            self.since = Some(notification.updated_at);
+            self.emitted.clear();
+        }
+        self.emitted.push(notification.id.clone());
+    }
+}
+
+/// Emit the unread notifications updated since the previous poll, marking their threads read.
+///
+/// Requests go through the response cache, which makes them conditional on `Last-Modified`,
+/// so polling an unchanged inbox costs no rate limit.
+fn fetch_notifications(
+    gh: &GithubV3,
+    state: &Mutex<StateHandler>,
+    emit: &mut dyn FnMut(Notification),
+) -> Result<(), HandlerError> {
+    let mut checkpoint = NotificationCheckpoint::load(&state.lock().unwrap());
+
+    let endpoint = match checkpoint.since {
+        Some(since) => format!("notifications?since={}", since.format("%Y-%m-%dT%H:%M:%SZ")),
+        None => "notifications".to_string(),
+    };
+    let notifications: Vec<Notification> = gh
         .get()
         .custom_endpoint(&endpoint)
-        .send(&[StatusCode::Ok])
-        .map_err(|error| HandlerError::Internal { error })?;
-    println!("{:#?}", response);
-    Ok(vec![])
+        .affinity(Affinity::Bot)
+        .paginate()
+        .collect::<Result<_, _>>()
+        .map_err(handler_error)?;
+
+    // GitHub returns the threads updated at `since` again, and a not modified inbox is served
+    // from the cache, skip what was emitted already
+    let mut notifications: Vec<Notification> = notifications
+        .into_iter()
+        .filter(|n| checkpoint.is_new(n))
+        .collect();
+    notifications.sort_by_key(|n| n.updated_at);
+    debug!("{} new notifications", notifications.len());
+
+    for notification in notifications {
+        let endpoint = format!("notifications/threads/{}", notification.id);
+        checkpoint.advance(&notification);
+
+        // Emitted first, so a thread is never marked read without being handled
+        emit(notification);
+        let _value: EmptyResponse = gh
+            .patch(())
+            .custom_endpoint(&endpoint)
+            .affinity(Affinity::Bot)
+            .send(&[StatusCode::RESET_CONTENT])
+            .map_err(handler_error)?;
+
+        // A failure later on doesn't emit the thread again
+        checkpoint
+            .save(&mut state.lock().unwrap())
+            .map_err(HandlerError::internal)?;
+    }
+
+    Ok(())
 }
 
 fn fetch_pr_status(
-    gh: &GithubV3,
+    gh: &Arc<GithubV3>,
     mut repo: Repository,
+    workers: &ThreadPool,
 ) -> Result<Option<Repository>, HandlerError> {
     let mut stats = repo.stats.take().unwrap_or_default();
     let old_prs = stats.prs.clone();
 
-    let mut new_prs = Vec::with_capacity(old_prs.len());
-    for pr in stats.prs {
-        let endpoint = format!("repos/{}/pulls/{}", repo.name_with_owner, pr.number);
-        let response: Value = gh
-            .get()
-            .custom_endpoint(&endpoint)
-            .send(&[StatusCode::Ok])
-            .map_err(|error| HandlerError::Internal { error })?;
-
-        let pr_obj = response
-            .as_object()
-            .ok_or_else(|| HandlerError::internal(err_msg("GET PR returned <non object>")))?;
-
-        let pr_title = pr_obj
-            .get("title")
-            .and_then(|t| t.as_str())
-            .ok_or_else(|| HandlerError::internal(err_msg("no title associated with PR")))?;
-
-        let pr_number = pr_obj
-            .get("number")
-            .and_then(|t| t.as_i64())
-            .ok_or_else(|| HandlerError::internal(err_msg("no number associated with PR")))?;
-
-        let pr_status = pr_obj
-            .get("state")
-            .and_then(|t| t.as_str())
-            .and_then(|t| PRStatus::from_str(t))
-            .ok_or_else(|| HandlerError::internal(err_msg("no state associated with PR")))?;
-
-        new_prs.push(PR {
-            title: pr_title.to_string(),
-            number: pr_number,
-            status: pr_status,
-        });
-    }
+    // PRs are issued against the parent
+    let base_repo = repo
+        .parent
+        .as_ref()
+        .map(|parent| parent.name_with_owner.clone())
+        .unwrap_or_else(|| repo.name_with_owner.clone());
+
+    // PRs are independent, fetch them in parallel
+    let new_prs = {
+        let gh = gh.clone();
+        parallel_map(workers, stats.prs, move |pr| {
+            fetch_pr(&gh, &base_repo, pr.number)
+        })
+    };
+    let new_prs = new_prs.into_iter().collect::<Result<Vec<_>, _>>()?;
 
     if new_prs != old_prs {
         stats.prs = new_prs;
@@ -362,3 +658,268 @@ fn fetch_pr_status(
         Ok(None)
     }
 }
+
+fn fetch_pr(gh: &GithubV3, name_with_owner: &str, number: i64) -> Result<PR, HandlerError> {
+    let endpoint = format!("repos/{}/pulls/{}", name_with_owner, number);
+    let pr: pr::v3::PullRequest = gh
+        .get()
+        .custom_endpoint(&endpoint)
+        .send(&[StatusCode::OK])
+        .map_err(handler_error)?;
+
+    let endpoint = format!("repos/{}/pulls/{}/reviews", name_with_owner, number);
+    let reviews: Vec<pr::v3::Review> = gh
+        .get()
+        .custom_endpoint(&endpoint)
+        .paginate()
+        .collect::<Result<_, _>>()
+        .map_err(handler_error)?;
+
+    let endpoint = format!("repos/{}/commits/{}/status", name_with_owner, pr.head.sha);
+    let status: pr::v3::CombinedStatus = gh
+        .get()
+        .custom_endpoint(&endpoint)
+        .send(&[StatusCode::OK])
+        .map_err(handler_error)?;
+
+    let mut pr = PR::from(pr);
+    pr.review_state = ReviewState::summarize(&reviews);
+    pr.checks = status.checks();
+    Ok(pr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake_github::{FakeGithub, FakePull, FakeRepo};
+    use rustyrobot::github::cassette::Cassette;
+    use rustyrobot::github::tokens::TokenPool;
+    use rustyrobot::github::transport::Transport;
+    use rustyrobot::shutdown::GracefulShutdown;
+    use rustyrobot::types::pr::CheckStatus;
+    use rustyrobot::types::{PRStatus, Stats};
+    use std::fmt::Debug;
+
+    // Nothing listens there, replay must not touch the network
+    const UNREACHABLE: &str = "http://127.0.0.1:9";
+
+    fn transport(api_url: &str, cassette: Cassette) -> Transport {
+        Transport::new(api_url)
+            .unwrap()
+            .with_cassette(Arc::new(cassette))
+    }
+
+    fn tokens() -> Arc<TokenPool> {
+        Arc::new(TokenPool::single("bot"))
+    }
+
+    /// Run the flow against the fake while recording it, then replay the cassette offline
+    fn record_and_replay<T, F>(fake: &FakeGithub, flow: F) -> T
+    where
+        T: PartialEq + Debug,
+        F: Fn(Transport) -> T,
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.json");
+
+        let recorded = flow(transport(&fake.url(), Cassette::record(&path)));
+        let replayed = flow(transport(UNREACHABLE, Cassette::replay(&path).unwrap()));
+        assert_eq!(replayed, recorded);
+        recorded
+    }
+
+    fn get_repo(gh: &GithubV3, name: &str) -> Repository {
+        let value: Value = gh
+            .get()
+            .custom_endpoint(&format!("repos/{}", name))
+            .send(&[StatusCode::OK])
+            .unwrap();
+        Repository::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn fetch_all_repos_replays() {
+        let fake = FakeGithub::start().unwrap();
+        for i in 0..5 {
+            fake.add_repo(FakeRepo::new(format!("owner/repo{}", i)).language(Some("Rust")));
+        }
+
+        let names = record_and_replay(&fake, |transport| {
+            let gh = Arc::new(GithubV4::with_transport(tokens(), transport).unwrap());
+            let state = StateHandler::new("rustyrobot.test.github.state").unwrap();
+            let shutdown = GracefulShutdown::new();
+            let query = "language:Rust"
+                .parse::<IncompleteQuery>()
+                .unwrap()
+                .search_for(SearchFor::Repository)
+                .count(2);
+
+            let mut names = Vec::new();
+            fetch_all_repos(
+                &gh,
+                &ThreadPool::new(2),
+                query,
+                &Arc::new(Mutex::new(state)),
+                &shutdown.thread_handle(),
+                &mut |repo| names.push(repo.name_with_owner),
+            )
+            .unwrap();
+            names.sort();
+            names
+        });
+
+        assert_eq!(names.len(), 5);
+        assert_eq!(names[0], "owner/repo0");
+    }
+
+    #[test]
+    fn window_cursor_follows_emitted_repositories() {
+        let fake = FakeGithub::start().unwrap();
+        for i in 0..5 {
+            fake.add_repo(FakeRepo::new(format!("owner/repo{}", i)));
+        }
+
+        let gh = GithubV4::with_pool(tokens(), &fake.url()).unwrap();
+        let state = StateHandler::new("rustyrobot.test.github.state").unwrap();
+        let shutdown = GracefulShutdown::new();
+        let query = IncompleteQuery::default()
+            .search_for(SearchFor::Repository)
+            .count(2);
+
+        let mut fetched = Vec::new();
+        fetch_window(
+            &gh,
+            query,
+            &Mutex::new(state),
+            &shutdown.thread_handle(),
+            &mut |item| {
+                fetched.push(match item {
+                    Fetched::Repository(repo) => repo.name_with_owner,
+                    Fetched::Checkpoint {
+                        cursor: Some(_), ..
+                    } => "checkpoint".to_string(),
+                    Fetched::Checkpoint { cursor: None, .. } => "done".to_string(),
+                })
+            },
+        )
+        .unwrap();
+
+        // Checkpointed once a page is drained, after its last repository
+        assert_eq!(
+            fetched,
+            vec![
+                "owner/repo0",
+                "owner/repo1",
+                "checkpoint",
+                "owner/repo2",
+                "owner/repo3",
+                "checkpoint",
+                "owner/repo4",
+                "checkpoint",
+                "done",
+            ]
+        );
+    }
+
+    #[test]
+    fn create_pr_replays() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo"));
+
+        let prs = record_and_replay(&fake, |transport| {
+            let gh = GithubV3::with_transport(tokens(), transport);
+            let fork = fork_repo(&gh, &get_repo(&gh, "owner/repo")).unwrap();
+            let repo = create_pr(&gh, fork, "fmt", "Format code", "cargo fmt").unwrap();
+
+            let prs = repo.stats.unwrap().prs;
+            prs.into_iter()
+                .map(|pr| (pr.number, pr.title, pr.status))
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(prs, vec![(1, "Format code".to_string(), PRStatus::Open)]);
+        assert_eq!(fake.state().pulls[0].head, "rustyrobot:fmt");
+    }
+
+    #[test]
+    fn fetch_pr_status_replays() {
+        let fake = FakeGithub::start().unwrap();
+        fake.add_repo(FakeRepo::new("owner/repo"));
+        fake.add_repo(FakeRepo::new("rustyrobot/repo").fork_of("owner/repo"));
+        let mut pull = FakePull::new("owner/repo", "rustyrobot:fmt");
+        pull.review("owner", "APPROVED");
+        pull.report_status("success");
+        fake.add_pull(pull);
+
+        let prs = record_and_replay(&fake, |transport| {
+            let gh = Arc::new(GithubV3::with_transport(tokens(), transport));
+            let mut repo = get_repo(&gh, "rustyrobot/repo");
+            let pr: pr::v3::PullRequest = gh
+                .get()
+                .custom_endpoint("repos/owner/repo/pulls/1")
+                .send(&[StatusCode::OK])
+                .unwrap();
+            repo.stats = Some(Stats {
+                prs: vec![PR::from(pr)],
+                ..Default::default()
+            });
+
+            fetch_pr_status(&gh, repo, &ThreadPool::new(2))
+                .unwrap()
+                .map(|repo| repo.stats.unwrap().prs)
+        });
+
+        let prs = prs.expect("review and checks are new");
+        assert_eq!(prs[0].review_state, Some(ReviewState::Approved));
+        assert_eq!(prs[0].checks, Some(CheckStatus::Success));
+    }
+
+    fn notification(id: &str, updated_at: &str) -> Notification {
+        json::from_str(&format!(
+            r#"{{
+                "id": "{}",
+                "reason": "mention",
+                "unread": true,
+                "updated_at": "{}",
+                "subject": {{
+                    "title": "Formatting",
+                    "type": "PullRequest",
+                    "url": null,
+                    "latest_comment_url": null
+                }},
+                "repository": {{
+                    "full_name": "owner/repo",
+                    "html_url": "https://github.com/owner/repo"
+                }}
+            }}"#,
+            id, updated_at
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn notifications_updated_at_the_checkpoint_are_emitted_once() {
+        let first = notification("1", "2018-08-10T12:00:00Z");
+        let second = notification("2", "2018-08-10T12:00:00Z");
+        let later = notification("3", "2018-08-10T12:00:01Z");
+
+        let mut checkpoint = NotificationCheckpoint::default();
+        assert!(checkpoint.is_new(&first));
+        checkpoint.advance(&first);
+
+        // Updated within the same second, but not emitted yet
+        assert!(!checkpoint.is_new(&first));
+        assert!(checkpoint.is_new(&second));
+        checkpoint.advance(&second);
+        assert_eq!(checkpoint.emitted, vec!["1", "2"]);
+
+        // Updating the thread again brings it back
+        let first_again = notification("1", "2018-08-10T12:00:01Z");
+        assert!(checkpoint.is_new(&later));
+        assert!(checkpoint.is_new(&first_again));
+        checkpoint.advance(&later);
+        assert_eq!(checkpoint.since, Some(later.updated_at));
+        assert_eq!(checkpoint.emitted, vec!["3"]);
+        assert!(!checkpoint.is_new(&second));
+    }
+}
@@ -8,12 +8,12 @@ extern crate log;
 #[macro_use]
 extern crate fern;
 extern crate chrono;
-extern crate github_rs as github_v3;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
 extern crate serde_json as json;
 
+use chrono::Utc;
 use failure::{err_msg, Error};
 use std::sync::{Arc, Mutex};
 
@@ -29,6 +29,7 @@ use rustyrobot::{
         },
         Event, GithubRequest,
     },
+    policy::Policy,
     search::{query::IncompleteQuery, query::SearchFor, search},
     shutdown::{GracefulShutdown, GracefulShutdownHandle},
     types::Repository,
@@ -75,13 +76,21 @@ fn main() {
     })
     .unwrap();
 
+    let policy = Policy::from_env().expect("failed to load the policy");
+
     HandlingConsumer::builder()
         .subscribe(topic::EVENT)
         .respond_to(topic::GITHUB_REQUEST)
         .group(group::PR_ISSUER)
-        .handler(|event, callback| {
+        .handler(move |event, callback| {
             match event {
+                // The repository might have been fetched long before it was formatted
                 Event::RepositoryFormatted(repo) => {
+                    if let Err(skip) = policy.check(&repo, Utc::now()) {
+                        info!("not issuing a PR to {}: {}", repo.name_with_owner, skip);
+                        return Ok(());
+                    }
+
                     let branch = {
                         let stats = repo.stats.as_ref().ok_or(HandlerError::Internal {
                             error: err_msg("stats are empty after the formatting stage"),
@@ -41,6 +41,12 @@ create_topic "rustyrobot.github.event" 1
 create_topic "rustyrobot.github.request" 1
 create_topic "rustyrobot.github.state" 1
 enable_compaction "rustyrobot.github.state"
+create_topic "rustyrobot.github.seen" 1
+enable_compaction "rustyrobot.github.seen"
+
+# Stage history of every repository
+create_topic "rustyrobot.repository.history" 1
+enable_compaction "rustyrobot.repository.history"
 
 # Fetcher topics
 create_topic "rustyrobot.fetcher.state" 1